@@ -0,0 +1,62 @@
+//compares evaluating the tree with `eval` and `eval_ref` to running the compiled program on the VM
+//run with `cargo bench`
+//`eval` consumes its input, so a fresh tree is cloned for every iteration (cloning is not measured, dropping is)
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use expression_evaluator::{
+    compile, eval, eval_ref, Environment, EvalOptions, Expression, Operation,
+};
+
+//`0 + (1 - (2 + (3 - ...)))`, nested `depth` levels deep
+fn deep(depth: i64) -> Expression {
+    (0..depth)
+        .rev()
+        .fold(Expression::Value(depth), |e, i| Expression::Op {
+            op: if i % 2 == 0 {
+                Operation::Add
+            } else {
+                Operation::Sub
+            },
+            left: Box::new(Expression::Value(i)),
+            right: Box::new(e),
+        })
+}
+
+//a complete binary tree of additions and multiplications with `2 ** height` leaves
+fn wide(height: u32) -> Expression {
+    if height == 0 {
+        return Expression::Value(1);
+    }
+    Expression::Op {
+        op: if height.is_multiple_of(2) {
+            Operation::Add
+        } else {
+            Operation::Mul
+        },
+        left: Box::new(wide(height - 1)),
+        right: Box::new(wide(height - 1)),
+    }
+}
+
+fn bench(c: &mut Criterion, name: &str, e: Expression) {
+    let mut group = c.benchmark_group(name);
+    group.bench_function("eval", |b| {
+        b.iter_batched(|| e.clone(), eval::<i64>, BatchSize::SmallInput)
+    });
+    group.bench_function("eval_ref", |b| {
+        let options = EvalOptions::default();
+        b.iter(|| eval_ref(&e, &mut Environment::new(), &options))
+    });
+    let program = compile(&e);
+    group.bench_function("vm", |b| b.iter(|| program.run()));
+    group.bench_function("compile", |b| b.iter(|| compile(&e)));
+    group.finish();
+}
+
+fn benchmarks(c: &mut Criterion) {
+    bench(c, "deep", deep(5_000));
+    bench(c, "wide", wide(16));
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+//limits on the work a single evaluation may do, so that a pathological expression cannot keep a thread busy
+//an evaluation that exceeds its `Budget` fails with `EvalErrorKind::BudgetExhausted`,
+//and one whose `CancellationToken` is cancelled, e.g. by a timeout in another thread, with `EvalErrorKind::Cancelled`
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+//`None` means unlimited, which is the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    //the number of subexpressions that may be evaluated, the definition of a function counts anew for every call
+    pub max_nodes: Option<usize>,
+    //how deeply the subexpressions that are evaluated may be nested, the root is at depth 0,
+    //the definition of a function is nested one level deeper than its call
+    pub max_depth: Option<usize>,
+}
+
+//the limit of a `Budget` that was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Nodes(usize),
+    Depth(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Nodes(max) => write!(f, "more than {max} subexpressions evaluated"),
+            Limit::Depth(max) => write!(f, "subexpressions nested more than {max} deep"),
+        }
+    }
+}
+
+//a flag shared by all its clones, evaluations with the token in their options stop once it is set
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    //stops the evaluations using this token or one of its clones, there is no way to undo it
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CancellationToken, Limit};
+    use std::thread;
+
+    #[test]
+    fn test_cancel() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        thread::spawn(move || token.cancel()).join().unwrap();
+        assert!(clone.is_cancelled());
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Limit::Nodes(100).to_string(),
+            "more than 100 subexpressions evaluated"
+        );
+        assert_eq!(
+            Limit::Depth(5).to_string(),
+            "subexpressions nested more than 5 deep"
+        );
+    }
+}
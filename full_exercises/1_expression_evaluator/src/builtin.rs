@@ -0,0 +1,134 @@
+//the functions that can be called without defining them, a function defined by `fn` with the same name shadows them
+// - `abs(x)`: the absolute value
+// - `min(x, y)` and `max(x, y)`: the smaller and the larger argument
+// - `gcd(x, y)`: the greatest common divisor, which is never negative, `gcd(0, 0)` is 0
+//like the operators, they fail if the result does not fit in the number type, e.g. `abs(-9223372036854775808)`
+
+use crate::{ArithmeticMode, EvalErrorKind, Number, Operation, UnaryOperation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Abs,
+    Min,
+    Max,
+    Gcd,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Some(match name {
+            "abs" => Builtin::Abs,
+            "min" => Builtin::Min,
+            "max" => Builtin::Max,
+            "gcd" => Builtin::Gcd,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Abs => "abs",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Gcd => "gcd",
+        }
+    }
+
+    //the number of arguments the function takes
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Abs => 1,
+            Builtin::Min | Builtin::Max | Builtin::Gcd => 2,
+        }
+    }
+
+    //applies the function to `arity` evaluated arguments
+    pub fn apply<N: Number>(self, args: &[N], mode: ArithmeticMode) -> Result<N, EvalErrorKind<N>> {
+        match (self, args) {
+            (Builtin::Abs, [x]) => abs(x, mode),
+            (Builtin::Min, [x, y]) => Ok(if y < x { y } else { x }.clone()),
+            (Builtin::Max, [x, y]) => Ok(if y > x { y } else { x }.clone()),
+            (Builtin::Gcd, [x, y]) => gcd(x, y, mode),
+            _ => panic!("`{}` takes {} arguments", self.name(), self.arity()),
+        }
+    }
+}
+
+fn abs<N: Number>(x: &N, mode: ArithmeticMode) -> Result<N, EvalErrorKind<N>> {
+    if *x < N::from_i64(0) {
+        N::apply_unary(UnaryOperation::Neg, x, mode)
+    } else {
+        Ok(x.clone())
+    }
+}
+
+//the Euclidean algorithm, it only terminates for exact number types
+//the remainder is smaller than the divisor, so it never overflows, but computing it may, e.g. for `i64::MIN % -1`
+fn gcd<N: Number>(x: &N, y: &N, mode: ArithmeticMode) -> Result<N, EvalErrorKind<N>> {
+    if !N::EXACT {
+        return Err(EvalErrorKind::Unsupported("gcd"));
+    }
+    let (mut x, mut y) = (x.clone(), y.clone());
+    while !y.is_zero() {
+        let remainder = N::apply(Operation::Mod, &x, &y, ArithmeticMode::Wrapping)?;
+        x = y;
+        y = remainder;
+    }
+    abs(&x, mode)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Builtin;
+    use crate::{ArithmeticMode, EvalErrorKind, Number, UnaryOperation};
+    use num_rational::BigRational;
+
+    #[test]
+    fn test_apply() {
+        let apply = |builtin: Builtin, args: &[i64]| builtin.apply(args, ArithmeticMode::Checked);
+        assert_eq!(apply(Builtin::Abs, &[-3]), Ok(3));
+        assert_eq!(apply(Builtin::Abs, &[3]), Ok(3));
+        assert_eq!(
+            apply(Builtin::Abs, &[i64::MIN]),
+            Err(EvalErrorKind::UnaryOverflow {
+                op: UnaryOperation::Neg,
+                operand: i64::MIN
+            })
+        );
+        assert_eq!(apply(Builtin::Min, &[2, -7]), Ok(-7));
+        assert_eq!(apply(Builtin::Max, &[2, -7]), Ok(2));
+        assert_eq!(apply(Builtin::Gcd, &[12, -18]), Ok(6));
+        assert_eq!(apply(Builtin::Gcd, &[0, 5]), Ok(5));
+        assert_eq!(apply(Builtin::Gcd, &[0, 0]), Ok(0));
+        assert_eq!(apply(Builtin::Gcd, &[i64::MIN, -1]), Ok(1));
+        assert!(apply(Builtin::Gcd, &[i64::MIN, 0]).is_err());
+    }
+
+    #[test]
+    fn test_number_types() {
+        let gcd = Builtin::Gcd.apply(
+            &[
+                BigRational::new(1.into(), 2.into()),
+                BigRational::from_i64(3),
+            ],
+            ArithmeticMode::Checked,
+        );
+        assert_eq!(gcd, Ok(BigRational::new(1.into(), 2.into())));
+        assert_eq!(
+            Builtin::Gcd.apply(&[4.0, 6.0], ArithmeticMode::Checked),
+            Err(EvalErrorKind::Unsupported("gcd"))
+        );
+        assert_eq!(
+            Builtin::Abs.apply(&[-0.5], ArithmeticMode::Checked),
+            Ok(0.5)
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        for builtin in [Builtin::Abs, Builtin::Min, Builtin::Max, Builtin::Gcd] {
+            assert_eq!(Builtin::from_name(builtin.name()), Some(builtin));
+        }
+        assert_eq!(Builtin::from_name("sq"), None);
+    }
+}
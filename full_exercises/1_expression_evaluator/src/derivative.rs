@@ -0,0 +1,584 @@
+//symbolic differentiation of an `Expression` with respect to one of its variables
+//the usual rules are applied: the sum and difference rule, the product rule for `*`, the quotient rule for `/`
+//and the power rule for `**`, if the exponent does not depend on the variable (e.g. `x ** 3` or `x ** n`),
+//the derivative of `x ** n` is 0 where `n` is 0, e.g. `if n == 0 then 0 else n * x ** (n - 1)`,
+//since `x ** -1` is undefined for integers and for `x = 0`
+//other variables are constants, except for variables bound by `let`, which depend on the variable through their value
+//the derivative of such a variable is written in terms of the variables outside of its `let`, if an inner `let` binds
+//one of them, the inner binding is renamed, e.g. `let x = x * x in x` becomes `let x_1 = x * x in x + x`
+//`if` is differentiated per branch, as a piecewise function
+//other operations can only be differentiated if they do not depend on the variable, their derivative is then 0
+//function calls cannot be differentiated, the definition of a function may even refer to the variable itself
+//the result is simplified, see `simplify`
+//for the integer types `i64` and `BigInt` the derivative is the one of the same expression over the real numbers,
+//e.g. `3 * x ** 2` for `x ** 3`, evaluating it at an integer gives the slope of that function there,
+//integer division rounds, so `/` is not differentiable for them and only the rational and floating point types
+//apply the quotient rule
+//the tree is walked with an explicit stack like `eval` does, so arbitrarily deep trees can be differentiated
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::simplify::substitute;
+use crate::{simplify, Expression, Number, Operation, Path, Step, UnaryOperation};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeriveErrorKind {
+    //the operation has no derivative, e.g. `%` or `<` applied to the variable, or `/` for integer types
+    Unsupported(&'static str),
+    //`**` with an exponent that depends on the variable
+    VariableExponent,
+    //a call of the function with this name
+    Call(String),
+}
+
+//a differentiation error, together with the path to the subexpression that caused it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeriveError {
+    pub kind: DeriveErrorKind,
+    pub path: Path,
+}
+
+impl fmt::Display for DeriveErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeriveErrorKind::Unsupported(op) => write!(f, "cannot differentiate `{op}`"),
+            DeriveErrorKind::VariableExponent => {
+                write!(f, "cannot differentiate `**` with a variable exponent")
+            }
+            DeriveErrorKind::Call(name) => write!(f, "cannot differentiate the call of `{name}`"),
+        }
+    }
+}
+
+impl fmt::Display for DeriveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_root() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} at {}", self.kind, self.path)
+        }
+    }
+}
+
+impl Error for DeriveError {}
+
+//returns the derivative of `e` with respect to `var`, e.g. `2 * x + 1` for `x * x + x`
+pub fn derive<N: Number>(e: &Expression<N>, var: &str) -> Result<Expression<N>, DeriveError> {
+    let mut names = HashSet::from([var.to_string()]);
+    collect_names(e, &mut names);
+    let mut deriver = Deriver {
+        var,
+        bound: Vec::new(),
+        renamed: 0,
+        names,
+        path: Vec::new(),
+    };
+    deriver.derive(e).map(simplify)
+}
+
+//a variable bound by a `let` around the current subexpression
+struct Binding<N> {
+    name: String,
+    //the name of the variable in the derivative, which differs from `name` if the binding is renamed
+    renamed: String,
+    //the derivative of its value
+    derivative: Expression<N>,
+}
+
+struct Deriver<'a, N> {
+    var: &'a str,
+    bound: Vec<Binding<N>>,
+    //the number of renamed bindings in `bound`
+    renamed: usize,
+    //the names of all variables in the expression and the ones given to renamed bindings, a new name is none of them
+    names: HashSet<String>,
+    path: Vec<Step>,
+}
+
+enum Task<'e, N> {
+    //differentiates the child reached by `step`
+    Within(Step, &'e Expression<N>),
+    Leave,
+    Derive(&'e Expression<N>),
+    //the derivative of the value of a `let` is computed, the variable is bound for the body
+    Bind(&'e Expression<N>),
+    //the derivatives of the children are computed, they are combined into the derivative of the expression
+    Combine(&'e Expression<N>),
+}
+
+impl<'e, N: Number> Deriver<'_, N> {
+    fn derive(&mut self, e: &'e Expression<N>) -> Result<Expression<N>, DeriveError> {
+        let mut tasks = vec![Task::Derive(e)];
+        let mut derivatives = Vec::new();
+        //a task never pops more derivatives than the tasks before it pushed
+        let pop =
+            |derivatives: &mut Vec<Expression<N>>| derivatives.pop().expect("missing derivative");
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Within(step, e) => {
+                    self.path.push(step);
+                    tasks.extend([Task::Leave, Task::Derive(e)]);
+                }
+                Task::Leave => {
+                    self.path.pop();
+                }
+                Task::Derive(e) => match e {
+                    Expression::Value(_) | Expression::Bool(_) => derivatives.push(constant(0)),
+                    Expression::Var(name) => {
+                        let derivative = match self.bound.iter().rev().find(|b| b.name == *name) {
+                            Some(binding) => binding.derivative.clone(),
+                            None if name == self.var => constant(1),
+                            None => constant(0),
+                        };
+                        derivatives.push(derivative);
+                    }
+                    Expression::Op { left, right, .. } => tasks.extend([
+                        Task::Combine(e),
+                        Task::Within(Step::Right, right),
+                        Task::Within(Step::Left, left),
+                    ]),
+                    Expression::Unary { operand, .. } => {
+                        tasks.extend([Task::Combine(e), Task::Within(Step::Operand, operand)])
+                    }
+                    Expression::Let { value, .. } => {
+                        tasks.extend([Task::Bind(e), Task::Within(Step::Value, value)])
+                    }
+                    Expression::If {
+                        then, otherwise, ..
+                    } => tasks.extend([
+                        Task::Combine(e),
+                        Task::Within(Step::Else, otherwise),
+                        Task::Within(Step::Then, then),
+                    ]),
+                    Expression::Fn { body, .. } => {
+                        tasks.extend([Task::Combine(e), Task::Within(Step::Body, body)])
+                    }
+                    Expression::Call { name, .. } => {
+                        return Err(self.error(DeriveErrorKind::Call(name.clone())))
+                    }
+                },
+                //the derivatives of bound variables end up in the body, the binding must not capture their variables
+                Task::Bind(e) => {
+                    let Expression::Let { name, body, .. } = e else {
+                        unreachable!("only `let`s are bound")
+                    };
+                    let derivative = pop(&mut derivatives);
+                    let captures = |derivative: &Expression<N>| is_free(name, derivative);
+                    let renamed = if captures(&derivative)
+                        || self.bound.iter().any(|b| captures(&b.derivative))
+                    {
+                        self.renamed += 1;
+                        self.fresh(name)
+                    } else {
+                        name.clone()
+                    };
+                    self.bound.push(Binding {
+                        name: name.clone(),
+                        renamed,
+                        derivative,
+                    });
+                    tasks.extend([Task::Combine(e), Task::Within(Step::Body, body)]);
+                }
+                Task::Combine(e) => {
+                    let derivative = match e {
+                        Expression::Op { op, left, right } => {
+                            let right_derivative = pop(&mut derivatives);
+                            let left_derivative = pop(&mut derivatives);
+                            self.combine_op(*op, left, right, left_derivative, right_derivative)?
+                        }
+                        Expression::Unary { op, .. } => match pop(&mut derivatives) {
+                            derivative if is_zero(&derivative) => constant(0),
+                            derivative if *op == UnaryOperation::Neg => Expression::Unary {
+                                op: *op,
+                                operand: Box::new(derivative),
+                            },
+                            _ => return Err(self.error(DeriveErrorKind::Unsupported(op.symbol()))),
+                        },
+                        //the derivative of the body refers to the bound variable, so the binding is kept
+                        Expression::Let { value, .. } => {
+                            let body_derivative = pop(&mut derivatives);
+                            let binding = self.bound.pop().expect("the variable was bound");
+                            if binding.renamed != binding.name {
+                                self.renamed -= 1;
+                            }
+                            if is_zero(&body_derivative) {
+                                constant(0)
+                            } else {
+                                Expression::Let {
+                                    name: binding.renamed,
+                                    value: Box::new(self.copy(value)),
+                                    body: Box::new(body_derivative),
+                                }
+                            }
+                        }
+                        Expression::If { cond, .. } => {
+                            let otherwise_derivative = pop(&mut derivatives);
+                            let then_derivative = pop(&mut derivatives);
+                            if is_zero(&then_derivative) && is_zero(&otherwise_derivative) {
+                                constant(0)
+                            } else {
+                                Expression::If {
+                                    cond: Box::new(self.copy(cond)),
+                                    then: Box::new(then_derivative),
+                                    otherwise: Box::new(otherwise_derivative),
+                                }
+                            }
+                        }
+                        //the derivative of the body may call the function, so the definition is kept
+                        Expression::Fn {
+                            name,
+                            params,
+                            definition,
+                            ..
+                        } => match pop(&mut derivatives) {
+                            body_derivative if is_zero(&body_derivative) => constant(0),
+                            body_derivative => Expression::Fn {
+                                name: name.clone(),
+                                params: params.clone(),
+                                definition: definition.clone(),
+                                body: Box::new(body_derivative),
+                            },
+                        },
+                        _ => unreachable!("only inner nodes are combined"),
+                    };
+                    derivatives.push(derivative);
+                }
+            }
+        }
+        Ok(pop(&mut derivatives))
+    }
+
+    fn combine_op(
+        &self,
+        op: Operation,
+        left: &Expression<N>,
+        right: &Expression<N>,
+        left_derivative: Expression<N>,
+        right_derivative: Expression<N>,
+    ) -> Result<Expression<N>, DeriveError> {
+        let (l, r) = (|| self.copy(left), || self.copy(right));
+        Ok(match op {
+            Operation::Add => sum(left_derivative, right_derivative),
+            Operation::Sub => difference(left_derivative, right_derivative),
+            //(l * r)' = l' * r + l * r'
+            Operation::Mul => sum(
+                product(left_derivative, r()),
+                product(l(), right_derivative),
+            ),
+            //(l / r)' = (l' * r - l * r') / r ** 2
+            Operation::Div if !N::INTEGER => match difference(
+                product(left_derivative, r()),
+                product(l(), right_derivative),
+            ) {
+                numerator if is_zero(&numerator) => constant(0),
+                numerator => binary(
+                    Operation::Div,
+                    numerator,
+                    binary(Operation::Pow, r(), constant(2)),
+                ),
+            },
+            //(l ** r)' = r * l ** (r - 1) * l', if r does not depend on the variable
+            Operation::Pow if is_zero(&right_derivative) => {
+                let derivative = product(
+                    product(
+                        r(),
+                        binary(
+                            Operation::Pow,
+                            l(),
+                            binary(Operation::Sub, r(), constant(1)),
+                        ),
+                    ),
+                    left_derivative,
+                );
+                //`l ** -1` is undefined where r is 0, the derivative of `l ** 0` is 0 there
+                if is_zero(&derivative) || matches!(right, Expression::Value(_)) {
+                    derivative
+                } else {
+                    Expression::If {
+                        cond: Box::new(binary(Operation::Eq, r(), constant(0))),
+                        then: Box::new(constant(0)),
+                        otherwise: Box::new(derivative),
+                    }
+                }
+            }
+            Operation::Pow => return Err(self.error(DeriveErrorKind::VariableExponent)),
+            _ if is_zero(&left_derivative) && is_zero(&right_derivative) => constant(0),
+            _ => return Err(self.error(DeriveErrorKind::Unsupported(op.symbol()))),
+        })
+    }
+
+    //a copy of a subexpression of the input for the derivative, with the variables of renamed bindings renamed
+    fn copy(&self, e: &Expression<N>) -> Expression<N> {
+        let mut copy = e.clone();
+        if self.renamed == 0 {
+            return copy;
+        }
+        //only the innermost binding of a name is visible
+        let mut seen = HashSet::new();
+        for binding in self.bound.iter().rev() {
+            if seen.insert(&binding.name) && binding.renamed != binding.name {
+                let renamed = Expression::Var(binding.renamed.clone());
+                copy = substitute(copy, &binding.name, &renamed);
+            }
+        }
+        copy
+    }
+
+    fn error(&self, kind: DeriveErrorKind) -> DeriveError {
+        DeriveError {
+            kind,
+            path: Path(self.path.clone()),
+        }
+    }
+
+    //a name for a renamed binding of `name` that is not used anywhere else
+    fn fresh(&mut self, name: &str) -> String {
+        let fresh = (1..)
+            .map(|n| format!("{name}_{n}"))
+            .find(|fresh| !self.names.contains(fresh))
+            .expect("there are infinitely many names");
+        self.names.insert(fresh.clone());
+        fresh
+    }
+}
+
+//adds the names of the variables and parameters in `e` to `names`
+fn collect_names<N>(e: &Expression<N>, names: &mut HashSet<String>) {
+    let mut pending = vec![e];
+    while let Some(e) = pending.pop() {
+        match e {
+            Expression::Var(name) | Expression::Let { name, .. } => {
+                names.insert(name.clone());
+            }
+            Expression::Fn { params, .. } => names.extend(params.iter().cloned()),
+            _ => {}
+        }
+        e.children_rev(|child| pending.push(child));
+    }
+}
+
+//whether the variable `name` occurs in `e` outside of the bindings of `name` and of function definitions,
+//which do not see the variables of enclosing `let`s
+fn is_free<N>(name: &str, e: &Expression<N>) -> bool {
+    let mut pending = vec![e];
+    while let Some(e) = pending.pop() {
+        match e {
+            Expression::Var(var) if var == name => return true,
+            Expression::Let {
+                name: inner, value, ..
+            } if inner == name => pending.push(value),
+            Expression::Fn { body, .. } => pending.push(body),
+            e => e.children_rev(|child| pending.push(child)),
+        }
+    }
+    false
+}
+
+fn constant<N: Number>(n: i64) -> Expression<N> {
+    Expression::Value(N::from_i64(n))
+}
+
+fn binary<N>(op: Operation, left: Expression<N>, right: Expression<N>) -> Expression<N> {
+    Expression::Op {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+//the derivatives of subexpressions that do not depend on the variable are 0,
+//these terms are left out right away, so an operation on them can still be differentiated
+fn sum<N: Number>(left: Expression<N>, right: Expression<N>) -> Expression<N> {
+    match (is_zero(&left), is_zero(&right)) {
+        (true, _) => right,
+        (_, true) => left,
+        _ => binary(Operation::Add, left, right),
+    }
+}
+
+fn difference<N: Number>(left: Expression<N>, right: Expression<N>) -> Expression<N> {
+    match (is_zero(&left), is_zero(&right)) {
+        (_, true) => left,
+        (true, _) => Expression::Unary {
+            op: UnaryOperation::Neg,
+            operand: Box::new(right),
+        },
+        _ => binary(Operation::Sub, left, right),
+    }
+}
+
+fn product<N: Number>(left: Expression<N>, right: Expression<N>) -> Expression<N> {
+    if is_zero(&left) || is_zero(&right) {
+        constant(0)
+    } else {
+        binary(Operation::Mul, left, right)
+    }
+}
+
+fn is_zero<N: Number>(e: &Expression<N>) -> bool {
+    matches!(e, Expression::Value(value) if value.is_zero())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{derive, DeriveError, DeriveErrorKind};
+    use crate::{eval_in, parse, Environment, Expression, Number, Operation, Path, Step};
+    use num_rational::BigRational;
+
+    fn derived(input: &str) -> String {
+        derive(&parse(input).unwrap(), "x").unwrap().to_string()
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(derived("3 * x + 1"), "3");
+        assert_eq!(derived("x - y"), "1");
+        assert_eq!(derived("x * x"), "x + x");
+        assert_eq!(derived("x ** 3"), "3 * x ** 2");
+        assert_eq!(derived("x ** n"), "if n == 0 then 0 else n * x ** (n - 1)");
+        assert_eq!(derived("x ** 0 + x ** (1 - 1)"), "0");
+        assert_eq!(derived("-(2 * x)"), "-2");
+        assert_eq!(derived("y / 2"), "0");
+        assert_eq!(derived("y ** 2 % 3"), "0");
+        assert_eq!(derived("(if x then 1 else y) << 2"), "0");
+        assert_eq!(
+            derived("if x < 0 then -x else x"),
+            "if x < 0 then -1 else 1"
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(
+            derived("let y = x * x in y + x"),
+            "let y = x * x in x + x + 1"
+        );
+        //`x` in the body refers to the inner binding, which does not depend on the outer `x`
+        assert_eq!(derived("let x = 5 in x * x"), "0");
+        assert_eq!(derived("fn f(y) = y; x * x"), "fn f(y) = y; x + x");
+        assert_eq!(derived("fn f(y) = y; 2"), "0");
+    }
+
+    //the derivative of a bound variable is not captured by a binding of a variable it refers to
+    #[test]
+    fn test_shadowing() {
+        let derivative_at_3 = |input: &str| {
+            let mut env = Environment::new();
+            env.bind("x", 3);
+            eval_in(derive(&parse(input).unwrap(), "x").unwrap(), &mut env)
+        };
+        assert_eq!(derived("let x = x * x in x"), "let x_1 = x * x in x + x");
+        assert_eq!(derivative_at_3("let x = x * x in x"), Ok(6));
+        assert_eq!(
+            derived("let y = x * x in let x = 1 in y"),
+            "let y = x * x in x + x"
+        );
+        assert_eq!(derivative_at_3("let y = x * x in let x = 1 in y"), Ok(6));
+        //the new name is not used anywhere else
+        assert_eq!(
+            derived("let x_1 = 2 in let x = x * x in x * x_1"),
+            "let x_2 = x * x in (x + x) * 2"
+        );
+        assert_eq!(
+            derivative_at_3("let x_1 = 2 in let x = x * x in x * x_1"),
+            Ok(12)
+        );
+        //no binding refers to the outer `x`, so none is renamed
+        assert_eq!(derived("let x = 5 in let y = x in y * x"), "0");
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |input| derive(&parse(input).unwrap(), "x").unwrap_err();
+        assert_eq!(
+            error("1 + 2 ** x"),
+            DeriveError {
+                kind: DeriveErrorKind::VariableExponent,
+                path: Path(vec![Step::Right]),
+            }
+        );
+        assert_eq!(error("x % 2").kind, DeriveErrorKind::Unsupported("%"));
+        //integer division rounds, `x / 2` is a step function
+        assert_eq!(
+            error("1 + x / 2"),
+            DeriveError {
+                kind: DeriveErrorKind::Unsupported("/"),
+                path: Path(vec![Step::Right]),
+            }
+        );
+        assert_eq!(error("!x").to_string(), "cannot differentiate `!`");
+        assert_eq!(
+            error("fn sq(y) = y * y; 1 + sq(x)"),
+            DeriveError {
+                kind: DeriveErrorKind::Call(String::from("sq")),
+                path: Path(vec![Step::Body, Step::Right]),
+            }
+        );
+    }
+
+    //`x ** -1` is undefined for integers, the derivative of `x ** 0` is 0 instead of `0 * x ** -1`
+    #[test]
+    fn test_zero_exponent() {
+        let e = parse("x ** n").unwrap();
+        let derivative = derive(&e, "x").unwrap();
+        let mut env = Environment::new();
+        env.bind("x", 3);
+        for (n, expected) in [(0, 0), (1, 1), (2, 6), (3, 27)] {
+            env.bind("n", n);
+            assert_eq!(eval_in(derivative.clone(), &mut env), Ok(expected));
+        }
+        let mut env = Environment::new();
+        env.bind("x", BigRational::from_i64(0));
+        env.bind("n", BigRational::from_i64(0));
+        let derivative = derive(&e.map(BigRational::from_i64), "x").unwrap();
+        assert_eq!(eval_in(derivative, &mut env), Ok(BigRational::from_i64(0)));
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        //((x + x) + x) + ... + x
+        let mut e = parse("x").unwrap();
+        for _ in 0..100_000 {
+            e = Expression::Op {
+                op: Operation::Add,
+                left: Box::new(e),
+                right: Box::new(parse("x").unwrap()),
+            };
+        }
+        assert_eq!(derive(&e, "x"), Ok(Expression::Value(100_001)));
+        let e = Expression::Op {
+            op: Operation::Add,
+            left: Box::new(e),
+            right: Box::new(parse("f(x)").unwrap()),
+        };
+        assert_eq!(
+            derive(&e, "x").unwrap_err().kind,
+            DeriveErrorKind::Call(String::from("f"))
+        );
+    }
+
+    #[test]
+    fn test_quotient_rule() {
+        //(x ** 2 + 1) / x at x = 2: (2 * x * x - (x ** 2 + 1)) / x ** 2 = 3/4
+        let e = parse("(x ** 2 + 1) / x")
+            .unwrap()
+            .map(BigRational::from_i64);
+        let derivative = derive(&e, "x").unwrap();
+        let mut env = Environment::new();
+        env.bind("x", BigRational::from_i64(2));
+        assert_eq!(
+            eval_in(derivative, &mut env),
+            Ok(BigRational::new(3.into(), 4.into()))
+        );
+        let derived = |input: &str| {
+            let e = parse(input).unwrap().map(BigRational::from_i64);
+            derive(&e, "x").unwrap().to_string()
+        };
+        assert_eq!(derived("x / 2"), "1/2");
+        assert_eq!(derived("1 / x"), "-1 / x ** 2");
+        let e = parse("1 / x").unwrap().map(f64::from_i64);
+        assert_eq!(derive(&e, "x").unwrap().to_string(), "-1 / x ** 2");
+    }
+}
@@ -0,0 +1,508 @@
+//turns an `Expression` tree back into text, in one of three notations:
+// - `Notation::Minimal`: infix with only the parentheses that are needed to parse back into the same tree,
+//   e.g. `10 * 9 + 5 * (3 - 4)`, this is what `Display` for `Expression` uses
+// - `Notation::Parenthesized`: infix with every compound subexpression in parentheses, e.g. `(10 * 9) + (5 * (3 - 4))`
+// - `Notation::Prefix`: S-expressions with the operator first, e.g. `(+ (* 10 9) (* 5 (- 3 4)))`
+//the infix notations can be read back with `parse` (for number types that print like integers)
+//`let`, `if` and `fn` extend as far to the right as possible, so they only need parentheses when an operator follows them
+
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+use crate::{Expression, Operation, Path, Step, UnaryOperation};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Notation {
+    #[default]
+    Minimal,
+    Parenthesized,
+    Prefix,
+}
+
+//an `Expression` together with the notation to display it in, see `Expression::display`
+pub struct Printed<'a, N> {
+    expression: &'a Expression<N>,
+    notation: Notation,
+}
+
+impl<N> Expression<N> {
+    //e.g. `format!("{}", expression.display(Notation::Prefix))`
+    pub fn display(&self, notation: Notation) -> Printed<'_, N> {
+        Printed {
+            expression: self,
+            notation,
+        }
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for Expression<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(Notation::Minimal).fmt(f)
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for Printed<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.notation {
+            Notation::Minimal => Infix::new(f, false).write(self.expression),
+            Notation::Parenthesized => Infix::new(f, true).write(self.expression),
+            Notation::Prefix => prefix(self.expression, f),
+        }
+    }
+}
+
+//the position of a subexpression in its parent
+#[derive(Clone, Copy)]
+struct Context {
+    //operations that bind less tight than this need parentheses
+    min_precedence: u8,
+    //whether an operator follows the subexpression, it would become part of an unparenthesized `let` body or `else` branch
+    operator_follows: bool,
+}
+
+impl Context {
+    const ROOT: Context = Context {
+        min_precedence: 0,
+        operator_follows: false,
+    };
+
+    //the context of the subexpression at the end of a `let`, `if` or `fn`, which extends up to the end of its parent
+    fn last(self) -> Context {
+        Context {
+            min_precedence: 0,
+            ..self
+        }
+    }
+}
+
+//the precedence of an atom, it never needs parentheses
+const ATOM: u8 = u8::MAX;
+
+//the pieces of the output that are still to be written, the tree is walked with an explicit stack like `eval` does,
+//so arbitrarily deep trees can be printed
+enum Task<'e, N> {
+    Text(Cow<'static, str>),
+    //a subexpression without parentheses around it
+    Contents(&'e Expression<N>, Context),
+    //the child reached by `step`, in parentheses if it needs them
+    Operand(Step, &'e Expression<N>, Context),
+    //the start and the end of the child reached by `step`
+    Enter(Step),
+    Leave,
+}
+
+struct Infix<'a> {
+    out: &'a mut dyn fmt::Write,
+    //whether every compound subexpression is parenthesized, instead of only the ones that need it
+    all: bool,
+    //the number of bytes written so far and the path of the subexpression being written with the offsets its
+    //steps start at, to find where the subexpression at `target` ends up in the output
+    offset: usize,
+    path: Vec<Step>,
+    starts: Vec<usize>,
+    target: Option<&'a [Step]>,
+    found: Option<Range<usize>>,
+}
+
+impl<'a> Infix<'a> {
+    fn new(out: &'a mut dyn fmt::Write, all: bool) -> Infix<'a> {
+        Infix {
+            out,
+            all,
+            offset: 0,
+            path: Vec::new(),
+            starts: Vec::new(),
+            target: None,
+            found: None,
+        }
+    }
+
+    fn write<N: fmt::Display>(&mut self, e: &Expression<N>) -> fmt::Result {
+        let mut pending = vec![Task::Contents(e, Context::ROOT)];
+        while let Some(task) = pending.pop() {
+            match task {
+                Task::Text(text) => self.write_str(&text)?,
+                Task::Contents(e, context) => self.contents(e, context, &mut pending)?,
+                Task::Operand(step, e, context) => {
+                    pending.push(Task::Leave);
+                    if self.needs_parentheses(e, context) {
+                        pending.push(Task::Text(")".into()));
+                        pending.push(Task::Contents(e, Context::ROOT));
+                        pending.push(Task::Text("(".into()));
+                    } else {
+                        pending.push(Task::Contents(e, context));
+                    }
+                    pending.push(Task::Enter(step));
+                }
+                Task::Enter(step) => {
+                    self.path.push(step);
+                    self.starts.push(self.offset);
+                }
+                Task::Leave => {
+                    let start = self.starts.pop().expect("the child was entered");
+                    if self.target == Some(&self.path[..]) {
+                        self.found = Some(start..self.offset);
+                    }
+                    self.path.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn needs_parentheses<N: fmt::Display>(&self, e: &Expression<N>, context: Context) -> bool {
+        let precedence = infix_precedence(e);
+        let needed = match e {
+            Expression::Let { .. } | Expression::If { .. } | Expression::Fn { .. } => {
+                context.operator_follows
+            }
+            _ => precedence < context.min_precedence,
+        };
+        needed || self.all && precedence != ATOM
+    }
+
+    //writes the start of `e` and adds the rest to `pending`, in reverse because it is a stack
+    fn contents<'e, N: fmt::Display>(
+        &mut self,
+        e: &'e Expression<N>,
+        context: Context,
+        pending: &mut Vec<Task<'e, N>>,
+    ) -> fmt::Result {
+        match e {
+            Expression::Op { op, left, right } => {
+                let precedence = op.precedence();
+                let (left_precedence, right_precedence) = if op.is_right_associative() {
+                    (precedence + 1, precedence)
+                } else {
+                    (precedence, precedence + 1)
+                };
+                pending.push(Task::Operand(
+                    Step::Right,
+                    right,
+                    Context {
+                        min_precedence: right_precedence,
+                        ..context
+                    },
+                ));
+                pending.push(Task::Text(format!(" {op} ").into()));
+                pending.push(Task::Operand(
+                    Step::Left,
+                    left,
+                    Context {
+                        min_precedence: left_precedence,
+                        operator_follows: true,
+                    },
+                ));
+                Ok(())
+            }
+            Expression::Unary { op, operand } => {
+                write!(self, "{op}")?;
+                match &**operand {
+                    //`-5` would be read back as a negative literal instead of a negation
+                    Expression::Value(value)
+                        if *op == UnaryOperation::Neg && !value.to_string().starts_with('-') =>
+                    {
+                        pending.push(Task::Leave);
+                        pending.push(Task::Text(format!("({value})").into()));
+                        pending.push(Task::Enter(Step::Operand));
+                    }
+                    operand => pending.push(Task::Operand(
+                        Step::Operand,
+                        operand,
+                        Context {
+                            min_precedence: Operation::Pow.precedence(),
+                            ..context
+                        },
+                    )),
+                }
+                Ok(())
+            }
+            Expression::Value(value) => write!(self, "{value}"),
+            Expression::Bool(value) => write!(self, "{value}"),
+            Expression::Var(name) => self.write_str(name),
+            Expression::Let { name, value, body } => {
+                write!(self, "let {name} = ")?;
+                pending.push(Task::Operand(Step::Body, body, context.last()));
+                pending.push(Task::Text(" in ".into()));
+                pending.push(Task::Operand(Step::Value, value, Context::ROOT));
+                Ok(())
+            }
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.write_str("if ")?;
+                pending.push(Task::Operand(Step::Else, otherwise, context.last()));
+                pending.push(Task::Text(" else ".into()));
+                pending.push(Task::Operand(Step::Then, then, Context::ROOT));
+                pending.push(Task::Text(" then ".into()));
+                pending.push(Task::Operand(Step::Cond, cond, Context::ROOT));
+                Ok(())
+            }
+            Expression::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => {
+                write!(self, "fn {name}({}) = ", params.join(", "))?;
+                pending.push(Task::Operand(Step::Body, body, context.last()));
+                pending.push(Task::Text("; ".into()));
+                pending.push(Task::Operand(Step::Definition, definition, Context::ROOT));
+                Ok(())
+            }
+            Expression::Call { name, args } => {
+                write!(self, "{name}(")?;
+                pending.push(Task::Text(")".into()));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    pending.push(Task::Operand(Step::Arg(i), arg, Context::ROOT));
+                    if i > 0 {
+                        pending.push(Task::Text(", ".into()));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Write for Infix<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.offset += s.len();
+        self.out.write_str(s)
+    }
+}
+
+impl<N: fmt::Display> Expression<N> {
+    //the byte range of the subexpression at `path` in the output of `Display`,
+    //or `None` if the path does not exist in this tree
+    pub fn locate(&self, path: &Path) -> Option<Range<usize>> {
+        let mut output = String::new();
+        let mut infix = Infix::new(&mut output, false);
+        infix.target = Some(path.steps());
+        infix.write(self).expect("writing to a string cannot fail");
+        match infix.found {
+            None if path.is_root() => Some(0..infix.offset),
+            found => found,
+        }
+    }
+}
+
+//how tight a subexpression binds when printed in infix notation
+//a unary operation or a negative literal starts with an operator, so it cannot be the base of `**`,
+//a value printed as a fraction, e.g. a `BigRational`, is read back as a division
+fn infix_precedence<N: fmt::Display>(e: &Expression<N>) -> u8 {
+    match e {
+        Expression::Op { op, .. } => op.precedence(),
+        Expression::Unary { .. } => Operation::Pow.precedence(),
+        Expression::Value(value) => {
+            let text = value.to_string();
+            if text.contains('/') {
+                Operation::Div.precedence()
+            } else if text.starts_with('-') {
+                Operation::Pow.precedence()
+            } else {
+                ATOM
+            }
+        }
+        Expression::Bool(_) | Expression::Var(_) | Expression::Call { .. } => ATOM,
+        Expression::Let { .. } | Expression::If { .. } | Expression::Fn { .. } => 0,
+    }
+}
+
+//written with an explicit stack like the infix notations
+fn prefix<N: fmt::Display>(e: &Expression<N>, f: &mut fmt::Formatter) -> fmt::Result {
+    enum Piece<'e, N> {
+        Expression(&'e Expression<N>),
+        Text(&'static str),
+    }
+    let mut pending = vec![Piece::Expression(e)];
+    while let Some(piece) = pending.pop() {
+        let e = match piece {
+            Piece::Expression(e) => e,
+            Piece::Text(text) => {
+                f.write_str(text)?;
+                continue;
+            }
+        };
+        match e {
+            Expression::Op { op, .. } => write!(f, "({op}")?,
+            Expression::Unary { op, .. } => write!(f, "({op}")?,
+            Expression::Value(value) => write!(f, "{value}")?,
+            Expression::Bool(value) => write!(f, "{value}")?,
+            Expression::Var(name) => f.write_str(name)?,
+            Expression::Let { name, .. } => write!(f, "(let {name}")?,
+            Expression::If { .. } => f.write_str("(if")?,
+            Expression::Fn { name, params, .. } => write!(f, "(fn {name} ({})", params.join(" "))?,
+            Expression::Call { name, .. } => write!(f, "({name}")?,
+        }
+        if !e.is_leaf() {
+            //every child is preceded by a space, the list is closed after the last one
+            pending.push(Piece::Text(")"));
+            e.children_rev(|child| {
+                pending.push(Piece::Expression(child));
+                pending.push(Piece::Text(" "));
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Notation;
+    use crate::{parse, Expression, Number, Operation, Path, Step, UnaryOperation};
+    use num_rational::BigRational;
+
+    fn printed(input: &str, notation: Notation) -> String {
+        parse(input).unwrap().display(notation).to_string()
+    }
+
+    //printing and parsing again has to give back the same tree
+    fn assert_round_trip(e: &Expression) {
+        for notation in [Notation::Minimal, Notation::Parenthesized] {
+            let text = e.display(notation).to_string();
+            assert_eq!(parse(&text).as_ref(), Ok(e), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_minimal() {
+        let minimal = |input| printed(input, Notation::Minimal);
+        assert_eq!(minimal("(10 * 9) + (5 * (3 - 4))"), "10 * 9 + 5 * (3 - 4)");
+        assert_eq!(minimal("(1 - 2) - (3 - 4)"), "1 - 2 - (3 - 4)");
+        assert_eq!(minimal("(2 ** 3) ** (4 ** 5)"), "(2 ** 3) ** 4 ** 5");
+        assert_eq!(minimal("(-2) ** 2 + -(2 ** 2)"), "(-2) ** 2 + -2 ** 2");
+        assert_eq!(minimal("-(x + 1) * -(5)"), "-(x + 1) * -(5)");
+        assert_eq!(minimal("(a < b) == (c || d)"), "a < b == (c || d)");
+        assert_eq!(minimal("1 + (let x = 2 in x)"), "1 + let x = 2 in x");
+        assert_eq!(minimal("(let x = 2 in x) + 1"), "(let x = 2 in x) + 1");
+        assert_eq!(
+            minimal("(if c then 1 else 2) * if (c) then (1) else (2)"),
+            "(if c then 1 else 2) * if c then 1 else 2"
+        );
+        assert_eq!(
+            minimal("let x = (let y = 1 in y) in x"),
+            "let x = let y = 1 in y in x"
+        );
+        assert_eq!(
+            minimal("fn sq(x) = (x * x); (sq((3)) + sq(4))"),
+            "fn sq(x) = x * x; sq(3) + sq(4)"
+        );
+        assert_eq!(
+            minimal("(fn f() = 1; f()) * max(1 + 2, let x = 1 in x)"),
+            "(fn f() = 1; f()) * max(1 + 2, let x = 1 in x)"
+        );
+    }
+
+    #[test]
+    fn test_parenthesized() {
+        let parenthesized = |input| printed(input, Notation::Parenthesized);
+        assert_eq!(
+            parenthesized("10 * 9 + 5 * (3 - 4)"),
+            "(10 * 9) + (5 * (3 - 4))"
+        );
+        assert_eq!(parenthesized("x - -2 ** -y"), "x - (-(2 ** (-y)))");
+        assert_eq!(
+            parenthesized("let x = 1 + 2 in x * x"),
+            "let x = (1 + 2) in (x * x)"
+        );
+    }
+
+    #[test]
+    fn test_prefix() {
+        let prefix = |input| printed(input, Notation::Prefix);
+        assert_eq!(
+            prefix("(10 * 9) + (5 * (3 - 4))"),
+            "(+ (* 10 9) (* 5 (- 3 4)))"
+        );
+        assert_eq!(
+            prefix("let x = -1 in if !x then x else ~x"),
+            "(let x -1 (if (! x) x (~ x)))"
+        );
+        assert_eq!(
+            prefix("fn f(x, y) = x + y; f(1, g())"),
+            "(fn f (x y) (+ x y) (f 1 (g)))"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            "(10 * 9) + (5 * (3 - 4))",
+            "2 ** 3 ** 2 - (2 ** 3) ** 2",
+            "-2 ** 2 + (-2) ** 2 - -2 - -(2) - --2",
+            "~!x << 1 >> 2 & 3 | 4 ^ 5",
+            "1 == 2 != (3 < 4) && (5 || 6 <= 7)",
+            "-9223372036854775808 % 10 / 3",
+            "let x = if a then b else c in if x then let y = x in y else x + 1",
+            "(if a then b else c) + (let x = 1 in x) * 2",
+            "fn f(a, b) = if a then f(a - 1, b * 2) else b; -f(3, gcd(4, 6)) ** 2",
+            "(fn g() = 1; g()) + (fn h(x) = let y = x in y; h(1))",
+        ];
+        for input in inputs {
+            assert_round_trip(&parse(input).unwrap());
+        }
+        //trees that the parser never produces itself
+        let neg = |operand| Expression::Unary {
+            op: UnaryOperation::Neg,
+            operand: Box::new(operand),
+        };
+        assert_round_trip(&neg(Expression::Value(5)));
+        assert_round_trip(&neg(Expression::Value(-5)));
+        assert_round_trip(&Expression::Op {
+            op: Operation::Pow,
+            left: Box::new(Expression::Value(-2)),
+            right: Box::new(neg(Expression::Value(2))),
+        });
+    }
+
+    #[test]
+    fn test_locate() {
+        let e = parse("(1 + 2) * -(3) + (let x = 4 in x)").unwrap();
+        let text = e.to_string();
+        let located = |steps: &[Step]| e.locate(&Path(steps.to_vec())).map(|range| &text[range]);
+        assert_eq!(located(&[]), Some(text.as_str()));
+        assert_eq!(located(&[Step::Left, Step::Left]), Some("(1 + 2)"));
+        assert_eq!(located(&[Step::Left, Step::Right]), Some("-(3)"));
+        assert_eq!(
+            located(&[Step::Left, Step::Right, Step::Operand]),
+            Some("(3)")
+        );
+        assert_eq!(located(&[Step::Right, Step::Body]), Some("x"));
+        assert_eq!(located(&[Step::Right, Step::Left]), None);
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        //1 - (1 - (1 - ... (1 - x)))
+        let mut e = parse("x").unwrap();
+        for _ in 0..1_000_000 {
+            e = Expression::Op {
+                op: Operation::Sub,
+                left: Box::new(Expression::Value(1)),
+                right: Box::new(e),
+            };
+        }
+        let text = e.to_string();
+        assert_eq!(text.len(), 5_999_999);
+        assert!(text.starts_with("1 - (1 - (") && text.contains("(1 - (1 - x))"));
+        let path = |depth| Path(vec![Step::Right; depth]);
+        assert_eq!(e.locate(&path(1_000_000)), Some(4_999_999..5_000_000));
+        assert_eq!(e.locate(&path(999_999)), Some(4_999_994..5_000_001));
+        //every right operand needs parentheses anyway
+        assert_eq!(e.display(Notation::Parenthesized).to_string(), text);
+        let text = e.display(Notation::Prefix).to_string();
+        assert_eq!(text.len(), 6_000_001);
+        assert!(text.starts_with("(- 1 (- 1 ") && text.contains("(- 1 x))"));
+    }
+
+    #[test]
+    fn test_fractions() {
+        let e = parse("x ** (1 / 3) - 1 / 3").unwrap();
+        let e = crate::simplify(e.map(BigRational::from_i64));
+        assert_eq!(e.to_string(), "x ** (1/3) - 1/3");
+    }
+}
@@ -0,0 +1,1026 @@
+//serialization of `Expression` trees, to send them to other programs
+//there are two formats:
+// - JSON (or any other serde format), every node is a map that is tagged by the key of its variant:
+//   `{"op":"Add","left":...,"right":...}`, `{"value":19}`, `{"bool":true}`, `{"var":"x"}`, `{"let":"x","equals":...,"in":...}`,
+//   `{"unary":"Neg","operand":...}`, `{"if":...,"then":...,"else":...}`,
+//   `{"fn":"sq","params":["x"],"equals":...,"in":...}` and `{"call":"sq","args":[...]}`
+// - a compact binary format, every node is a tag byte followed by its fields, see `to_binary`
+//decoding limits how deep the nodes may be nested, the limit is chosen by the caller
+//`to_json`, `to_binary` and `from_binary` walk the tree with an explicit stack, so they work for arbitrarily deep trees,
+//`from_json` and the `Serialize` and `Deserialize` implementations go through serde, which recurses once per level
+
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Zero;
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeMap};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::node::Shell;
+use crate::{Expression, Operation, UnaryOperation};
+
+//the nesting depth `Serialize` and `Deserialize` allow, and a limit for `from_json` that is safe on any thread:
+//decoding JSON takes about 5 KB of stack per level in debug builds and less than 1 KB in release builds,
+//so 256 levels fit in the 2 MB stack of a spawned thread, deeper trees can be decoded on a thread with a larger stack
+//`from_binary` does not recurse, its limit only has to bound the size of the input, e.g. `usize::MAX` for trusted input
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+//serde formats serialize nested values by recursion, so trees nested deeper than `DEFAULT_MAX_DEPTH` are refused
+//instead of overflowing the stack, `to_json` has no limit
+impl<N: Serialize> Serialize for Expression<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if depth(self) > DEFAULT_MAX_DEPTH {
+            return Err(ser::Error::custom("expression is nested too deeply"));
+        }
+        Nested(self).serialize(serializer)
+    }
+}
+
+//the number of nodes on the longest path from the root to a leaf, a single leaf has the depth 1
+fn depth<N>(e: &Expression<N>) -> usize {
+    let mut max = 0;
+    let mut pending = vec![(e, 1)];
+    while let Some((e, depth)) = pending.pop() {
+        max = max.max(depth);
+        e.children_rev(|child| pending.push((child, depth + 1)));
+    }
+    max
+}
+
+//a subexpression of a tree whose depth is checked
+struct Nested<'a, N>(&'a Expression<N>);
+
+impl<N: Serialize> Serialize for Nested<'_, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Expression::Op { op, left, right } => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("op", op)?;
+                map.serialize_entry("left", &Nested(left))?;
+                map.serialize_entry("right", &Nested(right))?;
+                map.end()
+            }
+            Expression::Value(value) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("value", value)?;
+                map.end()
+            }
+            Expression::Bool(value) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("bool", value)?;
+                map.end()
+            }
+            Expression::Var(name) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("var", name)?;
+                map.end()
+            }
+            Expression::Let { name, value, body } => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("let", name)?;
+                map.serialize_entry("equals", &Nested(value))?;
+                map.serialize_entry("in", &Nested(body))?;
+                map.end()
+            }
+            Expression::Unary { op, operand } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("unary", op)?;
+                map.serialize_entry("operand", &Nested(operand))?;
+                map.end()
+            }
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("if", &Nested(cond))?;
+                map.serialize_entry("then", &Nested(then))?;
+                map.serialize_entry("else", &Nested(otherwise))?;
+                map.end()
+            }
+            Expression::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => {
+                let mut map = serializer.serialize_map(Some(4))?;
+                map.serialize_entry("fn", name)?;
+                map.serialize_entry("params", params)?;
+                map.serialize_entry("equals", &Nested(definition))?;
+                map.serialize_entry("in", &Nested(body))?;
+                map.end()
+            }
+            Expression::Call { name, args } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("call", name)?;
+                map.serialize_entry("args", &args.iter().map(Nested).collect::<Vec<_>>())?;
+                map.end()
+            }
+        }
+    }
+}
+
+//formats may have their own, lower limit, e.g. `serde_json::from_str` stops at 128 levels, `from_json` does not
+impl<'de, N: Deserialize<'de>> Deserialize<'de> for Expression<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ExpressionSeed::new(DEFAULT_MAX_DEPTH).deserialize(deserializer)
+    }
+}
+
+//deserializes an `Expression` that is nested at most `max_depth` levels deep
+pub struct ExpressionSeed<N> {
+    max_depth: usize,
+    number: PhantomData<N>,
+}
+
+impl<N> ExpressionSeed<N> {
+    pub fn new(max_depth: usize) -> ExpressionSeed<N> {
+        ExpressionSeed {
+            max_depth,
+            number: PhantomData,
+        }
+    }
+}
+
+impl<'de, N: Deserialize<'de>> DeserializeSeed<'de> for ExpressionSeed<N> {
+    type Value = Expression<N>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        if self.max_depth == 0 {
+            return Err(de::Error::custom("expression is nested too deeply"));
+        }
+        deserializer.deserialize_map(self)
+    }
+}
+
+//deserializes the arguments of a call, every one nested at most `max_depth` levels deep
+struct ArgsSeed<N> {
+    max_depth: usize,
+    number: PhantomData<N>,
+}
+
+impl<'de, N: Deserialize<'de>> DeserializeSeed<'de> for ArgsSeed<N> {
+    type Value = Vec<Expression<N>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, N: Deserialize<'de>> Visitor<'de> for ArgsSeed<N> {
+    type Value = Vec<Expression<N>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of expressions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut args = Vec::new();
+        while let Some(arg) = seq.next_element_seed(ExpressionSeed::new(self.max_depth))? {
+            args.push(arg);
+        }
+        Ok(args)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Field {
+    Op,
+    Left,
+    Right,
+    Value,
+    Bool,
+    Var,
+    Let,
+    Equals,
+    In,
+    Unary,
+    Operand,
+    If,
+    Then,
+    Else,
+    Fn,
+    Params,
+    Call,
+    Args,
+}
+
+impl<'de, N: Deserialize<'de>> Visitor<'de> for ExpressionSeed<N> {
+    type Value = Expression<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an expression")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut op = None;
+        let mut value = None;
+        let mut boolean = None;
+        let mut var = None;
+        let mut name = None;
+        let mut unary = None;
+        let mut function = None;
+        let mut params = None;
+        let mut call = None;
+        let mut args = None;
+        //the subexpressions, by the key they are stored under
+        let mut children = Vec::new();
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Op => set(&mut op, map.next_value()?, "op")?,
+                Field::Value => set(&mut value, map.next_value()?, "value")?,
+                Field::Bool => set(&mut boolean, map.next_value()?, "bool")?,
+                Field::Var => set(&mut var, map.next_value()?, "var")?,
+                Field::Let => set(&mut name, map.next_value()?, "let")?,
+                Field::Unary => set(&mut unary, map.next_value()?, "unary")?,
+                Field::Fn => set(&mut function, map.next_value()?, "fn")?,
+                Field::Params => set(&mut params, map.next_value()?, "params")?,
+                Field::Call => set(&mut call, map.next_value()?, "call")?,
+                Field::Args => {
+                    let seed = ArgsSeed {
+                        max_depth: self.max_depth - 1,
+                        number: PhantomData,
+                    };
+                    set(&mut args, map.next_value_seed(seed)?, "args")?
+                }
+                field => {
+                    let child = map.next_value_seed(ExpressionSeed::new(self.max_depth - 1))?;
+                    children.push((field.key(), child));
+                }
+            }
+        }
+        let mut child = |key: &'static str| -> Result<_, A::Error> {
+            match children.iter().position(|(k, _)| *k == key) {
+                Some(i) => Ok(Box::new(children.swap_remove(i).1)),
+                None => Err(de::Error::missing_field(key)),
+            }
+        };
+        let e = match (op, value, boolean, var, name, unary, function, call) {
+            (Some(op), None, None, None, None, None, None, None) => Expression::Op {
+                op,
+                left: child("left")?,
+                right: child("right")?,
+            },
+            (None, Some(value), None, None, None, None, None, None) => Expression::Value(value),
+            (None, None, Some(value), None, None, None, None, None) => Expression::Bool(value),
+            (None, None, None, Some(var), None, None, None, None) => Expression::Var(var),
+            (None, None, None, None, Some(name), None, None, None) => Expression::Let {
+                name,
+                value: child("equals")?,
+                body: child("in")?,
+            },
+            (None, None, None, None, None, Some(op), None, None) => Expression::Unary {
+                op,
+                operand: child("operand")?,
+            },
+            (None, None, None, None, None, None, Some(name), None) => Expression::Fn {
+                name,
+                params: params.take().ok_or(de::Error::missing_field("params"))?,
+                definition: child("equals")?,
+                body: child("in")?,
+            },
+            (None, None, None, None, None, None, None, Some(name)) => Expression::Call {
+                name,
+                args: args.take().ok_or(de::Error::missing_field("args"))?,
+            },
+            (None, None, None, None, None, None, None, None) => Expression::If {
+                cond: child("if")?,
+                then: child("then")?,
+                otherwise: child("else")?,
+            },
+            _ => {
+                return Err(de::Error::custom(
+                    "expected exactly one of `op`, `value`, `bool`, `var`, `let`, `unary`, `if`, `fn` or `call`",
+                ))
+            }
+        };
+        let unexpected = children
+            .first()
+            .map(|(key, _)| *key)
+            .or(params.map(|_| "params"))
+            .or(args.map(|_| "args"));
+        match unexpected {
+            Some(key) => Err(de::Error::custom(format!("unexpected field `{key}`"))),
+            None => Ok(e),
+        }
+    }
+}
+
+impl Field {
+    fn key(&self) -> &'static str {
+        match self {
+            Field::Op => "op",
+            Field::Left => "left",
+            Field::Right => "right",
+            Field::Value => "value",
+            Field::Bool => "bool",
+            Field::Var => "var",
+            Field::Let => "let",
+            Field::Equals => "equals",
+            Field::In => "in",
+            Field::Unary => "unary",
+            Field::Operand => "operand",
+            Field::If => "if",
+            Field::Then => "then",
+            Field::Else => "else",
+            Field::Fn => "fn",
+            Field::Params => "params",
+            Field::Call => "call",
+            Field::Args => "args",
+        }
+    }
+}
+
+fn set<T, E: de::Error>(slot: &mut Option<T>, value: T, key: &'static str) -> Result<(), E> {
+    match slot.replace(value) {
+        Some(_) => Err(de::Error::duplicate_field(key)),
+        None => Ok(()),
+    }
+}
+
+//writes the same JSON as `Serialize` with an explicit stack, only the leaves are serialized by serde
+pub fn to_json<N: Serialize>(e: &Expression<N>) -> String {
+    enum Piece<'e, N> {
+        Expression(&'e Expression<N>),
+        Text(&'static str),
+    }
+    use Piece::{Expression as E, Text};
+    let mut output = Vec::new();
+    let mut pending = vec![E(e)];
+    while let Some(piece) = pending.pop() {
+        let e = match piece {
+            E(e) => e,
+            Text(text) => {
+                output.extend(text.as_bytes());
+                continue;
+            }
+        };
+        //the start of a node is written right away, the rest is pushed in reverse
+        match e {
+            Expression::Op { op, left, right } => {
+                write_json(&mut output, r#"{"op":"#, op);
+                pending.extend([Text("}"), E(right), Text(r#","right":"#), E(left)]);
+                pending.push(Text(r#","left":"#));
+            }
+            Expression::Value(value) => {
+                write_json(&mut output, r#"{"value":"#, value);
+                output.push(b'}');
+            }
+            Expression::Bool(value) => {
+                write_json(&mut output, r#"{"bool":"#, value);
+                output.push(b'}');
+            }
+            Expression::Var(name) => {
+                write_json(&mut output, r#"{"var":"#, name);
+                output.push(b'}');
+            }
+            Expression::Let { name, value, body } => {
+                write_json(&mut output, r#"{"let":"#, name);
+                pending.extend([Text("}"), E(body), Text(r#","in":"#), E(value)]);
+                pending.push(Text(r#","equals":"#));
+            }
+            Expression::Unary { op, operand } => {
+                write_json(&mut output, r#"{"unary":"#, op);
+                pending.extend([Text("}"), E(operand), Text(r#","operand":"#)]);
+            }
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                output.extend(br#"{"if":"#);
+                pending.extend([Text("}"), E(otherwise), Text(r#","else":"#), E(then)]);
+                pending.extend([Text(r#","then":"#), E(cond)]);
+            }
+            Expression::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => {
+                write_json(&mut output, r#"{"fn":"#, name);
+                write_json(&mut output, r#","params":"#, params);
+                pending.extend([Text("}"), E(body), Text(r#","in":"#), E(definition)]);
+                pending.push(Text(r#","equals":"#));
+            }
+            Expression::Call { name, args } => {
+                write_json(&mut output, r#"{"call":"#, name);
+                output.extend(br#","args":["#);
+                pending.push(Text("]}"));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    pending.push(E(arg));
+                    if i > 0 {
+                        pending.push(Text(","));
+                    }
+                }
+            }
+        }
+    }
+    String::from_utf8(output).expect("JSON is UTF-8")
+}
+
+fn write_json<T: Serialize + ?Sized>(output: &mut Vec<u8>, key: &str, value: &T) {
+    output.extend(key.as_bytes());
+    serde_json::to_writer(output, value).expect("an expression can always be represented as JSON");
+}
+
+//serde_json's own limit of 128 levels is lifted, `max_depth` limits the depth instead, see `DEFAULT_MAX_DEPTH`
+pub fn from_json<'de, N: Deserialize<'de>>(
+    input: &'de str,
+    max_depth: usize,
+) -> Result<Expression<N>, serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(input);
+    deserializer.disable_recursion_limit();
+    let e = ExpressionSeed::new(max_depth).deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(e)
+}
+
+//the binary format: every node starts with a tag byte
+// - 0: `Op`, followed by the operation (1 byte), `left` and `right`
+// - 1: `Value`, followed by the number, see `BinaryNumber`
+// - 2: `Var`, followed by the name
+// - 3: `Let`, followed by the name, `value` and `body`
+// - 4: `Unary`, followed by the operation (1 byte) and the `operand`
+// - 5: `If`, followed by `cond`, `then` and `otherwise`
+// - 6: `Fn`, followed by the name, the number of parameters, their names, `definition` and `body`
+// - 7: `Call`, followed by the name, the number of arguments and the arguments
+// - 8 and 9: `Bool` with the value `false` and `true`
+//operations are numbered in the order they are declared in, starting at 0
+//names are UTF-8 and prefixed by their length in bytes, lengths and counts are unsigned LEB128 varints
+const OP: u8 = 0;
+const VALUE: u8 = 1;
+const VAR: u8 = 2;
+const LET: u8 = 3;
+const UNARY: u8 = 4;
+const IF: u8 = 5;
+const FN: u8 = 6;
+const CALL: u8 = 7;
+const FALSE: u8 = 8;
+const TRUE: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    UnexpectedEnd,
+    InvalidTag(u8),
+    InvalidOperation(u8),
+    InvalidUtf8,
+    //a number or length that does not fit in its type, or a rational with denominator 0
+    InvalidNumber,
+    TooDeep,
+    TrailingBytes,
+}
+
+//a binary decoding error, together with the byte offset at which it was detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeErrorKind::InvalidTag(tag) => write!(f, "invalid node tag {tag}"),
+            DecodeErrorKind::InvalidOperation(op) => write!(f, "invalid operation {op}"),
+            DecodeErrorKind::InvalidUtf8 => write!(f, "name is not valid UTF-8"),
+            DecodeErrorKind::InvalidNumber => write!(f, "invalid number"),
+            DecodeErrorKind::TooDeep => write!(f, "expression is nested too deeply"),
+            DecodeErrorKind::TrailingBytes => write!(f, "unexpected bytes after the expression"),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl Error for DecodeError {}
+
+//the binary representation of the number types
+pub trait BinaryNumber: Sized {
+    fn encode(&self, output: &mut Vec<u8>);
+    fn decode(input: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+//zigzag encoded as a varint, so small negative numbers are short as well
+impl BinaryNumber for i64 {
+    fn encode(&self, output: &mut Vec<u8>) {
+        write_varint(output, ((self << 1) ^ (self >> 63)) as u64);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, DecodeError> {
+        let n = input.varint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+}
+
+impl BinaryNumber for f64 {
+    fn encode(&self, output: &mut Vec<u8>) {
+        output.extend(self.to_le_bytes());
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, DecodeError> {
+        let bytes = input.bytes(8)?;
+        Ok(f64::from_le_bytes(
+            bytes.try_into().expect("8 bytes were read"),
+        ))
+    }
+}
+
+//the two's complement bytes, least significant first, prefixed by their length
+impl BinaryNumber for BigInt {
+    fn encode(&self, output: &mut Vec<u8>) {
+        let bytes = self.to_signed_bytes_le();
+        write_varint(output, bytes.len() as u64);
+        output.extend(bytes);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, DecodeError> {
+        let len = input.length()?;
+        Ok(BigInt::from_signed_bytes_le(input.bytes(len)?))
+    }
+}
+
+//the numerator followed by the denominator
+impl BinaryNumber for BigRational {
+    fn encode(&self, output: &mut Vec<u8>) {
+        self.numer().encode(output);
+        self.denom().encode(output);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, DecodeError> {
+        let numerator = BigInt::decode(input)?;
+        let offset = input.offset;
+        let denominator = BigInt::decode(input)?;
+        if Zero::is_zero(&denominator) {
+            return Err(DecodeError {
+                kind: DecodeErrorKind::InvalidNumber,
+                offset,
+            });
+        }
+        Ok(BigRational::new(numerator, denominator))
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        output.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    output.push(n as u8);
+}
+
+fn write_name(output: &mut Vec<u8>, name: &str) {
+    write_varint(output, name.len() as u64);
+    output.extend(name.as_bytes());
+}
+
+pub fn to_binary<N: BinaryNumber>(e: &Expression<N>) -> Vec<u8> {
+    let mut output = Vec::new();
+    encode(e, &mut output);
+    output
+}
+
+//the nodes are written in preorder, every node is followed by its children
+fn encode<N: BinaryNumber>(e: &Expression<N>, output: &mut Vec<u8>) {
+    let mut pending = vec![e];
+    while let Some(e) = pending.pop() {
+        match e {
+            Expression::Op { op, .. } => {
+                let code = Operation::ALL.iter().position(|o| o == op);
+                output.extend([OP, code.expect("every operation has a code") as u8]);
+            }
+            Expression::Value(value) => {
+                output.push(VALUE);
+                value.encode(output);
+            }
+            Expression::Bool(value) => output.push(if *value { TRUE } else { FALSE }),
+            Expression::Var(name) => {
+                output.push(VAR);
+                write_name(output, name);
+            }
+            Expression::Let { name, .. } => {
+                output.push(LET);
+                write_name(output, name);
+            }
+            Expression::Unary { op, .. } => {
+                let code = UnaryOperation::ALL.iter().position(|o| o == op);
+                output.extend([UNARY, code.expect("every operation has a code") as u8]);
+            }
+            Expression::If { .. } => output.push(IF),
+            Expression::Fn { name, params, .. } => {
+                output.push(FN);
+                write_name(output, name);
+                write_varint(output, params.len() as u64);
+                for param in params {
+                    write_name(output, param);
+                }
+            }
+            Expression::Call { name, args } => {
+                output.push(CALL);
+                write_name(output, name);
+                write_varint(output, args.len() as u64);
+            }
+        }
+        e.children_rev(|child| pending.push(child));
+    }
+}
+
+//decodes an expression that is nested at most `max_depth` levels deep, the whole input has to be used
+pub fn from_binary<N: BinaryNumber>(
+    input: &[u8],
+    max_depth: usize,
+) -> Result<Expression<N>, DecodeError> {
+    let mut reader = Reader { input, offset: 0 };
+    let e = reader.expression(max_depth)?;
+    if reader.offset < input.len() {
+        return Err(reader.error(DecodeErrorKind::TrailingBytes));
+    }
+    Ok(e)
+}
+
+//the part of the binary input that has not been decoded yet
+pub struct Reader<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            kind,
+            offset: self.offset,
+        }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        match self
+            .input
+            .get(self.offset..)
+            .and_then(|rest| rest.get(..len))
+        {
+            Some(bytes) => {
+                self.offset += len;
+                Ok(bytes)
+            }
+            None => Err(self.error(DecodeErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    pub fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn varint(&mut self) -> Result<u64, DecodeError> {
+        let start = self.offset;
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                break;
+            }
+            n |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(DecodeError {
+            kind: DecodeErrorKind::InvalidNumber,
+            offset: start,
+        })
+    }
+
+    pub fn length(&mut self) -> Result<usize, DecodeError> {
+        let start = self.offset;
+        usize::try_from(self.varint()?).map_err(|_| DecodeError {
+            kind: DecodeErrorKind::InvalidNumber,
+            offset: start,
+        })
+    }
+
+    fn name(&mut self) -> Result<String, DecodeError> {
+        let len = self.length()?;
+        let start = self.offset;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError {
+            kind: DecodeErrorKind::InvalidUtf8,
+            offset: start,
+        })
+    }
+
+    fn operation<T: Copy>(&mut self, operations: &[T]) -> Result<T, DecodeError> {
+        let code = self.byte()?;
+        match operations.get(usize::from(code)) {
+            Some(op) => Ok(*op),
+            None => Err(DecodeError {
+                kind: DecodeErrorKind::InvalidOperation(code),
+                offset: self.offset - 1,
+            }),
+        }
+    }
+
+    //decodes with an explicit stack, the decoded subexpressions wait in `built` until their parent is complete
+    fn expression<N: BinaryNumber>(
+        &mut self,
+        max_depth: usize,
+    ) -> Result<Expression<N>, DecodeError> {
+        enum Task {
+            //decodes `count` expressions one after another, at the level `depth`
+            Decode { depth: usize, count: usize },
+            Build(Shell),
+        }
+        let mut tasks = vec![Task::Decode { depth: 1, count: 1 }];
+        let mut built = Vec::new();
+        while let Some(task) = tasks.pop() {
+            let depth = match task {
+                Task::Decode { count: 0, .. } => continue,
+                Task::Decode { depth, count } => {
+                    //the count is not trusted to preallocate, every expression takes at least one byte anyway
+                    tasks.push(Task::Decode {
+                        depth,
+                        count: count - 1,
+                    });
+                    depth
+                }
+                Task::Build(shell) => {
+                    let e = shell.build(&mut built);
+                    built.push(e);
+                    continue;
+                }
+            };
+            if depth > max_depth {
+                return Err(self.error(DecodeErrorKind::TooDeep));
+            }
+            let (shell, count) = match self.byte()? {
+                OP => (Shell::Op(self.operation(&Operation::ALL)?), 2),
+                VALUE => {
+                    built.push(Expression::Value(N::decode(self)?));
+                    continue;
+                }
+                FALSE => {
+                    built.push(Expression::Bool(false));
+                    continue;
+                }
+                TRUE => {
+                    built.push(Expression::Bool(true));
+                    continue;
+                }
+                VAR => {
+                    built.push(Expression::Var(self.name()?));
+                    continue;
+                }
+                LET => (Shell::Let(self.name()?), 2),
+                UNARY => (Shell::Unary(self.operation(&UnaryOperation::ALL)?), 1),
+                IF => (Shell::If, 3),
+                FN => {
+                    let name = self.name()?;
+                    let params = (0..self.length()?)
+                        .map(|_| self.name())
+                        .collect::<Result<_, _>>()?;
+                    (Shell::Fn(name, params), 2)
+                }
+                CALL => {
+                    let name = self.name()?;
+                    let count = self.length()?;
+                    (Shell::Call(name, count), count)
+                }
+                tag => {
+                    return Err(DecodeError {
+                        kind: DecodeErrorKind::InvalidTag(tag),
+                        offset: self.offset - 1,
+                    })
+                }
+            };
+            tasks.push(Task::Build(shell));
+            tasks.push(Task::Decode {
+                depth: depth + 1,
+                count,
+            });
+        }
+        Ok(built.pop().expect("the root was decoded"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        from_binary, from_json, to_binary, to_json, DecodeError, DecodeErrorKind, DEFAULT_MAX_DEPTH,
+    };
+    use crate::{parse, Expression, Number, Operation};
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    #[test]
+    fn test_json() {
+        let e = parse("19 + x").unwrap();
+        let json = to_json(&e);
+        assert_eq!(
+            json,
+            r#"{"op":"Add","left":{"value":19},"right":{"var":"x"}}"#
+        );
+        assert_eq!(from_json::<i64>(&json, DEFAULT_MAX_DEPTH).unwrap(), e);
+
+        let e = parse("let x = -1 in if !x then x else x ** 2").unwrap();
+        assert_eq!(
+            to_json(&e),
+            r#"{"let":"x","equals":{"value":-1},"in":{"if":{"unary":"Not","operand":{"var":"x"}},"then":{"var":"x"},"else":{"op":"Pow","left":{"var":"x"},"right":{"value":2}}}}"#
+        );
+        assert_eq!(
+            from_json::<i64>(&to_json(&e), DEFAULT_MAX_DEPTH).unwrap(),
+            e
+        );
+
+        let e = parse("fn sq(x) = x * x; sq(max(1, 2))").unwrap();
+        assert_eq!(
+            to_json(&e),
+            r#"{"fn":"sq","params":["x"],"equals":{"op":"Mul","left":{"var":"x"},"right":{"var":"x"}},"in":{"call":"sq","args":[{"call":"max","args":[{"value":1},{"value":2}]}]}}"#
+        );
+        assert_eq!(
+            from_json::<i64>(&to_json(&e), DEFAULT_MAX_DEPTH).unwrap(),
+            e
+        );
+
+        let e = parse("true && !false").unwrap();
+        assert_eq!(
+            to_json(&e),
+            r#"{"op":"And","left":{"bool":true},"right":{"unary":"Not","operand":{"bool":false}}}"#
+        );
+        assert_eq!(
+            from_json::<i64>(&to_json(&e), DEFAULT_MAX_DEPTH).unwrap(),
+            e
+        );
+
+        //the keys may be in any order
+        let json = r#"{"right":{"value":2},"op":"Mul","left":{"value":0.5}}"#;
+        assert_eq!(
+            from_json::<f64>(json, DEFAULT_MAX_DEPTH).unwrap(),
+            Expression::Op {
+                op: Operation::Mul,
+                left: Box::new(Expression::Value(0.5)),
+                right: Box::new(Expression::Value(2.0)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let error = |json| {
+            from_json::<i64>(json, DEFAULT_MAX_DEPTH)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error(r#"{"op":"Add","left":{"value":1}}"#).starts_with("missing field `right`"));
+        assert!(error(r#"{"value":1,"var":"x"}"#).starts_with("expected exactly one of"));
+        assert!(error(r#"{"value":1,"left":{"value":1}}"#).starts_with("unexpected field `left`"));
+        assert!(error(r#"{"value":1,"value":2}"#).starts_with("duplicate field `value`"));
+        assert!(
+            error(r#"{"op":"Foo","left":{"value":1},"right":{"value":1}}"#)
+                .starts_with("unknown variant `Foo`")
+        );
+        assert!(error(r#"{"value":1} 2"#).starts_with("trailing characters"));
+        assert!(error(r#"{"fn":"f","equals":{"value":1},"in":{"value":1}}"#)
+            .starts_with("missing field `params`"));
+        assert!(error(r#"{"value":1,"args":[]}"#).starts_with("unexpected field `args`"));
+    }
+
+    #[test]
+    fn test_binary() {
+        let e = parse("let x = -1 in if !x then x else x ** 200 % y").unwrap();
+        assert_eq!(from_binary(&to_binary(&e), DEFAULT_MAX_DEPTH), Ok(e));
+        let e = parse("fn f(a, b) = a - b; f(1, g())").unwrap();
+        assert_eq!(from_binary(&to_binary(&e), DEFAULT_MAX_DEPTH), Ok(e));
+        assert_eq!(
+            to_binary(&parse("19 + x").unwrap()),
+            [0, 0, 1, 38, 2, 1, b'x']
+        );
+        assert_eq!(to_binary(&parse("!true").unwrap()), [4, 1, 9]);
+        assert_eq!(
+            from_binary(&[0, 17, 9, 8], DEFAULT_MAX_DEPTH),
+            Ok(parse("true && false").unwrap())
+        );
+
+        let e = parse("(1 / 3) + 2 ** 100")
+            .unwrap()
+            .map(BigRational::from_i64);
+        let e = crate::simplify(e);
+        assert_eq!(from_binary(&to_binary(&e), DEFAULT_MAX_DEPTH), Ok(e));
+        let e = parse("-(2 ** 70)").unwrap().map(BigInt::from_i64);
+        assert_eq!(from_binary(&to_binary(&e), DEFAULT_MAX_DEPTH), Ok(e));
+        let e = Expression::Value(f64::MIN_POSITIVE);
+        assert_eq!(from_binary(&to_binary(&e), DEFAULT_MAX_DEPTH), Ok(e));
+    }
+
+    #[test]
+    fn test_invalid_binary() {
+        let error = |input: &[u8]| from_binary::<i64>(input, DEFAULT_MAX_DEPTH).unwrap_err();
+        let error_at = |kind, offset| DecodeError { kind, offset };
+        assert_eq!(error(&[]), error_at(DecodeErrorKind::UnexpectedEnd, 0));
+        assert_eq!(
+            error(&[0, 0, 1, 2]),
+            error_at(DecodeErrorKind::UnexpectedEnd, 4)
+        );
+        assert_eq!(error(&[10]), error_at(DecodeErrorKind::InvalidTag(10), 0));
+        assert_eq!(
+            error(&[0, 19]),
+            error_at(DecodeErrorKind::InvalidOperation(19), 1)
+        );
+        assert_eq!(
+            error(&[7, 1, b'f', 2, 1, 2]),
+            error_at(DecodeErrorKind::UnexpectedEnd, 6)
+        );
+        assert_eq!(
+            error(&[2, 1, 0xff]),
+            error_at(DecodeErrorKind::InvalidUtf8, 2)
+        );
+        assert_eq!(
+            error(&[1, 2, 2]),
+            error_at(DecodeErrorKind::TrailingBytes, 2)
+        );
+        assert_eq!(
+            error(&[1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+            error_at(DecodeErrorKind::InvalidNumber, 1)
+        );
+        let zero_denominator = [1, 1, 1, 0];
+        assert_eq!(
+            from_binary::<BigRational>(&zero_denominator, DEFAULT_MAX_DEPTH),
+            Err(error_at(DecodeErrorKind::InvalidNumber, 3))
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        //-(-(-(...)))
+        let mut binary = [4, 0].repeat(1_000_000);
+        binary.extend([1, 2]);
+        assert_eq!(
+            from_binary::<i64>(&binary, DEFAULT_MAX_DEPTH),
+            Err(DecodeError {
+                kind: DecodeErrorKind::TooDeep,
+                offset: 2 * DEFAULT_MAX_DEPTH,
+            })
+        );
+        assert!(from_binary::<i64>(&binary[2 * 999_990..], 10).is_err());
+        assert!(from_binary::<i64>(&binary[2 * 999_990..], 11).is_ok());
+
+        let json = r#"{"unary":"Neg","operand":{"unary":"Neg","operand":{"value":1}}}"#;
+        assert!(from_json::<i64>(json, 3).is_ok());
+        assert!(from_json::<i64>(json, 2)
+            .unwrap_err()
+            .to_string()
+            .starts_with("expression is nested too deeply"));
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        //parse accepts long chains like 1 + 2 + ... + 200, which are nested deeper than serde_json allows by default
+        let sum = |n: i64| {
+            let terms = (1..=n).map(|i| i.to_string()).collect::<Vec<_>>();
+            parse(&terms.join(" + ")).unwrap()
+        };
+        let e = sum(200);
+        assert_eq!(
+            from_json::<i64>(&to_json(&e), DEFAULT_MAX_DEPTH).unwrap(),
+            e
+        );
+        let json = serde_json::to_string(&e).unwrap();
+        assert_eq!(json, to_json(&e));
+        assert_eq!(from_json::<i64>(&json, DEFAULT_MAX_DEPTH).unwrap(), e);
+
+        //deeper trees can be decoded with a larger limit on a thread with a larger stack
+        let e = sum(2000);
+        let json = to_json(&e);
+        let decoded = std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(move || from_json::<i64>(&json, 2000).unwrap())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(decoded, e);
+        assert!(serde_json::to_string(&e)
+            .unwrap_err()
+            .to_string()
+            .starts_with("expression is nested too deeply"));
+
+        //the binary format and `to_json` have no limit on the depth at all
+        let e = parse(&vec!["1"; 1_000_000].join(" + ")).unwrap();
+        assert_eq!(from_binary(&to_binary(&e), usize::MAX), Ok(e.clone()));
+        let json = to_json(&e);
+        assert!(json.starts_with(&r#"{"op":"Add","left":"#.repeat(999_999)));
+        assert!(from_json::<i64>(&json, DEFAULT_MAX_DEPTH)
+            .unwrap_err()
+            .to_string()
+            .starts_with("expression is nested too deeply"));
+    }
+}
@@ -0,0 +1,114 @@
+//the errors that can occur while evaluating an `Expression`
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{Limit, Operation, Path, TypeError, TypeErrorKind, UnaryOperation};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalErrorKind<N = i64> {
+    DivisionByZero,
+    //the result of `left op right` does not fit in the numeric type,
+    //or is larger than the arbitrary-precision types compute, see `number::MAX_BITS`
+    Overflow {
+        op: Operation,
+        left: N,
+        right: N,
+    },
+    UnaryOverflow {
+        op: UnaryOperation,
+        operand: N,
+    },
+    //the operation is not defined for these operands, e.g. a negative exponent for integers
+    OutOfDomain {
+        op: Operation,
+        left: N,
+        right: N,
+    },
+    //the number type does not support this operator, e.g. bitwise operations on floating point numbers
+    Unsupported(&'static str),
+    UnboundVariable(String),
+    //a call of a function that is neither defined nor built in
+    UndefinedFunction(String),
+    WrongArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    //more nested function calls than `EvalOptions::max_call_depth`, usually an endless recursion
+    RecursionLimit(usize),
+    //the evaluation did more work than `EvalOptions::budget` allows
+    BudgetExhausted(Limit),
+    //the `EvalOptions::cancellation` token was cancelled
+    Cancelled,
+    //the expression is not well-typed, so it was not evaluated, see `type_check`
+    Type(TypeErrorKind),
+}
+
+//an evaluation error, together with the path to the subexpression that caused it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError<N = i64> {
+    pub kind: EvalErrorKind<N>,
+    pub path: Path,
+}
+
+impl<N: fmt::Display> fmt::Display for EvalErrorKind<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::Overflow { op, left, right } => {
+                write!(f, "integer overflow in `{left} {op} {right}`")
+            }
+            EvalErrorKind::UnaryOverflow { op, operand } => {
+                write!(f, "integer overflow in `{op}({operand})`")
+            }
+            EvalErrorKind::OutOfDomain { op, left, right } => {
+                write!(f, "`{left} {op} {right}` is undefined")
+            }
+            EvalErrorKind::Unsupported(op) => {
+                write!(f, "`{op}` is not supported by this number type")
+            }
+            EvalErrorKind::UnboundVariable(name) => write!(f, "unbound variable `{name}`"),
+            EvalErrorKind::UndefinedFunction(name) => write!(f, "undefined function `{name}`"),
+            EvalErrorKind::WrongArity {
+                name,
+                expected,
+                found,
+            } => {
+                let s = if *expected == 1 { "" } else { "s" };
+                let were = if *found == 1 { "was" } else { "were" };
+                write!(
+                    f,
+                    "`{name}` takes {expected} argument{s} but {found} {were} given"
+                )
+            }
+            EvalErrorKind::RecursionLimit(limit) => {
+                write!(f, "more than {limit} nested function calls")
+            }
+            EvalErrorKind::BudgetExhausted(limit) => write!(f, "budget exhausted: {limit}"),
+            EvalErrorKind::Cancelled => write!(f, "evaluation cancelled"),
+            EvalErrorKind::Type(kind) => write!(f, "type error: {kind}"),
+        }
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for EvalError<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_root() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} at {}", self.kind, self.path)
+        }
+    }
+}
+
+impl<N: fmt::Debug + fmt::Display> Error for EvalError<N> {}
+
+impl<N> From<TypeError> for EvalError<N> {
+    fn from(error: TypeError) -> EvalError<N> {
+        EvalError {
+            kind: EvalErrorKind::Type(error.kind),
+            path: error.path,
+        }
+    }
+}
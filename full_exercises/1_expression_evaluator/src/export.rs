@@ -0,0 +1,234 @@
+//renders an `Expression` as a picture of its tree, to review expressions that are too large to read in infix notation:
+// - `to_dot`: a Graphviz DOT graph, e.g. `dot -Tsvg` turns it into an image
+// - `to_tree`: an indented tree for the terminal, with the root on the first line
+//operators, `let`s, conditionals, functions and calls are inner nodes, literals and variables are leaves,
+//the children of an inner node are in the order they are printed in infix notation,
+//the edges of children other than operands are labeled with their `Step`, e.g. `then` or `arg0`
+//the trees are walked without recursion, so arbitrarily deep trees can be rendered
+
+use std::fmt::{self, Write};
+
+use crate::{Expression, Step};
+
+//e.g. for `1 + x`
+//  digraph expression {
+//      ordering=out;
+//      n0 [label="+"];
+//      n1 [label="1", shape=box];
+//      n0 -> n1;
+//      n2 [label="x", shape=box];
+//      n0 -> n2;
+//  }
+pub fn to_dot<N: fmt::Display>(e: &Expression<N>) -> String {
+    let mut output = String::from("digraph expression {\n    ordering=out;\n");
+    //the nodes are numbered in the order they are written, the parent comes first
+    let mut count = 0;
+    let mut pending = vec![(e, None)];
+    while let Some((e, parent)) = pending.pop() {
+        let id = count;
+        count += 1;
+        let shape = match e {
+            Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => ", shape=box",
+            _ => "",
+        };
+        let label = escape(&label(e));
+        writeln!(output, "    n{id} [label=\"{label}\"{shape}];").expect(WRITE);
+        match parent {
+            Some((parent, step)) if is_labeled(step) => {
+                writeln!(output, "    n{parent} -> n{id} [label=\"{step}\"];").expect(WRITE)
+            }
+            Some((parent, _)) => writeln!(output, "    n{parent} -> n{id};").expect(WRITE),
+            None => {}
+        }
+        pending.extend(
+            children(e)
+                .into_iter()
+                .rev()
+                .map(|(step, child)| (child, Some((id, step)))),
+        );
+    }
+    output.push('}');
+    output
+}
+
+//e.g. for `if x then 1 + 2 else 0`
+//  if
+//  ├── cond: x
+//  ├── then: +
+//  │   ├── 1
+//  │   └── 2
+//  └── else: 0
+pub fn to_tree<N: fmt::Display>(e: &Expression<N>) -> String {
+    let mut output = String::new();
+    //every pending subexpression comes with the start of its line and the indentation of its children
+    let mut pending = vec![(e, String::new(), String::new())];
+    while let Some((e, line, indentation)) = pending.pop() {
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        write!(output, "{line}{}", label(e)).expect(WRITE);
+        let children = children(e);
+        let last = children.len().saturating_sub(1);
+        pending.extend(
+            children
+                .into_iter()
+                .enumerate()
+                .rev()
+                .map(|(i, (step, child))| {
+                    let (branch, continuation) = if i == last {
+                        ("└── ", "    ")
+                    } else {
+                        ("├── ", "│   ")
+                    };
+                    let mut line = format!("{indentation}{branch}");
+                    if is_labeled(step) {
+                        write!(line, "{step}: ").expect(WRITE);
+                    }
+                    (child, line, format!("{indentation}{continuation}"))
+                }),
+        );
+    }
+    output
+}
+
+const WRITE: &str = "writing to a string cannot fail";
+
+//the text of a node, without its children
+fn label<N: fmt::Display>(e: &Expression<N>) -> String {
+    match e {
+        Expression::Op { op, .. } => op.to_string(),
+        Expression::Unary { op, .. } => op.to_string(),
+        Expression::Value(value) => value.to_string(),
+        Expression::Bool(value) => value.to_string(),
+        Expression::Var(name) => name.clone(),
+        Expression::Let { name, .. } => format!("let {name}"),
+        Expression::If { .. } => String::from("if"),
+        Expression::Fn { name, params, .. } => format!("fn {name}({})", params.join(", ")),
+        Expression::Call { name, .. } => format!("{name}()"),
+    }
+}
+
+fn children<N>(e: &Expression<N>) -> Vec<(Step, &Expression<N>)> {
+    match e {
+        Expression::Op { left, right, .. } => vec![(Step::Left, left), (Step::Right, right)],
+        Expression::Unary { operand, .. } => vec![(Step::Operand, operand)],
+        Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => Vec::new(),
+        Expression::Let { value, body, .. } => vec![(Step::Value, value), (Step::Body, body)],
+        Expression::If {
+            cond,
+            then,
+            otherwise,
+        } => vec![
+            (Step::Cond, cond),
+            (Step::Then, then),
+            (Step::Else, otherwise),
+        ],
+        Expression::Fn {
+            definition, body, ..
+        } => vec![(Step::Definition, definition), (Step::Body, body)],
+        Expression::Call { args, .. } => args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| (Step::Arg(i), arg))
+            .collect(),
+    }
+}
+
+//the operands of operators are told apart by their order
+fn is_labeled(step: Step) -> bool {
+    !matches!(step, Step::Left | Step::Right | Step::Operand)
+}
+
+//in a quoted DOT string only `"` and `\` are special
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{to_dot, to_tree};
+    use crate::{parse, Expression, Number, Operation};
+    use num_rational::BigRational;
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(
+            to_dot(&parse("(10 * 9) + -x").unwrap()),
+            "digraph expression {
+    ordering=out;
+    n0 [label=\"+\"];
+    n1 [label=\"*\"];
+    n0 -> n1;
+    n2 [label=\"10\", shape=box];
+    n1 -> n2;
+    n3 [label=\"9\", shape=box];
+    n1 -> n3;
+    n4 [label=\"-\"];
+    n0 -> n4;
+    n5 [label=\"x\", shape=box];
+    n4 -> n5;
+}"
+        );
+        assert_eq!(
+            to_dot(&parse("let y = 1 in f(y)").unwrap()),
+            "digraph expression {
+    ordering=out;
+    n0 [label=\"let y\"];
+    n1 [label=\"1\", shape=box];
+    n0 -> n1 [label=\"value\"];
+    n2 [label=\"f()\"];
+    n0 -> n2 [label=\"body\"];
+    n3 [label=\"y\", shape=box];
+    n2 -> n3 [label=\"arg0\"];
+}"
+        );
+        assert_eq!(
+            to_dot(&Expression::Value(
+                BigRational::from_i64(1) / BigRational::from_i64(3)
+            )),
+            "digraph expression {\n    ordering=out;\n    n0 [label=\"1/3\", shape=box];\n}"
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        assert_eq!(
+            to_tree(&parse("(10 * 9) + (5 * (3 - 4))").unwrap()),
+            "+
+├── *
+│   ├── 10
+│   └── 9
+└── *
+    ├── 5
+    └── -
+        ├── 3
+        └── 4"
+        );
+        assert_eq!(
+            to_tree(&parse("fn sq(x) = x * x; if sq(2) then 1 else 0").unwrap()),
+            "fn sq(x)
+├── definition: *
+│   ├── x
+│   └── x
+└── body: if
+    ├── cond: sq()
+    │   └── arg0: 2
+    ├── then: 1
+    └── else: 0"
+        );
+        assert_eq!(to_tree(&Expression::Value(7)), "7");
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let mut e = Expression::Value(0);
+        for i in 0..100_000 {
+            e = Expression::Op {
+                op: Operation::Sub,
+                left: Box::new(e),
+                right: Box::new(Expression::Value(i)),
+            };
+        }
+        assert_eq!(to_dot(&e).lines().count(), 400_004);
+    }
+}
@@ -0,0 +1,1300 @@
+pub mod budget;
+pub mod builtin;
+pub mod derivative;
+pub mod display;
+pub mod encoding;
+pub mod error;
+pub mod export;
+pub mod node;
+pub mod number;
+pub mod parser;
+pub mod path;
+#[cfg(test)]
+mod properties;
+pub mod simplify;
+pub mod trace;
+pub mod typing;
+pub mod vm;
+
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+pub use budget::{Budget, CancellationToken, Limit};
+pub use builtin::Builtin;
+pub use derivative::{derive, DeriveError, DeriveErrorKind};
+pub use display::Notation;
+pub use error::{EvalError, EvalErrorKind};
+pub use export::{to_dot, to_tree};
+pub use node::Node;
+use node::Shell;
+pub use number::Number;
+pub use parser::{parse, ParseError, ParseErrorKind};
+pub use path::{Path, Step};
+pub use simplify::simplify;
+pub use trace::{trace, Reduction, Trace};
+pub use typing::{type_check, Type, TypeError, TypeErrorKind, Typing};
+pub use vm::{compile, Program};
+
+//an `Operation` combines the results of two subexpressions
+//comparisons and logical operations yield booleans, which are evaluated to 1 for true and 0 for false
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    //the remainder of `Div`, it has the same sign as the left operand
+    Mod,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    //`And` and `Or` short-circuit: the right operand is only evaluated if it determines the result
+    And,
+    Or,
+}
+
+impl Operation {
+    //every operation, in the order they are declared in
+    pub const ALL: [Operation; 19] = [
+        Operation::Add,
+        Operation::Sub,
+        Operation::Mul,
+        Operation::Div,
+        Operation::Mod,
+        Operation::Pow,
+        Operation::BitAnd,
+        Operation::BitOr,
+        Operation::BitXor,
+        Operation::Shl,
+        Operation::Shr,
+        Operation::Eq,
+        Operation::Ne,
+        Operation::Lt,
+        Operation::Le,
+        Operation::Gt,
+        Operation::Ge,
+        Operation::And,
+        Operation::Or,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operation::Add => "+",
+            Operation::Sub => "-",
+            Operation::Mul => "*",
+            Operation::Div => "/",
+            Operation::Mod => "%",
+            Operation::Pow => "**",
+            Operation::BitAnd => "&",
+            Operation::BitOr => "|",
+            Operation::BitXor => "^",
+            Operation::Shl => "<<",
+            Operation::Shr => ">>",
+            Operation::Eq => "==",
+            Operation::Ne => "!=",
+            Operation::Lt => "<",
+            Operation::Le => "<=",
+            Operation::Gt => ">",
+            Operation::Ge => ">=",
+            Operation::And => "&&",
+            Operation::Or => "||",
+        }
+    }
+
+    //binding strength in infix notation, higher binds tighter
+    //the order is the same as in Rust, `**` binds tighter than all other operators, including the unary ones
+    pub fn precedence(self) -> u8 {
+        match self {
+            Operation::Or => 1,
+            Operation::And => 2,
+            Operation::Eq
+            | Operation::Ne
+            | Operation::Lt
+            | Operation::Le
+            | Operation::Gt
+            | Operation::Ge => 3,
+            Operation::BitOr => 4,
+            Operation::BitXor => 5,
+            Operation::BitAnd => 6,
+            Operation::Shl | Operation::Shr => 7,
+            Operation::Add | Operation::Sub => 8,
+            Operation::Mul | Operation::Div | Operation::Mod => 9,
+            Operation::Pow => 10,
+        }
+    }
+
+    //`==`, `!=`, `<`, `<=`, `>` and `>=`, they yield a boolean
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            Operation::Eq
+                | Operation::Ne
+                | Operation::Lt
+                | Operation::Le
+                | Operation::Gt
+                | Operation::Ge
+        )
+    }
+
+    //`&&` and `||`, they combine booleans
+    pub fn is_logical(self) -> bool {
+        matches!(self, Operation::And | Operation::Or)
+    }
+
+    //all operators except `**` are left-associative, `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+    pub fn is_right_associative(self) -> bool {
+        self == Operation::Pow
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+//a `UnaryOperation` transforms the result of a single subexpression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UnaryOperation {
+    Neg,
+    //logical negation of a boolean
+    Not,
+    BitNot,
+}
+
+impl UnaryOperation {
+    //every unary operation, in the order they are declared in
+    pub const ALL: [UnaryOperation; 3] = [
+        UnaryOperation::Neg,
+        UnaryOperation::Not,
+        UnaryOperation::BitNot,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOperation::Neg => "-",
+            UnaryOperation::Not => "!",
+            UnaryOperation::BitNot => "~",
+        }
+    }
+}
+
+impl fmt::Display for UnaryOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+//an `Expression` is either an operation on one or two subexpressions, a literal value, a variable,
+//a `let` binding which evaluates `body` with `name` bound to the result of `value`,
+//or a conditional which evaluates `then` if the boolean `cond` is true and `otherwise` if it is not
+//the type of the literal values is generic, see `Number` for the supported types
+//info: the size of stack allocatable data structures needs to be known and constant at compile time,
+//so the recursive members are boxed
+//`Expression` implements `Drop` to deallocate deep trees without recursion, so an owned `Expression` cannot be
+//destructured by a `match`, `into_node` takes it apart instead, see `node`
+//`Clone`, `PartialEq`, `Eq` and `Hash` compare the structure of the trees and are available whenever the number type
+//supports them, e.g. trees of `i64` can be used as keys of a `HashMap`, trees of `f64` cannot
+//they work without recursion, like `Drop`, see `node`, while the derived `Debug` recurses and is meant for small trees
+#[derive(Debug)]
+pub enum Expression<N = i64> {
+    Op {
+        op: Operation,
+        left: Box<Expression<N>>,
+        right: Box<Expression<N>>,
+    },
+    Value(N),
+    //`true` or `false`, evaluated to 1 or 0 like the results of comparisons, see `Type`
+    Bool(bool),
+    Var(String),
+    Let {
+        name: String,
+        value: Box<Expression<N>>,
+        body: Box<Expression<N>>,
+    },
+    Unary {
+        op: UnaryOperation,
+        operand: Box<Expression<N>>,
+    },
+    If {
+        cond: Box<Expression<N>>,
+        then: Box<Expression<N>>,
+        otherwise: Box<Expression<N>>,
+    },
+    //defines the function `name`, which can be called in `body` and, recursively, in its own `definition`
+    //like a `fn` item in Rust, the definition does not capture the variables of enclosing `let`s,
+    //it only sees its parameters, the variables of the environment and the functions in scope where it is defined
+    Fn {
+        name: String,
+        params: Vec<String>,
+        definition: Box<Expression<N>>,
+        body: Box<Expression<N>>,
+    },
+    //calls the innermost function `name` defined around the call, or else the built-in function, see `Builtin`
+    Call {
+        name: String,
+        args: Vec<Expression<N>>,
+    },
+}
+
+impl<N> Expression<N> {
+    //converts every literal value with `f`, e.g. `parse("1 / 3")?.map(f64::from_i64)` to evaluate a parsed
+    //expression as floating point numbers
+    //the tree is taken apart and built again with explicit stacks, so arbitrarily deep trees can be converted
+    pub fn map<M>(self, mut f: impl FnMut(N) -> M) -> Expression<M> {
+        enum Task<N> {
+            Map(Expression<N>),
+            //builds the node once its children are converted
+            Build(Shell),
+        }
+        let mut pending = vec![Task::Map(self)];
+        let mut mapped = Vec::new();
+        while let Some(task) = pending.pop() {
+            let e = match task {
+                Task::Map(e) => match e.into_node().split() {
+                    Ok((shell, children)) => {
+                        pending.push(Task::Build(shell));
+                        pending.extend(children.into_iter().rev().map(Task::Map));
+                        continue;
+                    }
+                    Err(Node::Value(value)) => Expression::Value(f(value)),
+                    Err(Node::Bool(value)) => Expression::Bool(value),
+                    Err(Node::Var(name)) => Expression::Var(name),
+                    Err(_) => unreachable!("only leaves are not split"),
+                },
+                Task::Build(shell) => shell.build(&mut mapped),
+            };
+            mapped.push(e);
+        }
+        mapped.pop().expect("the root is converted last")
+    }
+}
+
+//the variables in scope during evaluation
+//bindings are kept in a stack, so a later binding shadows earlier ones with the same name
+#[derive(Debug, Clone)]
+pub struct Environment<N = i64> {
+    bindings: Vec<(String, N)>,
+}
+
+impl<N: Number> Environment<N> {
+    pub fn new() -> Environment<N> {
+        Environment {
+            bindings: Vec::new(),
+        }
+    }
+
+    pub fn bind(&mut self, name: impl Into<String>, value: N) {
+        self.bindings.push((name.into(), value));
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&N> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl<N: Number> Default for Environment<N> {
+    fn default() -> Self {
+        Environment::new()
+    }
+}
+
+//how the arithmetic operations handle results that do not fit in an i64
+//types without a bounded range ignore the mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    //over/underflow is reported as an error
+    #[default]
+    Checked,
+    //results wrap around at the boundaries of i64
+    Wrapping,
+    //results are clamped to `i64::MIN` or `i64::MAX`
+    Saturating,
+}
+
+//the number of nested function calls `EvalOptions::default()` allows
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    pub mode: ArithmeticMode,
+    //calling a function while this many calls are in progress is an error, so endless recursion terminates
+    pub max_call_depth: usize,
+    pub budget: Budget,
+    //cancelling the token, e.g. from another thread, stops the evaluation
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        EvalOptions {
+            mode: ArithmeticMode::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::default(),
+            cancellation: None,
+        }
+    }
+}
+
+//evaluates an expression without any variables in scope
+//This function takes ownership of the given `Expression`
+pub fn eval<N: Number>(e: Expression<N>) -> Result<N, EvalError<N>> {
+    eval_in(e, &mut Environment::new())
+}
+
+//evaluates an expression with the variables bound in `env`, using checked arithmetic
+pub fn eval_in<N: Number>(e: Expression<N>, env: &mut Environment<N>) -> Result<N, EvalError<N>> {
+    eval_with(e, env, &EvalOptions::default())
+}
+
+//evaluates an expression, the operations are applied as defined by the `Number` type
+//errors are reported on division by 0, on operands outside the domain of an operation (e.g. negative exponents),
+//on operations the number type does not support, on variables that are not bound in `env`,
+//on calls of undefined functions or with the wrong number of arguments, on calls nested deeper than
+//`options.max_call_depth`, when `options.budget` is exhausted or `options.cancellation` is cancelled,
+//and, depending on `options.mode`, on integer over/underflow
+//nothing is evaluated if the expression is not well-typed, the first error of `type_check` is reported instead
+//bindings introduced by `let` are only visible in its body, `env` is left as it was when this function returns
+pub fn eval_with<N: Number>(
+    e: Expression<N>,
+    env: &mut Environment<N>,
+    options: &EvalOptions,
+) -> Result<N, EvalError<N>> {
+    eval_ref(&e, env, options)
+}
+
+//evaluates an expression like `eval_with`, but without consuming it,
+//so the same tree can be evaluated again, e.g. with other variables bound in `env`
+pub fn eval_ref<N: Number>(
+    e: &Expression<N>,
+    env: &mut Environment<N>,
+    options: &EvalOptions,
+) -> Result<N, EvalError<N>> {
+    if let Some(error) = type_check(e).errors.into_iter().next() {
+        return Err(error.into());
+    }
+    Evaluator::new(env, options).evaluate(e)
+}
+
+//the state of a single evaluation
+//the evaluation does not recurse, the pending work is kept in `tasks` and the intermediate results in `values`,
+//so arbitrarily deep trees can be evaluated without overflowing the stack
+struct Evaluator<'a, 'e, N> {
+    env: &'a mut Environment<N>,
+    options: &'a EvalOptions,
+    //the steps from the root to the subexpression that is being evaluated, so errors can point at it
+    path: Vec<Step>,
+    //the number of bindings in `env` before the evaluation started, they are visible in every function
+    globals: usize,
+    //the functions defined by the `Fn`s around the subexpression that is being evaluated, and around the calls
+    //that are in progress, like `env` the innermost one comes last
+    functions: Vec<Function<'e, N>>,
+    frame: Frame,
+    //the number of calls in progress
+    depth: usize,
+    //the number of subexpressions evaluated so far, and the depth of the current one, for the budget
+    visited: usize,
+    nesting: usize,
+    //the values of the subexpressions outside of function calls, with their paths, in the order they were computed,
+    //only collected for `trace`
+    reductions: Option<Vec<(Vec<Step>, N)>>,
+}
+
+//a function that is in scope
+struct Function<'e, N> {
+    name: &'e str,
+    params: &'e [String],
+    definition: &'e Expression<N>,
+    //the path of the definition, errors in the function point into it
+    path: Vec<Step>,
+    //the ranges of `functions` the definition sees: the functions in scope where it is defined, including itself
+    scope: Vec<Range<usize>>,
+}
+
+//the part of `env` and `functions` that belongs to the innermost function call that is in progress,
+//or to the expression itself if there is none
+struct Frame {
+    //the bindings from this index on are visible, below it only the globals are
+    bindings: usize,
+    //the functions from this index on are visible, and the ones in `scope`
+    functions: usize,
+    scope: Vec<Range<usize>>,
+}
+
+//a pending piece of work of the `Evaluator`, handled in last-in first-out order
+enum Task<'e, N> {
+    //evaluates the subexpression reached from the current one by the step, and pushes its value
+    Within(Step, &'e Expression<N>),
+    //returns to the parent subexpression
+    Leave,
+    Evaluate(&'e Expression<N>),
+    //pops the right and left operand and pushes the result
+    Apply(Operation),
+    ApplyUnary(UnaryOperation),
+    //pops the left operand of `&&` or `||`, and evaluates the right one only if it is needed
+    ShortCircuit(Operation, &'e Expression<N>),
+    //pops the condition and evaluates one of the branches
+    Branch(&'e Expression<N>, &'e Expression<N>),
+    //pops the value of a `let`, binds it and evaluates the body
+    Bind(&'e str, &'e Expression<N>),
+    //removes the bindings made in the body of a `let`
+    Unbind(usize),
+    //removes the functions defined in the body of a `Fn`
+    Undefine(usize),
+    //pops the arguments and calls the function
+    Call(&'e str, usize),
+    //ends the innermost function call, restoring the frame and path of the caller
+    Return(Frame, Vec<Step>),
+    //records the value on top of the stack as the value of the current subexpression
+    Reduced,
+}
+
+impl<'a, 'e, N: Number> Evaluator<'a, 'e, N> {
+    fn new(env: &'a mut Environment<N>, options: &'a EvalOptions) -> Evaluator<'a, 'e, N> {
+        let globals = env.bindings.len();
+        Evaluator {
+            env,
+            options,
+            path: Vec::new(),
+            globals,
+            functions: Vec::new(),
+            frame: Frame {
+                bindings: globals,
+                functions: 0,
+                scope: Vec::new(),
+            },
+            depth: 0,
+            visited: 0,
+            nesting: 0,
+            reductions: None,
+        }
+    }
+
+    fn evaluate(&mut self, e: &'e Expression<N>) -> Result<N, EvalError<N>> {
+        let scope = self.env.bindings.len();
+        let result = self.run(vec![Task::Evaluate(e)]);
+        self.env.bindings.truncate(scope);
+        result
+    }
+
+    fn run(&mut self, mut tasks: Vec<Task<'e, N>>) -> Result<N, EvalError<N>> {
+        let mut values = Vec::new();
+        //a task never pops more values than the tasks before it pushed
+        let pop = |values: &mut Vec<N>| values.pop().expect("missing intermediate value");
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Within(step, e) => {
+                    self.enter(step)?;
+                    tasks.extend([Task::Leave, Task::Evaluate(e)]);
+                }
+                Task::Leave => self.leave(),
+                Task::Evaluate(e) => {
+                    self.visit()?;
+                    //outside of function calls every subexpression but a literal is reduced, a call is reduced as a whole
+                    let literal = matches!(e, Expression::Value(_) | Expression::Bool(_));
+                    if self.reductions.is_some() && self.depth == 0 && !literal {
+                        tasks.push(Task::Reduced);
+                    }
+                    match e {
+                        Expression::Value(value) => values.push(value.clone()),
+                        Expression::Bool(value) => values.push(N::from_bool(*value)),
+                        Expression::Var(name) => match self.lookup(name) {
+                            Some(value) => values.push(value.clone()),
+                            None => {
+                                let kind = EvalErrorKind::UnboundVariable(name.clone());
+                                return Err(self.error(kind));
+                            }
+                        },
+                        Expression::Let { name, value, body } => {
+                            tasks.extend([Task::Bind(name, body), Task::Within(Step::Value, value)])
+                        }
+                        Expression::Op {
+                            op: op @ (Operation::And | Operation::Or),
+                            left,
+                            right,
+                        } => tasks.extend([
+                            Task::ShortCircuit(*op, right),
+                            Task::Within(Step::Left, left),
+                        ]),
+                        Expression::Op { op, left, right } => tasks.extend([
+                            Task::Apply(*op),
+                            Task::Within(Step::Right, right),
+                            Task::Within(Step::Left, left),
+                        ]),
+                        Expression::Unary { op, operand } => tasks
+                            .extend([Task::ApplyUnary(*op), Task::Within(Step::Operand, operand)]),
+                        Expression::If {
+                            cond,
+                            then,
+                            otherwise,
+                        } => tasks.extend([
+                            Task::Branch(then, otherwise),
+                            Task::Within(Step::Cond, cond),
+                        ]),
+                        Expression::Fn {
+                            name,
+                            params,
+                            definition,
+                            body,
+                        } => {
+                            let defined = self.define(name, params, definition);
+                            tasks.extend([Task::Undefine(defined), Task::Within(Step::Body, body)]);
+                        }
+                        Expression::Call { name, args } => {
+                            tasks.push(Task::Call(name, args.len()));
+                            tasks.extend(
+                                args.iter()
+                                    .enumerate()
+                                    .rev()
+                                    .map(|(i, arg)| Task::Within(Step::Arg(i), arg)),
+                            );
+                        }
+                    }
+                }
+                Task::Apply(op) => {
+                    let right = pop(&mut values);
+                    let left = pop(&mut values);
+                    let result = N::apply(op, &left, &right, self.options.mode)
+                        .map_err(|kind| self.error(kind))?;
+                    values.push(result);
+                }
+                Task::ApplyUnary(op) => {
+                    let operand = pop(&mut values);
+                    let result = N::apply_unary(op, &operand, self.options.mode)
+                        .map_err(|kind| self.error(kind))?;
+                    values.push(result);
+                }
+                Task::ShortCircuit(op, right) => {
+                    let left = pop(&mut values);
+                    match op {
+                        Operation::And if !left.is_true() => values.push(N::from_bool(false)),
+                        Operation::Or if left.is_true() => values.push(N::from_bool(true)),
+                        _ => {
+                            values.push(left);
+                            tasks.extend([Task::Apply(op), Task::Within(Step::Right, right)]);
+                        }
+                    }
+                }
+                Task::Branch(then, otherwise) => {
+                    if pop(&mut values).is_true() {
+                        tasks.push(Task::Within(Step::Then, then));
+                    } else {
+                        tasks.push(Task::Within(Step::Else, otherwise));
+                    }
+                }
+                Task::Bind(name, body) => {
+                    let scope = self.env.bindings.len();
+                    self.env.bind(name, pop(&mut values));
+                    tasks.extend([Task::Unbind(scope), Task::Within(Step::Body, body)]);
+                }
+                Task::Unbind(scope) => self.env.bindings.truncate(scope),
+                Task::Undefine(defined) => self.functions.truncate(defined),
+                Task::Call(name, count) => {
+                    let args = values.split_off(values.len() - count);
+                    if let Some(result) = self.call(name, args, &mut tasks)? {
+                        values.push(result);
+                    }
+                }
+                Task::Return(frame, path) => {
+                    let callee = mem::replace(&mut self.frame, frame);
+                    self.env.bindings.truncate(callee.bindings);
+                    self.functions.truncate(callee.functions);
+                    self.path = path;
+                    self.depth -= 1;
+                    self.nesting -= 1;
+                }
+                Task::Reduced => {
+                    let value = values.last().expect("missing intermediate value");
+                    if let Some(reductions) = &mut self.reductions {
+                        reductions.push((self.path.clone(), value.clone()));
+                    }
+                }
+            }
+        }
+        Ok(pop(&mut values))
+    }
+
+    //the innermost visible binding of a variable
+    fn lookup(&self, name: &str) -> Option<&N> {
+        let bindings = &self.env.bindings;
+        bindings[self.frame.bindings..]
+            .iter()
+            .rev()
+            .chain(bindings[..self.globals].iter().rev())
+            .find(|(bound, _)| bound == name)
+            .map(|(_, value)| value)
+    }
+
+    //adds a function to the scope, returns the number of functions before it, to remove it again
+    fn define(
+        &mut self,
+        name: &'e str,
+        params: &'e [String],
+        definition: &'e Expression<N>,
+    ) -> usize {
+        let defined = self.functions.len();
+        let mut scope = self.frame.scope.clone();
+        scope.push(self.frame.functions..defined + 1);
+        self.functions.push(Function {
+            name,
+            params,
+            definition,
+            path: self.path.clone(),
+            scope,
+        });
+        defined
+    }
+
+    //calls the innermost visible function `name`, or else the built-in function
+    //a built-in function returns its result right away, a defined function pushes the tasks that evaluate it
+    fn call(
+        &mut self,
+        name: &str,
+        args: Vec<N>,
+        tasks: &mut Vec<Task<'e, N>>,
+    ) -> Result<Option<N>, EvalError<N>> {
+        let visible = iter::once(self.frame.functions..self.functions.len())
+            .chain(self.frame.scope.iter().rev().cloned())
+            .flat_map(|range| range.rev())
+            .find(|&i| self.functions[i].name == name);
+        let Some(index) = visible else {
+            let Some(builtin) = Builtin::from_name(name) else {
+                return Err(self.error(EvalErrorKind::UndefinedFunction(name.to_string())));
+            };
+            self.check_arity(name, builtin.arity(), args.len())?;
+            return builtin
+                .apply(&args, self.options.mode)
+                .map(Some)
+                .map_err(|kind| self.error(kind));
+        };
+        self.check_arity(name, self.functions[index].params.len(), args.len())?;
+        if self.depth == self.options.max_call_depth {
+            return Err(self.error(EvalErrorKind::RecursionLimit(self.options.max_call_depth)));
+        }
+        self.nest()?;
+        self.depth += 1;
+        let function = &self.functions[index];
+        let frame = Frame {
+            bindings: self.env.bindings.len(),
+            functions: self.functions.len(),
+            scope: function.scope.clone(),
+        };
+        let mut path = function.path.clone();
+        path.push(Step::Definition);
+        for (param, arg) in function.params.iter().zip(args) {
+            self.env.bind(param.as_str(), arg);
+        }
+        tasks.extend([
+            Task::Return(
+                mem::replace(&mut self.frame, frame),
+                mem::replace(&mut self.path, path),
+            ),
+            Task::Evaluate(function.definition),
+        ]);
+        Ok(None)
+    }
+
+    //counts a subexpression against the budget, and stops if the evaluation was cancelled
+    fn visit(&mut self) -> Result<(), EvalError<N>> {
+        self.visited += 1;
+        match self.options.budget.max_nodes {
+            Some(max) if self.visited > max => {
+                Err(self.error(EvalErrorKind::BudgetExhausted(Limit::Nodes(max))))
+            }
+            _ if self
+                .options
+                .cancellation
+                .as_ref()
+                .is_some_and(|token| token.is_cancelled()) =>
+            {
+                Err(self.error(EvalErrorKind::Cancelled))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    //moves to a subexpression of the current one
+    fn enter(&mut self, step: Step) -> Result<(), EvalError<N>> {
+        self.path.push(step);
+        self.nest()
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+        self.nesting -= 1;
+    }
+
+    fn nest(&mut self) -> Result<(), EvalError<N>> {
+        self.nesting += 1;
+        match self.options.budget.max_depth {
+            Some(max) if self.nesting > max => {
+                Err(self.error(EvalErrorKind::BudgetExhausted(Limit::Depth(max))))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_arity(&self, name: &str, expected: usize, found: usize) -> Result<(), EvalError<N>> {
+        if expected == found {
+            return Ok(());
+        }
+        Err(self.error(EvalErrorKind::WrongArity {
+            name: name.to_string(),
+            expected,
+            found,
+        }))
+    }
+
+    fn error(&self, kind: EvalErrorKind<N>) -> EvalError<N> {
+        EvalError {
+            kind,
+            path: Path(self.path.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        eval, eval_in, eval_ref, eval_with, ArithmeticMode, Budget, CancellationToken, Environment,
+        EvalError, EvalErrorKind, EvalOptions, Expression, Limit, Operation, Path, Step, Type,
+        TypeErrorKind, UnaryOperation,
+    };
+    use std::thread;
+    use std::time::Duration;
+
+    fn op(op: Operation, left: Expression, right: Expression) -> Expression {
+        Expression::Op {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval(Expression::Value(19)), Ok(19));
+        let expr = op(
+            Operation::Add,
+            op(Operation::Mul, Expression::Value(10), Expression::Value(9)),
+            op(
+                Operation::Mul,
+                Expression::Value(5),
+                op(Operation::Sub, Expression::Value(3), Expression::Value(4)),
+            ),
+        );
+        assert_eq!(eval(expr), Ok(85));
+    }
+
+    fn error(kind: EvalErrorKind, path: &[Step]) -> EvalError {
+        EvalError {
+            kind,
+            path: Path(path.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let expr = op(Operation::Div, Expression::Value(99), Expression::Value(0));
+        assert_eq!(eval(expr), Err(error(EvalErrorKind::DivisionByZero, &[])));
+        let expr = op(
+            Operation::Add,
+            Expression::Value(i64::MAX),
+            Expression::Value(1),
+        );
+        assert_eq!(
+            eval(expr),
+            Err(error(
+                EvalErrorKind::Overflow {
+                    op: Operation::Add,
+                    left: i64::MAX,
+                    right: 1
+                },
+                &[]
+            ))
+        );
+        let expr = op(
+            Operation::Div,
+            Expression::Value(i64::MIN),
+            Expression::Value(-1),
+        );
+        assert_eq!(
+            eval(expr).unwrap_err().to_string(),
+            "integer overflow in `-9223372036854775808 / -1`"
+        );
+    }
+
+    #[test]
+    fn test_error_path() {
+        //(10 * 9) + (5 * (3 / 0))
+        let expr = op(
+            Operation::Add,
+            op(Operation::Mul, Expression::Value(10), Expression::Value(9)),
+            op(
+                Operation::Mul,
+                Expression::Value(5),
+                op(Operation::Div, Expression::Value(3), Expression::Value(0)),
+            ),
+        );
+        let failing = op(Operation::Div, Expression::Value(3), Expression::Value(0));
+        let path = Path(vec![Step::Right, Step::Right]);
+        assert_eq!(expr.at(&path), Some(&failing));
+        let result = eval(expr);
+        assert_eq!(
+            result,
+            Err(error(EvalErrorKind::DivisionByZero, path.steps()))
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "division by zero at right.right"
+        );
+    }
+
+    fn let_(name: &str, value: Expression, body: Expression) -> Expression {
+        Expression::Let {
+            name: name.to_string(),
+            value: Box::new(value),
+            body: Box::new(body),
+        }
+    }
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(name.to_string())
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            eval(var("x")),
+            Err(error(
+                EvalErrorKind::UnboundVariable(String::from("x")),
+                &[]
+            ))
+        );
+
+        let mut env = Environment::new();
+        env.bind("x", 2);
+        let expr = op(Operation::Mul, var("x"), Expression::Value(21));
+        assert_eq!(eval_in(expr, &mut env), Ok(42));
+    }
+
+    #[test]
+    fn test_let_scoping() {
+        //let x = 1 in (let x = x + 10 in x) + x
+        let expr = let_(
+            "x",
+            Expression::Value(1),
+            op(
+                Operation::Add,
+                let_(
+                    "x",
+                    op(Operation::Add, var("x"), Expression::Value(10)),
+                    var("x"),
+                ),
+                var("x"),
+            ),
+        );
+        assert_eq!(eval(expr), Ok(12));
+
+        //(let y = 1 in y) + y
+        let mut env = Environment::new();
+        let expr = op(
+            Operation::Add,
+            let_("y", Expression::Value(1), var("y")),
+            var("y"),
+        );
+        assert_eq!(
+            eval_in(expr, &mut env),
+            Err(error(
+                EvalErrorKind::UnboundVariable(String::from("y")),
+                &[Step::Right]
+            ))
+        );
+        assert_eq!(env.lookup("y"), None);
+    }
+
+    #[test]
+    fn test_eval_with_mode() {
+        //(i64::MAX + 1) - 1
+        let expr = || {
+            op(
+                Operation::Sub,
+                op(
+                    Operation::Add,
+                    Expression::Value(i64::MAX),
+                    Expression::Value(1),
+                ),
+                Expression::Value(1),
+            )
+        };
+        let options = |mode| EvalOptions {
+            mode,
+            ..EvalOptions::default()
+        };
+        let mut env = Environment::new();
+        assert!(eval_with(expr(), &mut env, &options(ArithmeticMode::Checked)).is_err());
+        assert_eq!(
+            eval_with(expr(), &mut env, &options(ArithmeticMode::Wrapping)),
+            Ok(i64::MAX)
+        );
+        assert_eq!(
+            eval_with(expr(), &mut env, &options(ArithmeticMode::Saturating)),
+            Ok(i64::MAX - 1)
+        );
+    }
+
+    #[test]
+    fn test_if_and_short_circuit() {
+        let division_by_zero = || op(Operation::Div, Expression::Value(1), Expression::Value(0));
+        let expr = op(
+            Operation::And,
+            Expression::Bool(false),
+            op(Operation::Eq, division_by_zero(), Expression::Value(0)),
+        );
+        assert_eq!(eval(expr), Ok(0));
+        let expr = op(
+            Operation::Or,
+            Expression::Bool(true),
+            op(Operation::Eq, division_by_zero(), Expression::Value(0)),
+        );
+        assert_eq!(eval(expr), Ok(1));
+
+        let expr = Expression::If {
+            cond: Box::new(op(
+                Operation::Lt,
+                Expression::Value(1),
+                Expression::Value(2),
+            )),
+            then: Box::new(Expression::Unary {
+                op: UnaryOperation::Neg,
+                operand: Box::new(division_by_zero()),
+            }),
+            otherwise: Box::new(division_by_zero()),
+        };
+        assert_eq!(
+            eval(expr),
+            Err(error(
+                EvalErrorKind::DivisionByZero,
+                &[Step::Then, Step::Operand]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_type_errors() {
+        //the division is not evaluated, the tree is rejected before
+        let expr = op(
+            Operation::Add,
+            op(Operation::Div, Expression::Value(1), Expression::Value(0)),
+            Expression::Bool(true),
+        );
+        assert_eq!(
+            eval(expr),
+            Err(error(
+                EvalErrorKind::Type(TypeErrorKind::Mismatch {
+                    expected: Type::Number,
+                    found: Type::Bool,
+                }),
+                &[Step::Right]
+            ))
+        );
+        let expr = crate::parse("if x then 1 else 2").unwrap();
+        assert_eq!(
+            eval(expr).unwrap_err().to_string(),
+            "type error: expected a boolean, found a number at cond"
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        //((((0 + 1) + 2) + ...) + 999999), leaning to the left
+        let mut expr = Expression::Value(0);
+        for i in 1..1_000_000 {
+            expr = op(Operation::Add, expr, Expression::Value(i));
+        }
+        assert_eq!(eval(expr), Ok(499_999_500_000));
+
+        let mut expr = var("x");
+        for _ in 0..1_000_000 {
+            expr = let_(
+                "x",
+                op(Operation::Sub, var("x"), Expression::Value(1)),
+                expr,
+            );
+        }
+        let mut env = Environment::new();
+        env.bind("x", 0);
+        assert_eq!(eval_in(expr, &mut env), Ok(-1_000_000));
+        assert_eq!(env.lookup("x"), Some(&0));
+    }
+
+    #[test]
+    fn test_map() {
+        let input = "fn f(a, b) = a ** b; let x = 1 in if !(x < 2) then f(x, 3) else -(x / 2)";
+        let expected = crate::parse(input).unwrap();
+        let e = expected.clone().map(|n| n as f64 / 2.0);
+        assert_eq!(
+            e.to_string(),
+            "fn f(a, b) = a ** b; let x = 0.5 in if !(x < 1) then f(x, 1.5) else -(x / 1)"
+        );
+        assert_eq!(e.map(|n| (n * 2.0) as i64), expected);
+
+        let mut expr = Expression::Value(0);
+        for i in 1..1_000_000 {
+            expr = op(Operation::Add, expr, Expression::Value(i));
+        }
+        assert_eq!(
+            eval(expr.map(num_bigint::BigInt::from)),
+            Ok(num_bigint::BigInt::from(499_999_500_000i64))
+        );
+    }
+
+    #[test]
+    fn test_eval_ref() {
+        //let y = x * x in y - x
+        let expr = let_(
+            "y",
+            op(Operation::Mul, var("x"), var("x")),
+            op(Operation::Sub, var("y"), var("x")),
+        );
+        let options = EvalOptions::default();
+        let mut env = Environment::new();
+        for (x, expected) in [(0, 0), (3, 6), (-4, 20)] {
+            env.bind("x", x);
+            assert_eq!(eval_ref(&expr, &mut env, &options), Ok(expected));
+        }
+        assert_eq!(env.lookup("y"), None);
+        env.bind("x", i64::MAX);
+        assert_eq!(
+            eval_ref(&expr, &mut env, &options),
+            eval_with(expr.clone(), &mut env, &options)
+        );
+    }
+
+    fn evaluated(input: &str) -> Result<i64, EvalError> {
+        let mut env = Environment::new();
+        env.bind("g", 100);
+        eval_in(crate::parse(input).unwrap(), &mut env)
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(evaluated("fn sq(x) = x * x; sq(3) + sq(4)"), Ok(25));
+        assert_eq!(
+            evaluated("fn fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2); fib(20)"),
+            Ok(6765)
+        );
+        assert_eq!(
+            evaluated("abs(-3) + min(4, -5) + max(4, -5) + gcd(-12, 18)"),
+            Ok(8)
+        );
+        //a definition shadows the built-in function, and the outer definition
+        assert_eq!(
+            evaluated("fn abs(x) = x; fn f() = 1; abs(-1) + (fn f() = 2; f()) + f()"),
+            Ok(2)
+        );
+        assert_eq!(
+            evaluated("fn f(x, y) = x; 1 + f(1)"),
+            Err(error(
+                EvalErrorKind::WrongArity {
+                    name: String::from("f"),
+                    expected: 2,
+                    found: 1
+                },
+                &[Step::Body, Step::Right]
+            ))
+        );
+        assert_eq!(
+            evaluated("max(1)").unwrap_err().to_string(),
+            "`max` takes 2 arguments but 1 was given"
+        );
+        assert_eq!(
+            evaluated("sq(1 / 0)"),
+            Err(error(EvalErrorKind::DivisionByZero, &[Step::Arg(0)]))
+        );
+        assert_eq!(
+            evaluated("sq(2)").unwrap_err().to_string(),
+            "undefined function `sq`"
+        );
+    }
+
+    #[test]
+    fn test_function_scoping() {
+        //the definition sees the variables of the environment, but not the ones bound by `let`
+        assert_eq!(evaluated("fn f(x) = x + g; f(1)"), Ok(101));
+        assert_eq!(
+            evaluated("let y = 1 in fn f(x) = x + y; f(1)"),
+            Err(error(
+                EvalErrorKind::UnboundVariable(String::from("y")),
+                &[Step::Body, Step::Definition, Step::Right]
+            ))
+        );
+        //nor the variables of the caller
+        assert!(evaluated("fn f() = x; let x = 1 in f()").is_err());
+        //the functions in scope are the ones around the definition, not the ones around the call
+        assert_eq!(
+            evaluated("fn f() = 1; fn g() = f(); fn f() = 2; g() * 10 + f()"),
+            Ok(12)
+        );
+        assert!(evaluated("fn f() = fn h() = 1; 0; fn g() = h(); f() + g()").is_err());
+        assert_eq!(
+            evaluated("fn f(n) = fn g(m) = m + n; g(1); f(2)")
+                .unwrap_err()
+                .kind,
+            EvalErrorKind::UnboundVariable(String::from("n"))
+        );
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let sum = |n| format!("fn sum(n) = if n > 0 then n + sum(n - 1) else 0; sum({n})");
+        let limit = super::DEFAULT_MAX_CALL_DEPTH as i64;
+        assert_eq!(evaluated(&sum(limit - 1)), Ok(limit * (limit - 1) / 2));
+        assert_eq!(
+            evaluated(&sum(limit)),
+            Err(error(
+                EvalErrorKind::RecursionLimit(super::DEFAULT_MAX_CALL_DEPTH),
+                &[Step::Definition, Step::Then, Step::Right]
+            ))
+        );
+        let options = EvalOptions {
+            max_call_depth: 3,
+            ..EvalOptions::default()
+        };
+        let expr = crate::parse(&sum(3)).unwrap();
+        assert_eq!(
+            eval_with(expr, &mut Environment::new(), &options)
+                .unwrap_err()
+                .to_string(),
+            "more than 3 nested function calls at definition.then.right"
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let options = |max_nodes, max_depth| EvalOptions {
+            budget: Budget {
+                max_nodes,
+                max_depth,
+            },
+            ..EvalOptions::default()
+        };
+        let evaluate = |input: &str, options: &EvalOptions| {
+            eval_with(
+                crate::parse(input).unwrap(),
+                &mut Environment::new(),
+                options,
+            )
+        };
+        //5 subexpressions, nested 2 deep
+        assert_eq!(evaluate("1 + 2 * 3", &options(Some(5), Some(2))), Ok(7));
+        assert_eq!(
+            evaluate("1 + 2 * 3", &options(Some(4), None)),
+            Err(error(
+                EvalErrorKind::BudgetExhausted(Limit::Nodes(4)),
+                &[Step::Right, Step::Right]
+            ))
+        );
+        assert_eq!(
+            evaluate("1 + 2 * 3", &options(None, Some(1))),
+            Err(error(
+                EvalErrorKind::BudgetExhausted(Limit::Depth(1)),
+                &[Step::Right, Step::Left]
+            ))
+        );
+        assert_eq!(
+            evaluate("1 + 2 * 3", &options(Some(4), None))
+                .unwrap_err()
+                .to_string(),
+            "budget exhausted: more than 4 subexpressions evaluated at right.right"
+        );
+        //every call evaluates the definition again, nested one level deeper than the call
+        let countdown = "fn f(n) = if n > 0 then f(n - 1) else 0; f(100)";
+        assert_eq!(evaluate(countdown, &options(None, None)), Ok(0));
+        assert_eq!(
+            evaluate(countdown, &options(None, Some(50))).map_err(|error| error.kind),
+            Err(EvalErrorKind::BudgetExhausted(Limit::Depth(50)))
+        );
+        let exponential = "fn f(n) = if n > 0 then f(n - 1) + f(n - 1) else 1; f(60)";
+        assert_eq!(
+            evaluate(exponential, &options(Some(10_000), None)).map_err(|error| error.kind),
+            Err(EvalErrorKind::BudgetExhausted(Limit::Nodes(10_000)))
+        );
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let options = EvalOptions {
+            cancellation: Some(token.clone()),
+            ..EvalOptions::default()
+        };
+        let one_plus_two = || op(Operation::Add, Expression::Value(1), Expression::Value(2));
+        assert_eq!(
+            eval_with(one_plus_two(), &mut Environment::new(), &options),
+            Ok(3)
+        );
+        token.cancel();
+        assert_eq!(
+            eval_with(one_plus_two(), &mut Environment::new(), &options),
+            Err(error(EvalErrorKind::Cancelled, &[]))
+        );
+
+        //an evaluation that would not end in a lifetime, cancelled from another thread
+        let token = CancellationToken::new();
+        let options = EvalOptions {
+            cancellation: Some(token.clone()),
+            ..EvalOptions::default()
+        };
+        let exponential = crate::parse("fn f(n) = if n > 0 then f(n - 1) + f(n - 1) else 1; f(60)");
+        let evaluation = thread::spawn(move || {
+            eval_with(exponential.unwrap(), &mut Environment::new(), &options)
+        });
+        thread::sleep(Duration::from_millis(10));
+        token.cancel();
+        assert_eq!(
+            evaluation.join().unwrap().unwrap_err().kind,
+            EvalErrorKind::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let expr = op(Operation::Add, var("x"), Expression::Value(1));
+        assert_eq!(expr.clone(), expr);
+        assert_ne!(expr, op(Operation::Add, Expression::Value(1), var("x")));
+
+        //results cached by the structure of the expression
+        let mut cache = std::collections::HashMap::new();
+        let mut env = Environment::new();
+        env.bind("x", 41);
+        for _ in 0..2 {
+            if !cache.contains_key(&expr) {
+                let result = eval_ref(&expr, &mut env, &EvalOptions::default());
+                cache.insert(expr.clone(), result);
+            }
+        }
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            cache[&op(Operation::Add, var("x"), Expression::Value(1))],
+            Ok(42)
+        );
+    }
+}
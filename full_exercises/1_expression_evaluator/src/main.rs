@@ -1,51 +1,77 @@
-//TODO define an enum `Operation` with four possible operations: `Add`, `Sub`, `Mul`, `Div` on two subexpressions
-
-//an `Expression` is either an operation on two subexpressions or a literal value
-//info: remember that enum variants can contain data: `Op` has named fields (like a struct) and `Value` includes an unnamed signed integer
-enum Expression {
-    Op {
-        op: Operation,
-        //info: the size of stack allocatable data structures needs to be known and constant at compile time
-        //However, these `left` and `right` members make `Expression` a recursive type which could have an infinite size 
-        //TODO fix this issue
-        left: Expression,
-        right: Expression,
-    },
-    Value(i64)
-}
+//the program has two modes you choose using the first argument to the program:
+// - no argument: evaluates some example expressions
+// - `repl`: starts an interactive read-eval-print loop (run with `cargo run -- repl`)
 
+mod repl;
 
-//TODO implement the evaluation of an expression (use pattern matching with the `match` keyword)
-//Use integer divition for the `Div` operation
-//The return type is a `Result`, which, in this case, is either an i64 on success, or a String with the error message on failure
-//Look for techniques to make this function as short as possible, for example: use only a single `match` keyword (look at the pattern and destructuring syntax), and propagate errors to the caller
-//This function takes ownership of the given `Expression`, DO NOT change this
-//Errors can occur, for example, when dividing by 0
-//Bonus TODO: return an appropriate error message on integer over/underflow
-fn eval(e: Expression) -> Result<i64, String> {
-   
-}
+use std::io;
+
+use expression_evaluator::{eval, parse, Expression, Operation};
 
 pub fn main() {
-    //TODO create an expression with literal value 19
-    let expr1 = 
-    assert_eq!(eval(expr1), ???);
-    
+    match std::env::args().nth(1).as_deref() {
+        None => examples(),
+        Some("repl") => {
+            if let Err(error) = repl::run(io::stdin().lock(), io::stdout().lock()) {
+                eprintln!("error: {error}");
+            }
+        }
+        Some(mode) => eprintln!("unknown mode `{mode}`, expected no argument or `repl`"),
+    }
+}
+
+fn examples() {
+    let expr1 = Expression::Value(19);
+    assert_eq!(eval(expr1), Ok(19));
+
     let expr2 = Expression::Op {
         op: Operation::Add,
-        left: Expression::Value(10),
-        right: Expression::Value(20),
+        left: Box::new(Expression::Value(10)),
+        right: Box::new(Expression::Value(20)),
     };
-    //TODO print the result of expr2 only if there were no errors
-    //hint: have a look at the `if-let` syntax
+    if let Ok(result) = eval(expr2) {
+        println!("expr2: {result}");
+    }
 
     let expr3 = Expression::Op {
         op: Operation::Div,
-        left: Expression::Value(99),
-        right: Expression::Value(0),
+        left: Box::new(Expression::Value(99)),
+        right: Box::new(Expression::Value(0)),
+    };
+    match eval(expr3) {
+        Ok(result) => println!("expr3: {result}"),
+        Err(message) => println!("expr3: error: {message}"),
+    }
+
+    //(10 * 9) + (5 * (3 - 4))
+    let expr4 = Expression::Op {
+        op: Operation::Add,
+        left: Box::new(Expression::Op {
+            op: Operation::Mul,
+            left: Box::new(Expression::Value(10)),
+            right: Box::new(Expression::Value(9)),
+        }),
+        right: Box::new(Expression::Op {
+            op: Operation::Mul,
+            left: Box::new(Expression::Value(5)),
+            right: Box::new(Expression::Op {
+                op: Operation::Sub,
+                left: Box::new(Expression::Value(3)),
+                right: Box::new(Expression::Value(4)),
+            }),
+        }),
     };
-    //TODO evaluate expr3 and print the result or the error message
+    println!("expr4: {:?}", eval(expr4));
 
-    //TODO create, evaluate, and print the expression `(10 * 9) + (5 * (3 - 4))`
+    //the same expression, parsed from its infix notation
+    match parse("(10 * 9) + (5 * (3 - 4))") {
+        Ok(expr5) => println!("expr5: {:?}", eval(expr5)),
+        Err(error) => println!("expr5: parse error: {error}"),
+    }
 
+    //variables are bound with `let`, inner bindings shadow outer ones
+    match parse("let x = 3 in (let x = x * x in x) + x") {
+        Ok(expr6) => println!("expr6: {:?}", eval(expr6)),
+        Err(error) => println!("expr6: parse error: {error}"),
+    }
 }
@@ -0,0 +1,584 @@
+//dropping an `Expression` without recursion, so arbitrarily deep trees can be deallocated
+//the default `Drop` of a boxed tree recurses into every child, which overflows the stack for deep trees,
+//e.g. a chain of a million additions
+//for the same reason `Clone`, `PartialEq` and `Hash` are implemented with an explicit stack instead of being derived
+//because `Expression` implements `Drop`, its fields cannot be moved out by a `match` (error E0509), neither in this crate
+//nor by its users, `into_node` converts an `Expression` into a `Node`, which has the same variants and no `Drop`,
+//so it can be destructured
+//matching on a reference, e.g. `match &e`, is not affected, and `Node` converts back with `Expression::from`
+
+use std::hash::{Hash, Hasher};
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+
+use crate::{Expression, Operation, UnaryOperation};
+
+//the contents of a single `Expression`, see the variants of `Expression`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node<N = i64> {
+    Op {
+        op: Operation,
+        left: Box<Expression<N>>,
+        right: Box<Expression<N>>,
+    },
+    Value(N),
+    Bool(bool),
+    Var(String),
+    Let {
+        name: String,
+        value: Box<Expression<N>>,
+        body: Box<Expression<N>>,
+    },
+    Unary {
+        op: UnaryOperation,
+        operand: Box<Expression<N>>,
+    },
+    If {
+        cond: Box<Expression<N>>,
+        then: Box<Expression<N>>,
+        otherwise: Box<Expression<N>>,
+    },
+    Fn {
+        name: String,
+        params: Vec<String>,
+        definition: Box<Expression<N>>,
+        body: Box<Expression<N>>,
+    },
+    Call {
+        name: String,
+        args: Vec<Expression<N>>,
+    },
+}
+
+impl<N> Expression<N> {
+    //takes the expression apart, e.g. `match e.into_node() { Node::Op { op, left, right } => ... }`
+    pub fn into_node(self) -> Node<N> {
+        let e = ManuallyDrop::new(self);
+        //SAFETY: moving the fields out with `ptr::read` is sound because:
+        // - the references come from `e`, which is alive until the end of the function, so every field that is read
+        //   is initialized, aligned and valid for reads
+        // - `e` is wrapped in `ManuallyDrop`, so neither `Drop for Expression` nor the drop glue of its fields runs,
+        //   and after the `match` `e` is never used again, so the node becomes the only owner of every field
+        // - every arm reads every field of its variant exactly once (the `Copy` fields `op` and the boolean are copied),
+        //   so nothing is owned twice, which would be a double free, and nothing is left behind, which would be a leak
+        // - nothing between the reads can panic, so an unwind cannot observe the fields owned by both `e` and the node,
+        //   and even then `e` would not be dropped
+        //the `match` is exhaustive, so a new variant of `Expression` cannot be added without handling it here
+        unsafe {
+            match &*e {
+                Expression::Op { op, left, right } => Node::Op {
+                    op: *op,
+                    left: ptr::read(left),
+                    right: ptr::read(right),
+                },
+                Expression::Value(value) => Node::Value(ptr::read(value)),
+                Expression::Bool(value) => Node::Bool(*value),
+                Expression::Var(name) => Node::Var(ptr::read(name)),
+                Expression::Let { name, value, body } => Node::Let {
+                    name: ptr::read(name),
+                    value: ptr::read(value),
+                    body: ptr::read(body),
+                },
+                Expression::Unary { op, operand } => Node::Unary {
+                    op: *op,
+                    operand: ptr::read(operand),
+                },
+                Expression::If {
+                    cond,
+                    then,
+                    otherwise,
+                } => Node::If {
+                    cond: ptr::read(cond),
+                    then: ptr::read(then),
+                    otherwise: ptr::read(otherwise),
+                },
+                Expression::Fn {
+                    name,
+                    params,
+                    definition,
+                    body,
+                } => Node::Fn {
+                    name: ptr::read(name),
+                    params: ptr::read(params),
+                    definition: ptr::read(definition),
+                    body: ptr::read(body),
+                },
+                Expression::Call { name, args } => Node::Call {
+                    name: ptr::read(name),
+                    args: ptr::read(args),
+                },
+            }
+        }
+    }
+
+    //moves the children that have children of their own to `detached`, leaving leaves in their place
+    //replacing the contents instead of the boxes avoids allocating
+    fn detach_children(&mut self, detached: &mut Vec<Expression<N>>) {
+        let mut detach = |child: &mut Expression<N>| {
+            if !child.is_leaf() {
+                detached.push(mem::replace(child, Expression::Var(String::new())));
+            }
+        };
+        match self {
+            Expression::Op { left, right, .. } => {
+                detach(left);
+                detach(right);
+            }
+            Expression::Let { value, body, .. } => {
+                detach(value);
+                detach(body);
+            }
+            Expression::Unary { operand, .. } => detach(operand),
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                detach(cond);
+                detach(then);
+                detach(otherwise);
+            }
+            Expression::Fn {
+                definition, body, ..
+            } => {
+                detach(definition);
+                detach(body);
+            }
+            //all arguments are moved out, leaves included, which leaves an empty vector behind
+            Expression::Call { args, .. } => detached.append(args),
+            Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => {}
+        }
+    }
+
+    pub(crate) fn is_leaf(&self) -> bool {
+        matches!(
+            self,
+            Expression::Value(_) | Expression::Bool(_) | Expression::Var(_)
+        )
+    }
+
+    //passes the children to `f` from the last to the first, so a stack they are pushed on pops them in order
+    pub(crate) fn children_rev<'e>(&'e self, mut f: impl FnMut(&'e Expression<N>)) {
+        match self {
+            Expression::Op { left, right, .. } => {
+                f(right);
+                f(left);
+            }
+            Expression::Let { value, body, .. } => {
+                f(body);
+                f(value);
+            }
+            Expression::Unary { operand, .. } => f(operand),
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                f(otherwise);
+                f(then);
+                f(cond);
+            }
+            Expression::Fn {
+                definition, body, ..
+            } => {
+                f(body);
+                f(definition);
+            }
+            Expression::Call { args, .. } => args.iter().rev().for_each(f),
+            Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => {}
+        }
+    }
+
+    //whether two nodes are equal apart from their children, equal nodes have the same number of children
+    fn shallow_eq(&self, other: &Expression<N>) -> bool
+    where
+        N: PartialEq,
+    {
+        match (self, other) {
+            (Expression::Op { op, .. }, Expression::Op { op: other, .. }) => op == other,
+            (Expression::Value(value), Expression::Value(other)) => value == other,
+            (Expression::Bool(value), Expression::Bool(other)) => value == other,
+            (Expression::Var(name), Expression::Var(other)) => name == other,
+            (Expression::Let { name, .. }, Expression::Let { name: other, .. }) => name == other,
+            (Expression::Unary { op, .. }, Expression::Unary { op: other, .. }) => op == other,
+            (Expression::If { .. }, Expression::If { .. }) => true,
+            (
+                Expression::Fn { name, params, .. },
+                Expression::Fn {
+                    name: other,
+                    params: other_params,
+                    ..
+                },
+            ) => name == other && params == other_params,
+            (
+                Expression::Call { name, args },
+                Expression::Call {
+                    name: other,
+                    args: other_args,
+                },
+            ) => name == other && args.len() == other_args.len(),
+            _ => false,
+        }
+    }
+}
+
+//the children of a node are cloned before the node itself, which then takes them from the clones made so far
+impl<N: Clone> Clone for Expression<N> {
+    fn clone(&self) -> Expression<N> {
+        fn take<N>(cloned: &mut Vec<Expression<N>>) -> Box<Expression<N>> {
+            Box::new(cloned.pop().expect("the children are cloned first"))
+        }
+        //the nodes to clone, `true` once their children are cloned
+        let mut pending = vec![(self, false)];
+        let mut cloned = Vec::new();
+        while let Some((e, children_cloned)) = pending.pop() {
+            if !children_cloned && !e.is_leaf() {
+                pending.push((e, true));
+                e.children_rev(|child| pending.push((child, false)));
+                continue;
+            }
+            let e = match e {
+                Expression::Op { op, .. } => {
+                    let right = take(&mut cloned);
+                    let left = take(&mut cloned);
+                    Expression::Op {
+                        op: *op,
+                        left,
+                        right,
+                    }
+                }
+                Expression::Value(value) => Expression::Value(value.clone()),
+                Expression::Bool(value) => Expression::Bool(*value),
+                Expression::Var(name) => Expression::Var(name.clone()),
+                Expression::Let { name, .. } => {
+                    let body = take(&mut cloned);
+                    let value = take(&mut cloned);
+                    Expression::Let {
+                        name: name.clone(),
+                        value,
+                        body,
+                    }
+                }
+                Expression::Unary { op, .. } => Expression::Unary {
+                    op: *op,
+                    operand: take(&mut cloned),
+                },
+                Expression::If { .. } => {
+                    let otherwise = take(&mut cloned);
+                    let then = take(&mut cloned);
+                    let cond = take(&mut cloned);
+                    Expression::If {
+                        cond,
+                        then,
+                        otherwise,
+                    }
+                }
+                Expression::Fn { name, params, .. } => {
+                    let body = take(&mut cloned);
+                    let definition = take(&mut cloned);
+                    Expression::Fn {
+                        name: name.clone(),
+                        params: params.clone(),
+                        definition,
+                        body,
+                    }
+                }
+                Expression::Call { name, args } => Expression::Call {
+                    name: name.clone(),
+                    args: cloned.split_off(cloned.len() - args.len()),
+                },
+            };
+            cloned.push(e);
+        }
+        cloned.pop().expect("the root is cloned last")
+    }
+}
+
+//the trees are compared node by node in preorder, the children of two equal nodes are compared pairwise
+impl<N: PartialEq> PartialEq for Expression<N> {
+    fn eq(&self, other: &Expression<N>) -> bool {
+        let (mut left, mut right) = (vec![self], vec![other]);
+        while let (Some(l), Some(r)) = (left.pop(), right.pop()) {
+            if !l.shallow_eq(r) {
+                return false;
+            }
+            l.children_rev(|child| left.push(child));
+            r.children_rev(|child| right.push(child));
+        }
+        true
+    }
+}
+
+impl<N: Eq> Eq for Expression<N> {}
+
+//hashes the nodes in preorder, every node with its variant, its contents apart from the children,
+//and the number of arguments of a call, so equal trees hash the same
+impl<N: Hash> Hash for Expression<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut pending = vec![self];
+        while let Some(e) = pending.pop() {
+            mem::discriminant(e).hash(state);
+            match e {
+                Expression::Op { op, .. } => op.hash(state),
+                Expression::Value(value) => value.hash(state),
+                Expression::Bool(value) => value.hash(state),
+                Expression::Var(name) => name.hash(state),
+                Expression::Let { name, .. } => name.hash(state),
+                Expression::Unary { op, .. } => op.hash(state),
+                Expression::If { .. } => {}
+                Expression::Fn { name, params, .. } => {
+                    name.hash(state);
+                    params.hash(state);
+                }
+                Expression::Call { name, args } => {
+                    name.hash(state);
+                    args.len().hash(state);
+                }
+            }
+            e.children_rev(|child| pending.push(child));
+        }
+    }
+}
+
+impl<N> From<Node<N>> for Expression<N> {
+    fn from(node: Node<N>) -> Expression<N> {
+        match node {
+            Node::Op { op, left, right } => Expression::Op { op, left, right },
+            Node::Value(value) => Expression::Value(value),
+            Node::Bool(value) => Expression::Bool(value),
+            Node::Var(name) => Expression::Var(name),
+            Node::Let { name, value, body } => Expression::Let { name, value, body },
+            Node::Unary { op, operand } => Expression::Unary { op, operand },
+            Node::If {
+                cond,
+                then,
+                otherwise,
+            } => Expression::If {
+                cond,
+                then,
+                otherwise,
+            },
+            Node::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => Expression::Fn {
+                name,
+                params,
+                definition,
+                body,
+            },
+            Node::Call { name, args } => Expression::Call { name, args },
+        }
+    }
+}
+
+//an inner node without its children, to take a tree apart and build a new one bottom-up with explicit stacks,
+//e.g. in `map` or `simplify`, so arbitrarily deep trees can be transformed without recursion
+pub(crate) enum Shell {
+    Op(Operation),
+    Let(String),
+    Unary(UnaryOperation),
+    If,
+    Fn(String, Vec<String>),
+    //with the number of arguments
+    Call(String, usize),
+}
+
+impl<N> Node<N> {
+    //the shell of an inner node with its children in order, or the leaf itself
+    pub(crate) fn split(self) -> Result<(Shell, Vec<Expression<N>>), Node<N>> {
+        Ok(match self {
+            Node::Op { op, left, right } => (Shell::Op(op), vec![*left, *right]),
+            Node::Let { name, value, body } => (Shell::Let(name), vec![*value, *body]),
+            Node::Unary { op, operand } => (Shell::Unary(op), vec![*operand]),
+            Node::If {
+                cond,
+                then,
+                otherwise,
+            } => (Shell::If, vec![*cond, *then, *otherwise]),
+            Node::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => (Shell::Fn(name, params), vec![*definition, *body]),
+            Node::Call { name, args } => (Shell::Call(name, args.len()), args),
+            leaf => return Err(leaf),
+        })
+    }
+}
+
+impl Shell {
+    //puts the children back, they are the last expressions in `built`, in order
+    pub(crate) fn build<N>(self, built: &mut Vec<Expression<N>>) -> Expression<N> {
+        fn take<N>(built: &mut Vec<Expression<N>>) -> Box<Expression<N>> {
+            Box::new(built.pop().expect("the children are built first"))
+        }
+        match self {
+            Shell::Op(op) => {
+                let right = take(built);
+                let left = take(built);
+                Expression::Op { op, left, right }
+            }
+            Shell::Let(name) => {
+                let body = take(built);
+                let value = take(built);
+                Expression::Let { name, value, body }
+            }
+            Shell::Unary(op) => Expression::Unary {
+                op,
+                operand: take(built),
+            },
+            Shell::If => {
+                let otherwise = take(built);
+                let then = take(built);
+                let cond = take(built);
+                Expression::If {
+                    cond,
+                    then,
+                    otherwise,
+                }
+            }
+            Shell::Fn(name, params) => {
+                let body = take(built);
+                let definition = take(built);
+                Expression::Fn {
+                    name,
+                    params,
+                    definition,
+                    body,
+                }
+            }
+            Shell::Call(name, count) => Expression::Call {
+                name,
+                args: built.split_off(built.len() - count),
+            },
+        }
+    }
+}
+
+//every node below the root is detached before it is dropped, so dropping it does not recurse any further
+impl<N> Drop for Expression<N> {
+    fn drop(&mut self) {
+        let mut detached = Vec::new();
+        self.detach_children(&mut detached);
+        while let Some(mut e) = detached.pop() {
+            e.detach_children(&mut detached);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Node;
+    use crate::{parse, Expression, Operation};
+    use std::collections::HashSet;
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    #[test]
+    fn test_into_node() {
+        let node = parse("1 + x").unwrap().into_node();
+        assert_eq!(
+            node,
+            Node::Op {
+                op: Operation::Add,
+                left: Box::new(Expression::Value(1)),
+                right: Box::new(Expression::Var(String::from("x"))),
+            }
+        );
+        assert_eq!(Expression::from(node), parse("1 + x").unwrap());
+    }
+
+    //the fields of every variant are moved to the node and back, run it with `cargo +nightly miri test` to check
+    //that nothing is dropped twice or leaked
+    #[test]
+    fn test_into_node_every_variant() {
+        for input in [
+            "1 - x",
+            "2",
+            "true",
+            "x",
+            "let x = 1 in x",
+            "-x",
+            "if b then 1 else 2",
+            "fn f(a, b) = a; f(1, 2)",
+            "f(x, 2)",
+        ] {
+            let e = parse(input).unwrap();
+            assert_eq!(Expression::from(e.clone().into_node()), e);
+        }
+    }
+
+    #[test]
+    fn test_drop_deep_tree() {
+        let mut e = Expression::Value(0);
+        for i in 0..1_000_000 {
+            e = Expression::If {
+                cond: Box::new(Expression::Var(String::from("x"))),
+                then: Box::new(e),
+                otherwise: Box::new(Expression::Value(i)),
+            };
+        }
+        drop(e);
+    }
+
+    #[test]
+    fn test_structure() {
+        let hash = |e: &Expression| {
+            let mut hasher = DefaultHasher::new();
+            e.hash(&mut hasher);
+            hasher.finish()
+        };
+        let inputs = [
+            "1 + x * 2",
+            "1 + x * 3",
+            "(1 + x) * 2",
+            "let x = 1 in if x < 2 then -x else !true",
+            "let y = 1 in if x < 2 then -x else !true",
+            "fn f(a, b) = a; f(1, 2)",
+            "fn f(a) = a; f(1, 2)",
+            "fn f(a, b) = a; f(1)",
+            "f(g(1), 2)",
+            "f(g(1, 2))",
+        ];
+        for (i, input) in inputs.into_iter().enumerate() {
+            let e = parse(input).unwrap();
+            assert_eq!(e.clone(), e, "{input}");
+            assert_eq!(hash(&e.clone()), hash(&e), "{input}");
+            assert_eq!(format!("{:?}", e.clone()), format!("{e:?}"));
+            for (j, other) in inputs.into_iter().enumerate() {
+                assert_eq!(e == parse(other).unwrap(), i == j, "{input} == {other}");
+            }
+        }
+        assert_ne!(
+            hash(&parse("f(g(1), 2)").unwrap()),
+            hash(&parse("f(g(1, 2))").unwrap())
+        );
+        assert_ne!(Expression::Value(f64::NAN), Expression::Value(f64::NAN));
+    }
+
+    #[test]
+    fn test_structure_deep_tree() {
+        let deep = |last| {
+            let mut e = Expression::Value(0);
+            for i in 0..1_000_000 {
+                e = Expression::Op {
+                    op: Operation::Add,
+                    left: Box::new(e),
+                    right: Box::new(Expression::Value(if i == 0 { last } else { i })),
+                };
+            }
+            e
+        };
+        let e = deep(0);
+        let clone = e.clone();
+        assert!(clone == e);
+        assert!(deep(1) != e);
+        let mut set = HashSet::new();
+        set.insert(e);
+        assert!(set.contains(&clone));
+        assert!(!set.contains(&deep(1)));
+    }
+}
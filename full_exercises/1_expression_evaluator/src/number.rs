@@ -0,0 +1,656 @@
+//the numeric types an `Expression` can be evaluated in
+//each type decides for itself what the arithmetic operations mean, most notably `Div`:
+// - i64 and BigInt use integer division, rounding towards zero
+// - BigRational divides exactly, the result is a fraction
+// - f64 follows IEEE 754, dividing by zero yields an infinity or NaN instead of an error
+//comparisons and logical operations are the same for all types, see `Number::apply`
+
+use std::fmt;
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_rational::BigRational;
+use num_traits::{Pow, Zero};
+
+use crate::{ArithmeticMode, EvalErrorKind, Operation, UnaryOperation};
+
+pub trait Number: Clone + PartialEq + PartialOrd + fmt::Debug + fmt::Display {
+    //whether identities like `x - x == 0` and `x * 0 == 0` hold for every value
+    //floating point numbers break them with infinities, NaN and signed zeros
+    const EXACT: bool = true;
+
+    //whether the values are integers, so `Div` rounds, e.g. `7 / 2` is 3
+    const INTEGER: bool = false;
+
+    fn from_i64(value: i64) -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    //applies an arithmetic or bitwise operation to two evaluated operands
+    //`mode` only affects types with a bounded range
+    fn arithmetic(
+        op: Operation,
+        left: &Self,
+        right: &Self,
+        mode: ArithmeticMode,
+    ) -> Result<Self, EvalErrorKind<Self>>;
+
+    //applies `Neg` or `BitNot` to an evaluated operand
+    fn unary_arithmetic(
+        op: UnaryOperation,
+        operand: &Self,
+        mode: ArithmeticMode,
+    ) -> Result<Self, EvalErrorKind<Self>>;
+
+    fn from_bool(value: bool) -> Self {
+        Self::from_i64(value as i64)
+    }
+
+    fn is_true(&self) -> bool {
+        !self.is_zero()
+    }
+
+    //applies a single operation to two evaluated operands
+    fn apply(
+        op: Operation,
+        left: &Self,
+        right: &Self,
+        mode: ArithmeticMode,
+    ) -> Result<Self, EvalErrorKind<Self>> {
+        let result = match op {
+            Operation::Eq => left == right,
+            Operation::Ne => left != right,
+            Operation::Lt => left < right,
+            Operation::Le => left <= right,
+            Operation::Gt => left > right,
+            Operation::Ge => left >= right,
+            Operation::And => left.is_true() && right.is_true(),
+            Operation::Or => left.is_true() || right.is_true(),
+            _ => return Self::arithmetic(op, left, right, mode),
+        };
+        Ok(Self::from_bool(result))
+    }
+
+    //applies a single operation to an evaluated operand
+    fn apply_unary(
+        op: UnaryOperation,
+        operand: &Self,
+        mode: ArithmeticMode,
+    ) -> Result<Self, EvalErrorKind<Self>> {
+        match op {
+            UnaryOperation::Not => Ok(Self::from_bool(!operand.is_true())),
+            _ => Self::unary_arithmetic(op, operand, mode),
+        }
+    }
+}
+
+//exponentiation by squaring, `mul` returns `None` on overflow
+fn pow(base: i64, exponent: u64, mul: impl Fn(i64, i64) -> Option<i64>) -> Option<i64> {
+    let (mut result, mut base, mut exponent) = (1i64, base, exponent);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul(result, base)?;
+        }
+        exponent >>= 1;
+        //squaring is skipped after the last bit, it could overflow while the result does not
+        if exponent > 0 {
+            base = mul(base, base)?;
+        }
+    }
+    Some(result)
+}
+
+//division by 0 and negative exponents are errors in every mode
+//shift amounts outside of 0..64 are errors as well, unless they wrap around in `Wrapping` mode
+impl Number for i64 {
+    const INTEGER: bool = true;
+
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn arithmetic(
+        op: Operation,
+        &left: &i64,
+        &right: &i64,
+        mode: ArithmeticMode,
+    ) -> Result<i64, EvalErrorKind> {
+        let out_of_domain = EvalErrorKind::OutOfDomain { op, left, right };
+        match op {
+            Operation::Div | Operation::Mod if right == 0 => {
+                return Err(EvalErrorKind::DivisionByZero)
+            }
+            Operation::Pow if right < 0 => return Err(out_of_domain),
+            Operation::Shl | Operation::Shr
+                if mode != ArithmeticMode::Wrapping && !(0..64).contains(&right) =>
+            {
+                return Err(out_of_domain)
+            }
+            _ => {}
+        }
+        let checked = match op {
+            Operation::Add => left.checked_add(right),
+            Operation::Sub => left.checked_sub(right),
+            Operation::Mul => left.checked_mul(right),
+            Operation::Div => left.checked_div(right),
+            Operation::Mod => left.checked_rem(right),
+            Operation::Pow => pow(left, right as u64, i64::checked_mul),
+            Operation::BitAnd => Some(left & right),
+            Operation::BitOr => Some(left | right),
+            Operation::BitXor => Some(left ^ right),
+            //the shift amount is either in range or wraps around
+            Operation::Shl => Some(left.wrapping_shl(right as u32)),
+            Operation::Shr => Some(left.wrapping_shr(right as u32)),
+            _ => return Err(EvalErrorKind::Unsupported(op.symbol())),
+        };
+        if let Some(result) = checked {
+            return Ok(result);
+        }
+        match (mode, op) {
+            (ArithmeticMode::Checked, _) => Err(EvalErrorKind::Overflow { op, left, right }),
+            (ArithmeticMode::Wrapping, Operation::Pow) => {
+                Ok(pow(left, right as u64, |a, b| Some(a.wrapping_mul(b)))
+                    .expect("wrapping multiplication cannot overflow"))
+            }
+            (ArithmeticMode::Wrapping, _) => Ok(match op {
+                Operation::Add => left.wrapping_add(right),
+                Operation::Sub => left.wrapping_sub(right),
+                Operation::Mul => left.wrapping_mul(right),
+                Operation::Div => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            }),
+            //only the sign of the mathematical result matters to know which bound to clamp to
+            (ArithmeticMode::Saturating, Operation::Pow) if left < 0 && right % 2 == 1 => {
+                Ok(i64::MIN)
+            }
+            (ArithmeticMode::Saturating, Operation::Pow) => Ok(i64::MAX),
+            (ArithmeticMode::Saturating, _) => Ok(match op {
+                Operation::Add => left.saturating_add(right),
+                Operation::Sub => left.saturating_sub(right),
+                Operation::Mul => left.saturating_mul(right),
+                Operation::Div => left.saturating_div(right),
+                //`i64::MIN % -1` is the only remainder that overflows, mathematically it is 0
+                _ => 0,
+            }),
+        }
+    }
+
+    fn unary_arithmetic(
+        op: UnaryOperation,
+        &operand: &i64,
+        mode: ArithmeticMode,
+    ) -> Result<i64, EvalErrorKind> {
+        match (op, mode) {
+            (UnaryOperation::Neg, ArithmeticMode::Checked) => operand
+                .checked_neg()
+                .ok_or(EvalErrorKind::UnaryOverflow { op, operand }),
+            (UnaryOperation::Neg, ArithmeticMode::Wrapping) => Ok(operand.wrapping_neg()),
+            (UnaryOperation::Neg, ArithmeticMode::Saturating) => Ok(operand.saturating_neg()),
+            (UnaryOperation::BitNot, _) => Ok(!operand),
+            _ => Err(EvalErrorKind::Unsupported(op.symbol())),
+        }
+    }
+}
+
+//bitwise operations are not supported
+impl Number for f64 {
+    const EXACT: bool = false;
+
+    fn from_i64(value: i64) -> Self {
+        value as f64
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn arithmetic(
+        op: Operation,
+        left: &f64,
+        right: &f64,
+        _: ArithmeticMode,
+    ) -> Result<f64, EvalErrorKind<f64>> {
+        Ok(match op {
+            Operation::Add => left + right,
+            Operation::Sub => left - right,
+            Operation::Mul => left * right,
+            Operation::Div => left / right,
+            Operation::Mod => left % right,
+            Operation::Pow => left.powf(*right),
+            _ => return Err(EvalErrorKind::Unsupported(op.symbol())),
+        })
+    }
+
+    fn unary_arithmetic(
+        op: UnaryOperation,
+        operand: &f64,
+        _: ArithmeticMode,
+    ) -> Result<f64, EvalErrorKind<f64>> {
+        match op {
+            UnaryOperation::Neg => Ok(-operand),
+            _ => Err(EvalErrorKind::Unsupported(op.symbol())),
+        }
+    }
+}
+
+//arbitrary-precision integers never overflow, except for exponents that do not fit in a u32
+//bitwise operations behave as if the numbers are stored in two's complement
+//the largest results of `<<` and `**` the arbitrary-precision types compute, in bits of the integers,
+//a larger result is an `Overflow`, computing it could take longer than any `Budget` allows or abort the process
+//when the memory runs out, since a single operation cannot be interrupted
+//the limit is checked before the result is computed, against an estimate of its size
+pub const MAX_BITS: u64 = 1 << 20;
+
+//whether `base ** exponent` surely has more than `MAX_BITS` bits, it has at least `(bits - 1) * exponent`
+//0, 1 and -1 stay small for any exponent
+fn is_too_large(bits: u64, exponent: &BigUint) -> bool {
+    bits > 1 && BigUint::from(bits - 1) * exponent > BigUint::from(MAX_BITS)
+}
+
+//shift amounts and exponents are limited by `MAX_BITS`
+impl Number for BigInt {
+    const INTEGER: bool = true;
+
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn arithmetic(
+        op: Operation,
+        left: &BigInt,
+        right: &BigInt,
+        _: ArithmeticMode,
+    ) -> Result<BigInt, EvalErrorKind<BigInt>> {
+        let out_of_domain = || EvalErrorKind::OutOfDomain {
+            op,
+            left: left.clone(),
+            right: right.clone(),
+        };
+        let overflow = || EvalErrorKind::Overflow {
+            op,
+            left: left.clone(),
+            right: right.clone(),
+        };
+        Ok(match op {
+            Operation::Add => left + right,
+            Operation::Sub => left - right,
+            Operation::Mul => left * right,
+            Operation::Div | Operation::Mod if Zero::is_zero(right) => {
+                return Err(EvalErrorKind::DivisionByZero)
+            }
+            Operation::Div => left / right,
+            Operation::Mod => left % right,
+            Operation::Pow if right.sign() == Sign::Minus => return Err(out_of_domain()),
+            Operation::Pow if is_too_large(left.bits(), right.magnitude()) => {
+                return Err(overflow())
+            }
+            Operation::Pow => Pow::pow(left, right.magnitude()),
+            Operation::BitAnd => left & right,
+            Operation::BitOr => left | right,
+            Operation::BitXor => left ^ right,
+            Operation::Shl | Operation::Shr => match usize::try_from(right) {
+                //the result has `left.bits() + amount` bits, 0 stays 0
+                Ok(amount)
+                    if op == Operation::Shl
+                        && !Zero::is_zero(left)
+                        && left.bits().saturating_add(amount as u64) > MAX_BITS =>
+                {
+                    return Err(overflow())
+                }
+                Ok(amount) if op == Operation::Shl => left << amount,
+                Ok(amount) => left >> amount,
+                Err(_) => return Err(out_of_domain()),
+            },
+            _ => return Err(EvalErrorKind::Unsupported(op.symbol())),
+        })
+    }
+
+    fn unary_arithmetic(
+        op: UnaryOperation,
+        operand: &BigInt,
+        _: ArithmeticMode,
+    ) -> Result<BigInt, EvalErrorKind<BigInt>> {
+        match op {
+            UnaryOperation::Neg => Ok(-operand),
+            UnaryOperation::BitNot => Ok(!operand),
+            _ => Err(EvalErrorKind::Unsupported(op.symbol())),
+        }
+    }
+}
+
+//exact fractions of arbitrary-precision integers
+//exponents must be integers that fit in an i32 and are limited by `MAX_BITS`, bitwise operations are not supported
+impl Number for BigRational {
+    fn from_i64(value: i64) -> Self {
+        BigRational::from_integer(BigInt::from(value))
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn arithmetic(
+        op: Operation,
+        left: &BigRational,
+        right: &BigRational,
+        _: ArithmeticMode,
+    ) -> Result<BigRational, EvalErrorKind<BigRational>> {
+        Ok(match op {
+            Operation::Add => left + right,
+            Operation::Sub => left - right,
+            Operation::Mul => left * right,
+            Operation::Div | Operation::Mod if Zero::is_zero(right) => {
+                return Err(EvalErrorKind::DivisionByZero)
+            }
+            Operation::Div => left / right,
+            Operation::Mod => left % right,
+            Operation::Pow if !right.is_integer() => {
+                return Err(EvalErrorKind::OutOfDomain {
+                    op,
+                    left: left.clone(),
+                    right: right.clone(),
+                })
+            }
+            Operation::Pow => match i32::try_from(right.to_integer()) {
+                Ok(exponent) if exponent < 0 && Zero::is_zero(left) => {
+                    return Err(EvalErrorKind::DivisionByZero)
+                }
+                Ok(exponent)
+                    if !is_too_large(
+                        left.numer().bits().max(left.denom().bits()),
+                        &BigUint::from(exponent.unsigned_abs()),
+                    ) =>
+                {
+                    left.pow(exponent)
+                }
+                _ => {
+                    return Err(EvalErrorKind::Overflow {
+                        op,
+                        left: left.clone(),
+                        right: right.clone(),
+                    })
+                }
+            },
+            _ => return Err(EvalErrorKind::Unsupported(op.symbol())),
+        })
+    }
+
+    fn unary_arithmetic(
+        op: UnaryOperation,
+        operand: &BigRational,
+        _: ArithmeticMode,
+    ) -> Result<BigRational, EvalErrorKind<BigRational>> {
+        match op {
+            UnaryOperation::Neg => Ok(-operand),
+            _ => Err(EvalErrorKind::Unsupported(op.symbol())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::Number;
+    use crate::{
+        eval, parse, ArithmeticMode, EvalErrorKind, Expression, Operation, UnaryOperation,
+    };
+
+    #[test]
+    fn test_checked_mode() {
+        let apply = |op, left, right| i64::arithmetic(op, &left, &right, ArithmeticMode::Checked);
+        assert_eq!(apply(Operation::Add, i64::MAX - 1, 1), Ok(i64::MAX));
+        assert!(apply(Operation::Add, i64::MAX, 1).is_err());
+        assert_eq!(apply(Operation::Sub, i64::MIN + 1, 1), Ok(i64::MIN));
+        assert!(apply(Operation::Sub, i64::MIN, 1).is_err());
+        assert!(apply(Operation::Mul, i64::MIN, -1).is_err());
+        assert_eq!(apply(Operation::Div, i64::MIN, 1), Ok(i64::MIN));
+        assert_eq!(
+            apply(Operation::Div, i64::MIN, -1),
+            Err(EvalErrorKind::Overflow {
+                op: Operation::Div,
+                left: i64::MIN,
+                right: -1
+            })
+        );
+    }
+
+    #[test]
+    fn test_wrapping_mode() {
+        let apply = |op, left, right| i64::arithmetic(op, &left, &right, ArithmeticMode::Wrapping);
+        assert_eq!(apply(Operation::Add, i64::MAX, 1), Ok(i64::MIN));
+        assert_eq!(apply(Operation::Sub, i64::MIN, 1), Ok(i64::MAX));
+        assert_eq!(apply(Operation::Mul, i64::MAX, 2), Ok(-2));
+        assert_eq!(apply(Operation::Mul, i64::MIN, -1), Ok(i64::MIN));
+        assert_eq!(apply(Operation::Div, i64::MIN, -1), Ok(i64::MIN));
+        assert_eq!(
+            apply(Operation::Div, 1, 0),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_saturating_mode() {
+        let apply =
+            |op, left, right| i64::arithmetic(op, &left, &right, ArithmeticMode::Saturating);
+        assert_eq!(apply(Operation::Add, i64::MAX, 1), Ok(i64::MAX));
+        assert_eq!(apply(Operation::Add, i64::MIN, -1), Ok(i64::MIN));
+        assert_eq!(apply(Operation::Sub, i64::MIN, 1), Ok(i64::MIN));
+        assert_eq!(apply(Operation::Sub, i64::MAX, -1), Ok(i64::MAX));
+        assert_eq!(apply(Operation::Mul, i64::MAX, 2), Ok(i64::MAX));
+        assert_eq!(apply(Operation::Mul, i64::MIN, 2), Ok(i64::MIN));
+        assert_eq!(apply(Operation::Mul, i64::MIN, -1), Ok(i64::MAX));
+        assert_eq!(apply(Operation::Div, i64::MIN, -1), Ok(i64::MAX));
+        assert_eq!(
+            apply(Operation::Div, 1, 0),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_integer_operators() {
+        let checked = |op, left, right| i64::apply(op, &left, &right, ArithmeticMode::Checked);
+        assert_eq!(checked(Operation::Mod, -7, 3), Ok(-1));
+        assert_eq!(
+            checked(Operation::Mod, 7, 0),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+        assert!(checked(Operation::Mod, i64::MIN, -1).is_err());
+        assert_eq!(checked(Operation::Pow, -2, 63), Ok(i64::MIN));
+        assert!(checked(Operation::Pow, 2, 63).is_err());
+        assert_eq!(checked(Operation::Pow, -1, i64::MAX), Ok(-1));
+        assert_eq!(checked(Operation::Pow, 0, 0), Ok(1));
+        assert_eq!(
+            checked(Operation::Pow, 2, -1),
+            Err(EvalErrorKind::OutOfDomain {
+                op: Operation::Pow,
+                left: 2,
+                right: -1
+            })
+        );
+        assert_eq!(checked(Operation::Shl, 1, 63), Ok(i64::MIN));
+        assert!(checked(Operation::Shl, 1, 64).is_err());
+        assert!(checked(Operation::Shr, 1, -1).is_err());
+        assert_eq!(checked(Operation::Shr, -8, 1), Ok(-4));
+        assert_eq!(checked(Operation::BitXor, 6, 3), Ok(5));
+        assert_eq!(checked(Operation::Lt, 1, 2), Ok(1));
+        assert_eq!(checked(Operation::And, 5, 0), Ok(0));
+
+        let wrapping = |op, left, right| i64::apply(op, &left, &right, ArithmeticMode::Wrapping);
+        assert_eq!(wrapping(Operation::Mod, i64::MIN, -1), Ok(0));
+        assert_eq!(wrapping(Operation::Pow, 3, 41), Ok(3i64.wrapping_pow(41)));
+        assert_eq!(wrapping(Operation::Shl, 1, 65), Ok(2));
+
+        let saturating =
+            |op, left, right| i64::apply(op, &left, &right, ArithmeticMode::Saturating);
+        assert_eq!(saturating(Operation::Mod, i64::MIN, -1), Ok(0));
+        assert_eq!(saturating(Operation::Pow, -3, 41), Ok(i64::MIN));
+        assert_eq!(saturating(Operation::Pow, -3, 42), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        let mode = ArithmeticMode::Checked;
+        assert_eq!(
+            i64::apply_unary(UnaryOperation::Neg, &i64::MIN, mode),
+            Err(EvalErrorKind::UnaryOverflow {
+                op: UnaryOperation::Neg,
+                operand: i64::MIN
+            })
+        );
+        assert_eq!(
+            i64::apply_unary(UnaryOperation::Neg, &i64::MIN, ArithmeticMode::Wrapping),
+            Ok(i64::MIN)
+        );
+        assert_eq!(
+            i64::apply_unary(UnaryOperation::Neg, &i64::MIN, ArithmeticMode::Saturating),
+            Ok(i64::MAX)
+        );
+        assert_eq!(i64::apply_unary(UnaryOperation::Not, &7, mode), Ok(0));
+        assert_eq!(i64::apply_unary(UnaryOperation::BitNot, &7, mode), Ok(-8));
+        assert_eq!(
+            f64::apply_unary(UnaryOperation::BitNot, &7.0, mode),
+            Err(EvalErrorKind::Unsupported("~"))
+        );
+    }
+
+    #[test]
+    fn test_backend_operators() {
+        assert_eq!(eval(parsed::<f64>("2 ** -1 + 7 % 4")), Ok(3.5));
+        assert_eq!(
+            eval(parsed::<f64>("1 & 1")).unwrap_err().kind,
+            EvalErrorKind::Unsupported("&")
+        );
+        assert_eq!(
+            eval(parsed::<BigInt>("2 ** 100 >> 98 | ~0 & 1")),
+            Ok(BigInt::from(5))
+        );
+        assert!(eval(parsed::<BigInt>("2 ** -1")).is_err());
+        assert_eq!(
+            eval(parsed::<BigRational>("(2 / 3) ** -2 + 7 % 2"))
+                .unwrap()
+                .to_string(),
+            "13/4"
+        );
+        assert_eq!(
+            eval(parsed::<BigRational>("0 ** -1")).unwrap_err().kind,
+            EvalErrorKind::DivisionByZero
+        );
+        assert!(eval(parsed::<BigRational>("4 ** (1 / 2)")).is_err());
+    }
+
+    //results larger than `MAX_BITS` are errors instead of being computed
+    #[test]
+    fn test_large_results() {
+        let error = |input| eval(parsed::<BigInt>(input)).unwrap_err().kind;
+        assert!(matches!(
+            error("1 << 9223372036854775807"),
+            EvalErrorKind::Overflow {
+                op: Operation::Shl,
+                ..
+            }
+        ));
+        assert!(matches!(
+            error("3 ** 2000000000"),
+            EvalErrorKind::Overflow {
+                op: Operation::Pow,
+                ..
+            }
+        ));
+        assert!(matches!(
+            eval(parsed::<BigRational>("(1 / 3) ** 2000000000"))
+                .unwrap_err()
+                .kind,
+            EvalErrorKind::Overflow {
+                op: Operation::Pow,
+                ..
+            }
+        ));
+        assert_eq!(
+            eval(parsed::<BigInt>("(1 << 1048575) >> 1048574")),
+            Ok(BigInt::from(2))
+        );
+        assert!(eval(parsed::<BigInt>("1 << 1048576")).is_err());
+        assert_eq!(
+            eval(parsed::<BigInt>("(2 ** 1048576) >> 1048575")),
+            Ok(BigInt::from(2))
+        );
+        assert!(eval(parsed::<BigInt>("2 ** 1048577")).is_err());
+        //0, 1 and -1 stay small
+        assert_eq!(
+            eval(parsed::<BigInt>("0 << 9223372036854775807")),
+            Ok(BigInt::from(0))
+        );
+        assert_eq!(
+            eval(parsed::<BigInt>(
+                "(-1) ** 9223372036854775807 + 1 ** 9223372036854775807"
+            )),
+            Ok(BigInt::from(0))
+        );
+        assert_eq!(
+            eval(parsed::<BigRational>("(-1) ** 2000000000")),
+            Ok(BigRational::from_i64(1))
+        );
+        assert_eq!(
+            eval(parsed::<BigRational>("1 / 3 < 1 / 2")),
+            Ok(BigRational::from_i64(1))
+        );
+    }
+
+    fn parsed<N: Number>(input: &str) -> Expression<N> {
+        parse(input).unwrap().map(N::from_i64)
+    }
+
+    #[test]
+    fn test_backends() {
+        let input = "(7 / 2) * 2 + 9223372036854775807";
+        assert_eq!(
+            eval(parsed::<i64>("(7 / 2) * 2 + 1")),
+            Ok(7),
+            "integer division rounds towards zero"
+        );
+        assert!(eval(parsed::<i64>(input)).is_err());
+        assert_eq!(
+            eval(parsed::<BigInt>(input)),
+            Ok("9223372036854775813".parse().unwrap())
+        );
+        assert_eq!(
+            eval(parsed::<BigRational>(input)),
+            Ok(BigRational::from_integer(
+                "9223372036854775814".parse().unwrap()
+            ))
+        );
+        assert_eq!(
+            eval(parsed::<BigRational>("1 / 3")).unwrap().to_string(),
+            "1/3"
+        );
+        assert_eq!(eval(parsed::<f64>("7 / 2")), Ok(3.5));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let input = "1 / (2 - 2)";
+        assert_eq!(
+            eval(parsed::<i64>(input)).unwrap_err().kind,
+            EvalErrorKind::DivisionByZero
+        );
+        assert_eq!(
+            eval(parsed::<BigInt>(input)).unwrap_err().kind,
+            EvalErrorKind::DivisionByZero
+        );
+        assert_eq!(
+            eval(parsed::<BigRational>(input)).unwrap_err().kind,
+            EvalErrorKind::DivisionByZero
+        );
+        assert_eq!(eval(parsed::<f64>(input)), Ok(f64::INFINITY));
+        assert!(eval(parsed::<f64>("0 / 0")).unwrap().is_nan());
+    }
+}
@@ -0,0 +1,760 @@
+//turns the textual infix notation of an expression, e.g. `(10 * 9) + (5 * (3 - 4))`, into an `Expression` tree
+//variables are bound with `let <name> = <value> in <body>` and conditionals are written as `if <cond> then <a> else <b>`,
+//functions are defined with `fn <name>(<params>) = <definition>; <body>` and called with `<name>(<args>)`,
+//the boolean literals are `true` and `false`,
+//the body and the else branch extend as far to the right as possible
+//operators have the same precedence as in Rust, `**` (power) binds tighter than the unary operators and is right-associative
+//parsing happens in two stages: the input is first split into tokens, which are then combined into a tree
+//using precedence climbing (see https://en.wikipedia.org/wiki/Operator-precedence_parser#Precedence_climbing_method)
+//all errors carry the byte offset in the input at which they were detected
+//the parser is recursive, so the nesting depth is limited to `MAX_DEPTH` to fail with an error instead of overflowing
+//the stack, e.g. on a long run of `(` or `-`
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Expression, Operation, UnaryOperation};
+
+//the number of expressions that `parse` allows to be nested in each other, counting parentheses, operands of unary
+//operators and right operands of `**`, as well as the parts of `let`, `if`, `fn` and calls
+pub const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    //the magnitude of an integer literal, its sign is handled by the parser
+    Number(u64),
+    //the name of an identifier is looked up in the input using the token's offset and length
+    Identifier,
+    Keyword(&'static str),
+    //operators and parentheses
+    Symbol(&'static str),
+}
+
+const KEYWORDS: [&str; 8] = ["let", "in", "if", "then", "else", "fn", "true", "false"];
+
+//symbols that start with another symbol come first, so the longest match is found
+const SYMBOLS: [&str; 26] = [
+    "**", "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^",
+    "<", ">", "!", "~", "=", "(", ")", ",", ";",
+];
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: TokenKind,
+    offset: usize,
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedCharacter(char),
+    //an integer literal that does not fit in an i64
+    NumberOutOfRange,
+    UnexpectedToken {
+        found: String,
+        expected: &'static str,
+    },
+    UnexpectedEnd {
+        expected: &'static str,
+    },
+    //a `(` without a matching `)`, the offset points at the `(`
+    UnclosedParenthesis,
+    //a `)` without a matching `(`
+    UnmatchedParenthesis,
+    TrailingInput,
+    //the expression is nested more than `MAX_DEPTH` levels deep
+    TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub offset: usize,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{c}`"),
+            ParseErrorKind::NumberOutOfRange => write!(f, "integer literal out of range"),
+            ParseErrorKind::UnexpectedToken { found, expected } => {
+                write!(f, "unexpected `{found}`, expected {expected}")
+            }
+            ParseErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "unexpected end of input, expected {expected}")
+            }
+            ParseErrorKind::UnclosedParenthesis => write!(f, "unclosed parenthesis"),
+            ParseErrorKind::UnmatchedParenthesis => write!(f, "unmatched closing parenthesis"),
+            ParseErrorKind::TrailingInput => write!(f, "unexpected trailing input"),
+            ParseErrorKind::TooDeep => write!(f, "expression is nested too deeply"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl Error for ParseError {}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = offset + 1;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    end = i + 1;
+                    chars.next();
+                }
+                let kind = match KEYWORDS
+                    .iter()
+                    .find(|&&keyword| keyword == &input[offset..end])
+                {
+                    Some(keyword) => TokenKind::Keyword(keyword),
+                    None => TokenKind::Identifier,
+                };
+                tokens.push(Token {
+                    kind,
+                    offset,
+                    len: end - offset,
+                });
+                continue;
+            }
+            '0'..='9' => {
+                let mut end = offset + 1;
+                while let Some((i, '0'..='9')) = chars.peek() {
+                    end = i + 1;
+                    chars.next();
+                }
+                let number = input[offset..end].parse().map_err(|_| ParseError {
+                    kind: ParseErrorKind::NumberOutOfRange,
+                    offset,
+                })?;
+                tokens.push(Token {
+                    kind: TokenKind::Number(number),
+                    offset,
+                    len: end - offset,
+                });
+                continue;
+            }
+            c => match SYMBOLS
+                .iter()
+                .find(|symbol| input[offset..].starts_with(*symbol))
+            {
+                Some(symbol) => {
+                    //skip the remaining characters of multi-character symbols
+                    for _ in 1..symbol.len() {
+                        chars.next();
+                    }
+                    TokenKind::Symbol(symbol)
+                }
+                None => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::UnexpectedCharacter(c),
+                        offset,
+                    })
+                }
+            },
+        };
+        tokens.push(Token {
+            kind,
+            offset,
+            len: match kind {
+                TokenKind::Symbol(symbol) => symbol.len(),
+                _ => c.len_utf8(),
+            },
+        });
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    //the number of `expression` calls that have not returned yet
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn text(&self, token: Token) -> &'a str {
+        &self.input[token.offset..token.offset + token.len]
+    }
+
+    //consumes the next token, which must be of the given kind
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<Token, ParseError> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(token),
+            other => Err(self.unexpected(other, expected)),
+        }
+    }
+
+    fn unexpected(&self, token: Option<Token>, expected: &'static str) -> ParseError {
+        match token {
+            Some(token) => ParseError {
+                kind: ParseErrorKind::UnexpectedToken {
+                    found: self.text(token).to_string(),
+                    expected,
+                },
+                offset: token.offset,
+            },
+            None => ParseError {
+                kind: ParseErrorKind::UnexpectedEnd { expected },
+                offset: self.input.len(),
+            },
+        }
+    }
+
+    fn peek_kind(&self, offset: usize) -> Option<TokenKind> {
+        self.tokens.get(self.position + offset).map(|t| t.kind)
+    }
+
+    fn binary_operation(&self) -> Option<Operation> {
+        let TokenKind::Symbol(symbol) = self.peek()?.kind else {
+            return None;
+        };
+        Some(match symbol {
+            "+" => Operation::Add,
+            "-" => Operation::Sub,
+            "*" => Operation::Mul,
+            "/" => Operation::Div,
+            "%" => Operation::Mod,
+            "**" => Operation::Pow,
+            "&" => Operation::BitAnd,
+            "|" => Operation::BitOr,
+            "^" => Operation::BitXor,
+            "<<" => Operation::Shl,
+            ">>" => Operation::Shr,
+            "==" => Operation::Eq,
+            "!=" => Operation::Ne,
+            "<" => Operation::Lt,
+            "<=" => Operation::Le,
+            ">" => Operation::Gt,
+            ">=" => Operation::Ge,
+            "&&" => Operation::And,
+            "||" => Operation::Or,
+            _ => return None,
+        })
+    }
+
+    //parses a sequence of operands separated by operators that bind at least as tight as `min_precedence`
+    //every nested expression is parsed by a call of this method, so it keeps track of the depth
+    fn expression(&mut self, min_precedence: u8) -> Result<Expression, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError {
+                kind: ParseErrorKind::TooDeep,
+                offset: self.peek().map_or(self.input.len(), |token| token.offset),
+            });
+        }
+        self.depth += 1;
+        let result = self.operands(min_precedence);
+        self.depth -= 1;
+        result
+    }
+
+    fn operands(&mut self, min_precedence: u8) -> Result<Expression, ParseError> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.binary_operation() {
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.next();
+            let right = if op.is_right_associative() {
+                self.expression(precedence)?
+            } else {
+                self.expression(precedence + 1)?
+            };
+            left = Expression::Op {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    //an operand, optionally preceded by unary operators
+    //`-` directly in front of a literal is part of the literal, unless the literal is the base of `**`
+    //so `-2 ** 2` is `-(2 ** 2)`, just like in mathematics
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        let op = match self.peek_kind(0) {
+            Some(TokenKind::Symbol("-")) => match (self.peek_kind(1), self.peek_kind(2)) {
+                (Some(TokenKind::Number(_)), next) if next != Some(TokenKind::Symbol("**")) => {
+                    return self.negative_literal();
+                }
+                _ => UnaryOperation::Neg,
+            },
+            Some(TokenKind::Symbol("!")) => UnaryOperation::Not,
+            Some(TokenKind::Symbol("~")) => UnaryOperation::BitNot,
+            _ => return self.primary(),
+        };
+        self.next();
+        let operand = self.expression(Operation::Pow.precedence())?;
+        Ok(Expression::Unary {
+            op,
+            operand: Box::new(operand),
+        })
+    }
+
+    //negating the magnitude instead of the parsed i64 also allows `i64::MIN` to be written down
+    fn negative_literal(&mut self) -> Result<Expression, ParseError> {
+        let minus = self.next();
+        match self.next() {
+            Some(Token {
+                kind: TokenKind::Number(magnitude),
+                ..
+            }) => 0i64
+                .checked_sub_unsigned(magnitude)
+                .map(Expression::Value)
+                .ok_or(ParseError {
+                    kind: ParseErrorKind::NumberOutOfRange,
+                    offset: minus.map_or(0, |t| t.offset),
+                }),
+            other => Err(self.unexpected(other, "a number")),
+        }
+    }
+
+    //a literal, a variable, a function call, a `let` binding, a conditional, a function definition
+    //or a parenthesized expression
+    fn primary(&mut self) -> Result<Expression, ParseError> {
+        let Some(token) = self.next() else {
+            return Err(self.unexpected(None, "an expression"));
+        };
+        match token.kind {
+            TokenKind::Identifier if self.peek_kind(0) == Some(TokenKind::Symbol("(")) => {
+                let args = self.list(|this| this.expression(0))?;
+                Ok(Expression::Call {
+                    name: self.text(token).to_string(),
+                    args,
+                })
+            }
+            TokenKind::Identifier => Ok(Expression::Var(self.text(token).to_string())),
+            TokenKind::Keyword("true") => Ok(Expression::Bool(true)),
+            TokenKind::Keyword("false") => Ok(Expression::Bool(false)),
+            TokenKind::Keyword("let") => {
+                let name = self.expect(TokenKind::Identifier, "a variable name")?;
+                self.expect(TokenKind::Symbol("="), "`=`")?;
+                let value = self.expression(0)?;
+                self.expect(TokenKind::Keyword("in"), "`in`")?;
+                let body = self.expression(0)?;
+                Ok(Expression::Let {
+                    name: self.text(name).to_string(),
+                    value: Box::new(value),
+                    body: Box::new(body),
+                })
+            }
+            TokenKind::Keyword("if") => {
+                let cond = self.expression(0)?;
+                self.expect(TokenKind::Keyword("then"), "`then`")?;
+                let then = self.expression(0)?;
+                self.expect(TokenKind::Keyword("else"), "`else`")?;
+                let otherwise = self.expression(0)?;
+                Ok(Expression::If {
+                    cond: Box::new(cond),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                })
+            }
+            TokenKind::Keyword("fn") => {
+                let name = self.expect(TokenKind::Identifier, "a function name")?;
+                let params = self.list(|this| {
+                    let param = this.expect(TokenKind::Identifier, "a parameter name")?;
+                    Ok(this.text(param).to_string())
+                })?;
+                self.expect(TokenKind::Symbol("="), "`=`")?;
+                let definition = self.expression(0)?;
+                self.expect(TokenKind::Symbol(";"), "`;`")?;
+                let body = self.expression(0)?;
+                Ok(Expression::Fn {
+                    name: self.text(name).to_string(),
+                    params,
+                    definition: Box::new(definition),
+                    body: Box::new(body),
+                })
+            }
+            TokenKind::Number(magnitude) => i64::try_from(magnitude)
+                .map(Expression::Value)
+                .map_err(|_| ParseError {
+                    kind: ParseErrorKind::NumberOutOfRange,
+                    offset: token.offset,
+                }),
+            TokenKind::Symbol("(") => {
+                let expression = self.expression(0)?;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::Symbol(")"),
+                        ..
+                    }) => Ok(expression),
+                    None => Err(ParseError {
+                        kind: ParseErrorKind::UnclosedParenthesis,
+                        offset: token.offset,
+                    }),
+                    other => Err(self.unexpected(other, "`)`")),
+                }
+            }
+            TokenKind::Symbol(")") => Err(ParseError {
+                kind: ParseErrorKind::UnmatchedParenthesis,
+                offset: token.offset,
+            }),
+            _ => Err(self.unexpected(Some(token), "an expression")),
+        }
+    }
+
+    //a parenthesized list of arguments or parameters separated by `,`, it may be empty
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let open = self.expect(TokenKind::Symbol("("), "`(`")?;
+        let mut items = Vec::new();
+        if self.peek_kind(0) == Some(TokenKind::Symbol(")")) {
+            self.next();
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Symbol(","),
+                    ..
+                }) => {}
+                Some(Token {
+                    kind: TokenKind::Symbol(")"),
+                    ..
+                }) => return Ok(items),
+                None => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::UnclosedParenthesis,
+                        offset: open.offset,
+                    })
+                }
+                other => return Err(self.unexpected(other, "`,` or `)`")),
+            }
+        }
+    }
+}
+
+//parses the infix notation of an expression
+//the usual precedence rules apply: `*` and `/` bind tighter than `+` and `-`
+pub fn parse(input: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        position: 0,
+        depth: 0,
+    };
+    let expression = parser.expression(0)?;
+    match parser.peek() {
+        None => Ok(expression),
+        Some(Token {
+            kind: TokenKind::Symbol(")"),
+            offset,
+            ..
+        }) => Err(ParseError {
+            kind: ParseErrorKind::UnmatchedParenthesis,
+            offset,
+        }),
+        Some(token) => Err(ParseError {
+            kind: ParseErrorKind::TrailingInput,
+            offset: token.offset,
+        }),
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, ParseError, ParseErrorKind, MAX_DEPTH};
+    use crate::{eval, Expression, Operation, UnaryOperation};
+
+    fn error(input: &str) -> ParseError {
+        parse(input).unwrap_err()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("19"), Ok(Expression::Value(19)));
+        assert_eq!(
+            parse("10 - -20"),
+            Ok(Expression::Op {
+                op: Operation::Sub,
+                left: Box::new(Expression::Value(10)),
+                right: Box::new(Expression::Value(-20)),
+            })
+        );
+        assert_eq!(
+            parse("-9223372036854775808"),
+            Ok(Expression::Value(i64::MIN))
+        );
+        assert_eq!(parse("true"), Ok(Expression::Bool(true)));
+        assert_eq!(parse("(false)"), Ok(Expression::Bool(false)));
+        assert_eq!(eval(parse("(10 * 9) + (5 * (3 - 4))").unwrap()), Ok(85));
+    }
+
+    #[test]
+    fn test_precedence_and_associativity() {
+        assert_eq!(eval(parse("1 + 2 * 3").unwrap()), Ok(7));
+        assert_eq!(eval(parse("(1 + 2) * 3").unwrap()), Ok(9));
+        assert_eq!(eval(parse("10 - 4 - 3").unwrap()), Ok(3));
+        assert_eq!(eval(parse("100 / 10 / 5").unwrap()), Ok(2));
+        assert_eq!(eval("2*3-4/2".parse().unwrap()), Ok(4));
+    }
+
+    #[test]
+    fn test_operators() {
+        let eval = |input| eval(parse(input).unwrap());
+        assert_eq!(eval("7 % 3 + 2 ** 3 ** 2"), Ok(513));
+        assert_eq!(eval("-2 ** 2"), Ok(-4));
+        assert_eq!(eval("(-2) ** 2"), Ok(4));
+        assert_eq!(
+            eval("2 ** -1").unwrap_err().to_string(),
+            "`2 ** -1` is undefined"
+        );
+        assert_eq!(eval("--3"), Ok(3));
+        assert_eq!(eval("1 << 4 | 3 & 1 ^ 2"), Ok(19));
+        assert_eq!(eval("~0"), Ok(-1));
+        assert_eq!(eval("1 + 1 == 2 && 3 < 2 || !false"), Ok(1));
+        assert_eq!(eval("1 <= 1 && 2 >= 3"), Ok(0));
+        assert_eq!(eval("1 != 2"), Ok(1));
+        assert_eq!(
+            parse("-x"),
+            Ok(Expression::Unary {
+                op: UnaryOperation::Neg,
+                operand: Box::new(Expression::Var(String::from("x"))),
+            })
+        );
+    }
+
+    #[test]
+    fn test_if() {
+        let eval = |input| eval(parse(input).unwrap());
+        assert_eq!(eval("if 1 < 2 then 10 else 20"), Ok(10));
+        assert_eq!(eval("if false then 1 / 0 else 2 + 3"), Ok(5));
+        assert_eq!(eval("let x = -5 in 1 + if x < 0 then -x else x"), Ok(6));
+        assert_eq!(
+            error("if 1 then 2"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedEnd { expected: "`else`" },
+                offset: 11,
+            }
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(parse("x_1"), Ok(Expression::Var(String::from("x_1"))));
+        assert_eq!(
+            parse("let x = 2 in x * x"),
+            Ok(Expression::Let {
+                name: String::from("x"),
+                value: Box::new(Expression::Value(2)),
+                body: Box::new(Expression::Op {
+                    op: Operation::Mul,
+                    left: Box::new(Expression::Var(String::from("x"))),
+                    right: Box::new(Expression::Var(String::from("x"))),
+                }),
+            })
+        );
+        assert_eq!(
+            eval(parse("1 + let x = 2 in let x = x * 10 in x + 1").unwrap()),
+            Ok(22)
+        );
+        assert_eq!(
+            error("let 1 = 2 in 3"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedToken {
+                    found: String::from("1"),
+                    expected: "a variable name"
+                },
+                offset: 4,
+            }
+        );
+        assert_eq!(
+            error("let x = 2"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedEnd { expected: "`in`" },
+                offset: 9,
+            }
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            parse("fn sq(x) = x * x; sq(3)"),
+            Ok(Expression::Fn {
+                name: String::from("sq"),
+                params: vec![String::from("x")],
+                definition: Box::new(parse("x * x").unwrap()),
+                body: Box::new(Expression::Call {
+                    name: String::from("sq"),
+                    args: vec![Expression::Value(3)],
+                }),
+            })
+        );
+        assert_eq!(
+            eval(parse("fn sq(x) = x * x; sq(3) + sq(4)").unwrap()),
+            Ok(25)
+        );
+        assert_eq!(
+            eval(parse("fn f() = 1; fn g(a, b) = a - b; g(f(), max(2, 3)) * 2").unwrap()),
+            Ok(-4)
+        );
+        assert_eq!(
+            error("fn f(x = 1; f(1)"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedToken {
+                    found: String::from("="),
+                    expected: "`,` or `)`"
+                },
+                offset: 7,
+            }
+        );
+        assert_eq!(
+            error("fn f(1) = 1; f(1)").kind,
+            ParseErrorKind::UnexpectedToken {
+                found: String::from("1"),
+                expected: "a parameter name"
+            }
+        );
+        assert_eq!(
+            error("fn f(x) = x f(1)").kind,
+            ParseErrorKind::UnexpectedToken {
+                found: String::from("f"),
+                expected: "`;`"
+            }
+        );
+        assert_eq!(
+            error("1 + f(2, 3"),
+            ParseError {
+                kind: ParseErrorKind::UnclosedParenthesis,
+                offset: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("1 + $").kind,
+            ParseErrorKind::UnexpectedCharacter('$')
+        );
+        assert_eq!(error("1 + $").offset, 4);
+        assert_eq!(
+            error("1 + * 2"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedToken {
+                    found: String::from("*"),
+                    expected: "an expression"
+                },
+                offset: 4,
+            }
+        );
+        assert_eq!(
+            error("1 +"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedEnd {
+                    expected: "an expression"
+                },
+                offset: 3,
+            }
+        );
+        assert_eq!(
+            error("9223372036854775808").kind,
+            ParseErrorKind::NumberOutOfRange
+        );
+        assert_eq!(
+            error("99999999999999999999").kind,
+            ParseErrorKind::NumberOutOfRange
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_parentheses() {
+        assert_eq!(
+            error("2 * (3 + 4"),
+            ParseError {
+                kind: ParseErrorKind::UnclosedParenthesis,
+                offset: 4,
+            }
+        );
+        assert_eq!(
+            error("(3 + 4))"),
+            ParseError {
+                kind: ParseErrorKind::UnmatchedParenthesis,
+                offset: 7,
+            }
+        );
+        assert_eq!(error(")").kind, ParseErrorKind::UnmatchedParenthesis);
+    }
+
+    #[test]
+    fn test_trailing_input() {
+        assert_eq!(
+            error("1 + 2 3"),
+            ParseError {
+                kind: ParseErrorKind::TrailingInput,
+                offset: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_too_deep() {
+        assert_eq!(
+            error(&"(".repeat(1_000_000)),
+            ParseError {
+                kind: ParseErrorKind::TooDeep,
+                offset: MAX_DEPTH,
+            }
+        );
+        assert_eq!(error(&"-".repeat(1_000_000)).kind, ParseErrorKind::TooDeep);
+        assert_eq!(
+            error(&format!("{}1", "2 ** ".repeat(1_000_000))).kind,
+            ParseErrorKind::TooDeep
+        );
+        assert_eq!(
+            error(&"let x = ".repeat(1_000_000)).to_string(),
+            format!(
+                "expression is nested too deeply at offset {}",
+                8 * MAX_DEPTH
+            )
+        );
+        //the depth is counted per path, long sequences and deep nesting within the limit are fine
+        let nested = format!(
+            "{}1{}",
+            "(".repeat(MAX_DEPTH - 1),
+            ")".repeat(MAX_DEPTH - 1)
+        );
+        assert_eq!(eval(parse(&nested).unwrap()), Ok(1));
+        let sum = vec!["1"; 1_000_000].join(" + ");
+        assert_eq!(eval(parse(&sum).unwrap()), Ok(1_000_000));
+    }
+}
@@ -0,0 +1,164 @@
+//a `Path` locates a subexpression by the steps taken from the root of an `Expression` tree
+
+use std::fmt;
+
+use crate::Expression;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Step {
+    //the `left` or `right` operand of an `Op`
+    Left,
+    Right,
+    //the bound `value` or the `body` of a `Let`
+    Value,
+    Body,
+    //the operand of a `Unary`
+    Operand,
+    //the three branches of an `If`
+    Cond,
+    Then,
+    Else,
+    //the `definition` of a `Fn`, its `body` is reached by `Body`
+    Definition,
+    //an argument of a `Call`, numbered from 0
+    Arg(usize),
+}
+
+//an empty path refers to the root of the tree
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Path(pub Vec<Step>);
+
+impl Path {
+    pub fn root() -> Path {
+        Path::default()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.0
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Step::Left => "left",
+            Step::Right => "right",
+            Step::Value => "value",
+            Step::Body => "body",
+            Step::Operand => "operand",
+            Step::Cond => "cond",
+            Step::Then => "then",
+            Step::Else => "else",
+            Step::Definition => "definition",
+            Step::Arg(i) => return write!(f, "arg{i}"),
+        };
+        f.write_str(name)
+    }
+}
+
+//e.g. `right.right.left`, or `root` for the empty path
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return f.write_str("root");
+        }
+        for (i, step) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{step}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<N> Expression<N> {
+    //returns the subexpression at the given path, or `None` if the path does not exist in this tree
+    pub fn at(&self, path: &Path) -> Option<&Expression<N>> {
+        path.0
+            .iter()
+            .try_fold(self, |expression, step| match (expression, step) {
+                (Expression::Op { left, .. }, Step::Left) => Some(&**left),
+                (Expression::Op { right, .. }, Step::Right) => Some(&**right),
+                (Expression::Let { value, .. }, Step::Value) => Some(&**value),
+                (Expression::Let { body, .. }, Step::Body) => Some(&**body),
+                (Expression::Unary { operand, .. }, Step::Operand) => Some(&**operand),
+                (Expression::If { cond, .. }, Step::Cond) => Some(&**cond),
+                (Expression::If { then, .. }, Step::Then) => Some(&**then),
+                (Expression::If { otherwise, .. }, Step::Else) => Some(&**otherwise),
+                (Expression::Fn { definition, .. }, Step::Definition) => Some(&**definition),
+                (Expression::Fn { body, .. }, Step::Body) => Some(&**body),
+                (Expression::Call { args, .. }, Step::Arg(i)) => args.get(*i),
+                _ => None,
+            })
+    }
+
+    pub fn at_mut(&mut self, path: &Path) -> Option<&mut Expression<N>> {
+        path.0
+            .iter()
+            .try_fold(self, |expression, step| match (expression, step) {
+                (Expression::Op { left, .. }, Step::Left) => Some(&mut **left),
+                (Expression::Op { right, .. }, Step::Right) => Some(&mut **right),
+                (Expression::Let { value, .. }, Step::Value) => Some(&mut **value),
+                (Expression::Let { body, .. }, Step::Body) => Some(&mut **body),
+                (Expression::Unary { operand, .. }, Step::Operand) => Some(&mut **operand),
+                (Expression::If { cond, .. }, Step::Cond) => Some(&mut **cond),
+                (Expression::If { then, .. }, Step::Then) => Some(&mut **then),
+                (Expression::If { otherwise, .. }, Step::Else) => Some(&mut **otherwise),
+                (Expression::Fn { definition, .. }, Step::Definition) => Some(&mut **definition),
+                (Expression::Fn { body, .. }, Step::Body) => Some(&mut **body),
+                (Expression::Call { args, .. }, Step::Arg(i)) => args.get_mut(*i),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Path, Step};
+    use crate::{parse, Expression};
+
+    #[test]
+    fn test_at() {
+        let expr = parse("(10 * 9) + let x = 5 in x * (3 - 4)").unwrap();
+        let path = Path(vec![Step::Right, Step::Body, Step::Right]);
+        assert_eq!(expr.at(&path), Some(&parse("3 - 4").unwrap()));
+        assert_eq!(
+            expr.at(&Path(vec![Step::Right, Step::Value])),
+            Some(&Expression::Value(5))
+        );
+        assert_eq!(expr.at(&Path::root()), Some(&expr));
+        assert_eq!(expr.at(&Path(vec![Step::Left, Step::Body])), None);
+
+        let call = parse("fn f(x) = x; 1 + f(2, y)").unwrap();
+        assert_eq!(
+            call.at(&Path(vec![Step::Body, Step::Right, Step::Arg(1)])),
+            Some(&Expression::Var(String::from("y")))
+        );
+        assert_eq!(
+            call.at(&Path(vec![Step::Body, Step::Right, Step::Arg(2)])),
+            None
+        );
+
+        let mut expr = expr;
+        *expr.at_mut(&path).unwrap() = Expression::Value(-1);
+        assert_eq!(expr, parse("(10 * 9) + let x = 5 in x * -1").unwrap());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Path::root().to_string(), "root");
+        assert_eq!(
+            Path(vec![Step::Right, Step::Body, Step::Left]).to_string(),
+            "right.body.left"
+        );
+        assert_eq!(
+            Path(vec![Step::Body, Step::Arg(1)]).to_string(),
+            "body.arg1"
+        );
+    }
+}
@@ -0,0 +1,506 @@
+//property tests on random expression trees
+//`expression()` generates trees with every kind of node, values near the boundaries of i64 and names that are often
+//unbound or undefined, so that evaluation fails in all possible ways
+//the results of `eval_with` are compared with `Reference`, a separate, recursive evaluator that computes with i128,
+//so an operation overflows exactly if its mathematical result is outside of the range of i64,
+//and with the other ways to evaluate a tree: `eval_ref`, the compiled `Program` and `trace`
+//most random trees are not well-typed, so the reference is compared with an `Evaluator` that skips `type_check`,
+//which treats booleans as the numbers 0 and 1
+//any panic, e.g. from an unchecked operation, fails the tests as well
+
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use crate::{
+    compile, eval_ref, eval_with, simplify, trace, type_check, ArithmeticMode, Budget, Builtin,
+    Environment, EvalErrorKind, EvalOptions, Evaluator, Expression, Number, Operation, Type,
+    UnaryOperation,
+};
+
+const MODES: [ArithmeticMode; 3] = [
+    ArithmeticMode::Checked,
+    ArithmeticMode::Wrapping,
+    ArithmeticMode::Saturating,
+];
+
+//small enough that a recursive function cannot make a test case slow
+const MAX_CALL_DEPTH: usize = 4;
+
+//mostly small numbers, so shifts and exponents are often in range, and the values at which operations overflow
+fn value() -> impl Strategy<Value = i64> {
+    prop_oneof![
+        4 => -4i64..=70,
+        2 => select(vec![i64::MIN, i64::MIN + 1, i64::MAX, i64::MAX - 1, 1 << 32, -(1 << 32)]),
+        1 => any::<i64>(),
+    ]
+}
+
+//`z` is not bound in the environment, see `globals`
+fn variable() -> impl Strategy<Value = String> {
+    prop_oneof![4 => select(vec!["x", "y"]), 1 => Just("z")].prop_map(String::from)
+}
+
+//`abs` shadows the built-in function, `h` is never defined
+fn function() -> impl Strategy<Value = String> {
+    select(vec!["f", "g", "abs"]).prop_map(String::from)
+}
+
+fn callee() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => select(vec!["f", "g", "abs", "min", "max", "gcd"]),
+        1 => Just("h"),
+    ]
+    .prop_map(String::from)
+}
+
+fn expression() -> impl Strategy<Value = Expression> {
+    let leaf = prop_oneof![
+        3 => value().prop_map(Expression::Value),
+        1 => any::<bool>().prop_map(Expression::Bool),
+        3 => variable().prop_map(Expression::Var),
+    ];
+    leaf.prop_recursive(6, 48, 3, |inner| {
+        prop_oneof![
+            3 => (select(Operation::ALL.to_vec()), inner.clone(), inner.clone()).prop_map(
+                |(op, left, right)| Expression::Op {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            ),
+            1 => (
+                select(UnaryOperation::ALL.to_vec()),
+                inner.clone(),
+            )
+                .prop_map(|(op, operand)| Expression::Unary {
+                    op,
+                    operand: Box::new(operand),
+                }),
+            1 => (variable(), inner.clone(), inner.clone()).prop_map(|(name, value, body)| {
+                Expression::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                }
+            }),
+            1 => (inner.clone(), inner.clone(), inner.clone()).prop_map(
+                |(cond, then, otherwise)| Expression::If {
+                    cond: Box::new(cond),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                }
+            ),
+            1 => (
+                function(),
+                prop::collection::vec(variable(), 0..3),
+                inner.clone(),
+                inner.clone(),
+            )
+                .prop_map(|(name, params, definition, body)| Expression::Fn {
+                    name,
+                    params,
+                    definition: Box::new(definition),
+                    body: Box::new(body),
+                }),
+            1 => (callee(), prop::collection::vec(inner, 0..3))
+                .prop_map(|(name, args)| Expression::Call { name, args }),
+        ]
+    })
+}
+
+fn globals() -> impl Strategy<Value = Vec<(String, i64)>> {
+    (value(), value()).prop_map(|(x, y)| vec![(String::from("x"), x), (String::from("y"), y)])
+}
+
+fn environment(globals: &[(String, i64)]) -> Environment {
+    let mut env = Environment::new();
+    for (name, value) in globals {
+        env.bind(name.clone(), *value);
+    }
+    env
+}
+
+fn options(mode: ArithmeticMode) -> EvalOptions {
+    EvalOptions {
+        mode,
+        max_call_depth: MAX_CALL_DEPTH,
+        ..EvalOptions::default()
+    }
+}
+
+//converts an exact result to i64 as `mode` prescribes, `None` if it overflows in `Checked` mode
+fn fit(value: i128, mode: ArithmeticMode) -> Option<i64> {
+    match mode {
+        ArithmeticMode::Checked => i64::try_from(value).ok(),
+        ArithmeticMode::Wrapping => Some(value as i64),
+        ArithmeticMode::Saturating => Some(value.clamp(i64::MIN.into(), i64::MAX.into()) as i64),
+    }
+}
+
+//`base ** exponent` can be too large even for i128, so the power is computed exactly as long as it fits,
+//and modulo 2^128 (which gives the wrapped i64 result as well) for the `Wrapping` mode
+fn pow(base: i64, exponent: u64, mode: ArithmeticMode) -> Option<i64> {
+    let (mut exact, mut wrapped) = (Some(1i128), 1i128);
+    let (mut base_exact, mut base_wrapped) = (Some(i128::from(base)), i128::from(base));
+    let mut exponent_left = exponent;
+    while exponent_left > 0 {
+        if exponent_left & 1 == 1 {
+            exact = exact.zip(base_exact).and_then(|(a, b)| a.checked_mul(b));
+            wrapped = wrapped.wrapping_mul(base_wrapped);
+        }
+        base_exact = base_exact.and_then(|b| b.checked_mul(b));
+        base_wrapped = base_wrapped.wrapping_mul(base_wrapped);
+        exponent_left >>= 1;
+    }
+    match (exact, mode) {
+        (Some(exact), _) => fit(exact, mode),
+        (None, ArithmeticMode::Checked) => None,
+        (None, ArithmeticMode::Wrapping) => Some(wrapped as i64),
+        (None, ArithmeticMode::Saturating) if base < 0 && exponent % 2 == 1 => Some(i64::MIN),
+        (None, ArithmeticMode::Saturating) => Some(i64::MAX),
+    }
+}
+
+fn binary(
+    op: Operation,
+    left: i64,
+    right: i64,
+    mode: ArithmeticMode,
+) -> Result<i64, EvalErrorKind> {
+    let (a, b) = (i128::from(left), i128::from(right));
+    let out_of_domain = EvalErrorKind::OutOfDomain { op, left, right };
+    let exact = match op {
+        Operation::Add => a + b,
+        Operation::Sub => a - b,
+        Operation::Mul => a * b,
+        Operation::Div | Operation::Mod if b == 0 => return Err(EvalErrorKind::DivisionByZero),
+        Operation::Div => a / b,
+        //the remainder always fits, but it is computed together with the quotient, which may not
+        Operation::Mod if mode == ArithmeticMode::Checked && fit(a / b, mode).is_none() => {
+            return Err(EvalErrorKind::Overflow { op, left, right })
+        }
+        Operation::Mod => a % b,
+        Operation::Pow if b < 0 => return Err(out_of_domain),
+        Operation::Pow => {
+            return pow(left, right as u64, mode).ok_or(EvalErrorKind::Overflow { op, left, right })
+        }
+        Operation::BitAnd => a & b,
+        Operation::BitOr => a | b,
+        Operation::BitXor => a ^ b,
+        Operation::Shl | Operation::Shr
+            if mode != ArithmeticMode::Wrapping && !(0..64).contains(&b) =>
+        {
+            return Err(out_of_domain)
+        }
+        //the bits shifted out of an i64 are lost, shifting never overflows
+        Operation::Shl => return Ok((a << b.rem_euclid(64)) as i64),
+        Operation::Shr => return Ok((a >> b.rem_euclid(64)) as i64),
+        Operation::Eq => (a == b).into(),
+        Operation::Ne => (a != b).into(),
+        Operation::Lt => (a < b).into(),
+        Operation::Le => (a <= b).into(),
+        Operation::Gt => (a > b).into(),
+        Operation::Ge => (a >= b).into(),
+        Operation::And => (a != 0 && b != 0).into(),
+        Operation::Or => (a != 0 || b != 0).into(),
+    };
+    fit(exact, mode).ok_or(EvalErrorKind::Overflow { op, left, right })
+}
+
+fn unary(op: UnaryOperation, operand: i64, mode: ArithmeticMode) -> Result<i64, EvalErrorKind> {
+    match op {
+        UnaryOperation::Neg => {
+            fit(-i128::from(operand), mode).ok_or(EvalErrorKind::UnaryOverflow { op, operand })
+        }
+        UnaryOperation::Not => Ok((operand == 0).into()),
+        UnaryOperation::BitNot => Ok(!operand),
+    }
+}
+
+fn builtin(builtin: Builtin, args: &[i64], mode: ArithmeticMode) -> Result<i64, EvalErrorKind> {
+    //the absolute value only overflows for `i64::MIN`, which is then the operand of the negation
+    let abs = |value: i128| {
+        fit(value.abs(), mode).ok_or(EvalErrorKind::UnaryOverflow {
+            op: UnaryOperation::Neg,
+            operand: i64::MIN,
+        })
+    };
+    match (builtin, args) {
+        (Builtin::Abs, &[x]) => abs(x.into()),
+        (Builtin::Min, &[x, y]) => Ok(x.min(y)),
+        (Builtin::Max, &[x, y]) => Ok(x.max(y)),
+        (Builtin::Gcd, &[x, y]) => {
+            let (mut x, mut y) = (i128::from(x).abs(), i128::from(y).abs());
+            while y != 0 {
+                (x, y) = (y, x % y);
+            }
+            abs(x)
+        }
+        _ => unreachable!("the arity is checked before"),
+    }
+}
+
+struct Definition<'e> {
+    name: &'e str,
+    params: &'e [String],
+    definition: &'e Expression,
+    //indices in `Reference::definitions` of the functions the definition can call, itself included
+    scope: Vec<usize>,
+}
+
+//a straightforward recursive evaluator, only suitable for small trees
+struct Reference<'e> {
+    globals: Vec<(String, i64)>,
+    mode: ArithmeticMode,
+    definitions: Vec<Definition<'e>>,
+    depth: usize,
+}
+
+impl<'e> Reference<'e> {
+    fn evaluate(
+        &mut self,
+        e: &'e Expression,
+        locals: &mut Vec<(String, i64)>,
+        scope: &mut Vec<usize>,
+    ) -> Result<i64, EvalErrorKind> {
+        match e {
+            Expression::Value(value) => Ok(*value),
+            Expression::Bool(value) => Ok((*value).into()),
+            Expression::Var(name) => locals
+                .iter()
+                .rev()
+                .chain(self.globals.iter().rev())
+                .find(|(bound, _)| bound == name)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| EvalErrorKind::UnboundVariable(name.clone())),
+            Expression::Let { name, value, body } => {
+                let value = self.evaluate(value, locals, scope)?;
+                locals.push((name.clone(), value));
+                let result = self.evaluate(body, locals, scope);
+                locals.pop();
+                result
+            }
+            Expression::Op { op, left, right } => {
+                let left = self.evaluate(left, locals, scope)?;
+                match op {
+                    Operation::And if left == 0 => Ok(0),
+                    Operation::Or if left != 0 => Ok(1),
+                    _ => {
+                        let right = self.evaluate(right, locals, scope)?;
+                        binary(*op, left, right, self.mode)
+                    }
+                }
+            }
+            Expression::Unary { op, operand } => {
+                let operand = self.evaluate(operand, locals, scope)?;
+                unary(*op, operand, self.mode)
+            }
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                if self.evaluate(cond, locals, scope)? != 0 {
+                    self.evaluate(then, locals, scope)
+                } else {
+                    self.evaluate(otherwise, locals, scope)
+                }
+            }
+            Expression::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => {
+                let index = self.definitions.len();
+                scope.push(index);
+                self.definitions.push(Definition {
+                    name,
+                    params,
+                    definition,
+                    scope: scope.clone(),
+                });
+                let result = self.evaluate(body, locals, scope);
+                scope.pop();
+                result
+            }
+            Expression::Call { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg, locals, scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let wrong_arity = |expected| EvalErrorKind::WrongArity {
+                    name: name.clone(),
+                    expected,
+                    found: args.len(),
+                };
+                let Some(&index) = scope
+                    .iter()
+                    .rev()
+                    .find(|&&index| self.definitions[index].name == name)
+                else {
+                    return match Builtin::from_name(name) {
+                        Some(function) if function.arity() == args.len() => {
+                            builtin(function, &args, self.mode)
+                        }
+                        Some(function) => Err(wrong_arity(function.arity())),
+                        None => Err(EvalErrorKind::UndefinedFunction(name.clone())),
+                    };
+                };
+                let function = &self.definitions[index];
+                if function.params.len() != args.len() {
+                    return Err(wrong_arity(function.params.len()));
+                }
+                if self.depth == MAX_CALL_DEPTH {
+                    return Err(EvalErrorKind::RecursionLimit(MAX_CALL_DEPTH));
+                }
+                let definition = function.definition;
+                let mut params = function.params.iter().cloned().zip(args).collect();
+                let mut function_scope = function.scope.clone();
+                self.depth += 1;
+                let result = self.evaluate(definition, &mut params, &mut function_scope);
+                self.depth -= 1;
+                result
+            }
+        }
+    }
+}
+
+fn reference(
+    e: &Expression,
+    globals: &[(String, i64)],
+    mode: ArithmeticMode,
+) -> Result<i64, EvalErrorKind> {
+    let mut reference = Reference {
+        globals: globals.to_vec(),
+        mode,
+        definitions: Vec::new(),
+        depth: 0,
+    };
+    reference.evaluate(e, &mut Vec::new(), &mut Vec::new())
+}
+
+proptest! {
+    #[test]
+    fn test_same_as_reference(e in expression(), globals in globals(), mode in select(MODES.to_vec())) {
+        let mut env = environment(&globals);
+        let result = Evaluator::new(&mut env, &options(mode)).evaluate(&e);
+        let expected = reference(&e, &globals, mode);
+        prop_assert_eq!(result.clone().map_err(|error| error.kind), expected);
+        //`eval_with` evaluates the same way, unless the tree is not well-typed
+        let checked = match type_check(&e).errors.into_iter().next() {
+            Some(error) => Err(error.into()),
+            None => result,
+        };
+        prop_assert_eq!(eval_with(e, &mut env, &options(mode)), checked);
+    }
+
+    //a well-typed boolean expression evaluates to 0 or 1
+    #[test]
+    fn test_well_typed(e in expression(), globals in globals(), mode in select(MODES.to_vec())) {
+        let typing = type_check(&e);
+        let result = eval_with(e, &mut environment(&globals), &options(mode));
+        if typing.is_well_typed() {
+            let is_type_error = matches!(&result, Err(error) if matches!(error.kind, EvalErrorKind::Type(_)));
+            prop_assert!(!is_type_error);
+            if typing.root() == Type::Bool {
+                prop_assert!(matches!(result, Ok(0 | 1) | Err(_)));
+            }
+        }
+    }
+
+    //simplifying neither removes type errors nor introduces them
+    #[test]
+    fn test_simplify_keeps_typing(e in expression()) {
+        let well_typed = type_check(&e).is_well_typed();
+        prop_assert_eq!(type_check(&simplify(e)).is_well_typed(), well_typed);
+    }
+
+    #[test]
+    fn test_operations(op in select(Operation::ALL.to_vec()), left in value(), right in value()) {
+        for mode in MODES {
+            let e = Expression::Op {
+                op,
+                left: Box::new(Expression::Value(left)),
+                right: Box::new(Expression::Value(right)),
+            };
+            let result = Evaluator::new(&mut Environment::new(), &options(mode)).evaluate(&e);
+            let expected = match op {
+                Operation::And if left == 0 => Ok(0),
+                Operation::Or if left != 0 => Ok(1),
+                _ => binary(op, left, right, mode),
+            };
+            prop_assert_eq!(result.map_err(|error| error.kind), expected);
+        }
+    }
+
+    #[test]
+    fn test_builtins(name in callee(), x in value(), y in value()) {
+        let Some(function) = Builtin::from_name(&name) else {
+            return Ok(());
+        };
+        let args = [x, y];
+        let args = &args[..function.arity()];
+        for mode in MODES {
+            prop_assert_eq!(function.apply(args, mode), builtin(function, args, mode));
+        }
+    }
+
+    //the other evaluators agree with `eval_with` on the errors as well, including their paths
+    #[test]
+    fn test_same_as_other_evaluators(e in expression(), globals in globals(), mode in select(MODES.to_vec())) {
+        let mut env = environment(&globals);
+        let options = options(mode);
+        let result = eval_with(e.clone(), &mut env, &options);
+        prop_assert_eq!(&eval_ref(&e, &mut env, &options), &result);
+        prop_assert_eq!(&compile(&e).run_with(&env, &options), &result);
+        let trace = trace(&e, &mut env, &options);
+        prop_assert_eq!(&trace.result, &result);
+        //printing the trace locates the failing subexpression
+        trace.to_string();
+        prop_assert_eq!(env.lookup("x"), Some(&globals[0].1));
+        prop_assert_eq!(env.lookup("z"), None);
+    }
+
+    //the compiled `Program` limits the depth exactly like `eval_with`
+    #[test]
+    fn test_same_max_depth(e in expression(), globals in globals(), max_depth in 0usize..8) {
+        let mut env = environment(&globals);
+        let options = EvalOptions {
+            budget: Budget {
+                max_nodes: None,
+                max_depth: Some(max_depth),
+            },
+            ..options(ArithmeticMode::Checked)
+        };
+        let result = eval_with(e.clone(), &mut env, &options);
+        prop_assert_eq!(compile(&e).run_with(&env, &options), result);
+    }
+
+    //floating point numbers do not support all operations, and never overflow
+    #[test]
+    fn test_floats(e in expression(), globals in globals()) {
+        let e = e.map(f64::from_i64);
+        let mut env = Environment::new();
+        for (name, value) in globals {
+            env.bind(name, value as f64);
+        }
+        let options = options(ArithmeticMode::Checked);
+        let result = eval_ref(&e, &mut env, &options);
+        let overflowed = matches!(
+            &result,
+            Err(error) if matches!(
+                error.kind,
+                EvalErrorKind::Overflow { .. } | EvalErrorKind::UnaryOverflow { .. }
+            )
+        );
+        prop_assert!(!overflowed);
+        //NaN is not equal to itself
+        let same = |other: Result<f64, _>| match (&other, &result) {
+            (Ok(a), Ok(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+            _ => other == result,
+        };
+        prop_assert!(same(compile(&e).run_with(&env, &options)));
+        prop_assert!(same(trace(&e, &mut env, &options).result));
+    }
+}
@@ -0,0 +1,379 @@
+//an interactive read-eval-print loop: every line is parsed and evaluated, and the result or error is printed
+//lines starting with `:` are commands, see `HELP`
+
+use std::io::{self, BufRead, Write};
+
+use expression_evaluator::{
+    derive, eval_with, parse, simplify, to_dot, to_tree, trace, type_check, ArithmeticMode,
+    Environment, EvalOptions, Expression, Notation, Number, Type,
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+const PROMPT: &str = "> ";
+
+const HELP: &str = "\
+enter an expression to evaluate it, e.g. `(10 * 9) + (5 * (3 - 4))` or `let x = 3 in x * x`
+operators: + - * / % ** & | ^ << >> == != < <= > >= && || and the unary - ! ~
+conditionals: `if x < 0 then -x else x`, the condition is a boolean, e.g. `true`, `!false` or `1 == 2 || 3 >= 4`
+functions: `fn sq(x) = x * x; sq(3) + sq(4)`, built in: abs(x), min(x, y), max(x, y), gcd(x, y)
+commands:
+  :ast <expression>       print the parsed expression tree
+  :simplify <expression>  print the expression after constant folding and simplification
+  :derive <var> <expr>    print the derivative of the expression with respect to the variable
+  :print <expression>     print the expression with minimal parentheses, fully parenthesized and in prefix notation
+  :trace <expression>     print every evaluation step, up to the step that fails
+  :check <expression>     print the type of the expression, `number` or `boolean`, or its type errors
+  :tree <expression>      draw the expression tree
+  :dot <expression>       print the expression tree as a Graphviz DOT graph
+  :mode [<mode>]          show or set the arithmetic mode: `checked`, `wrapping` or `saturating`
+  :type [<type>]          show or set the number type: `int`, `float`, `bigint` or `rational`
+  :help                   print this message
+  :quit                   exit the REPL";
+
+enum Command<'a> {
+    Eval(&'a str),
+    Ast(&'a str),
+    Simplify(&'a str),
+    Print(&'a str),
+    Derive(&'a str, &'a str),
+    Trace(&'a str),
+    Check(&'a str),
+    Tree(&'a str),
+    Dot(&'a str),
+    Mode(&'a str),
+    Type(&'a str),
+    Help,
+    Quit,
+}
+
+impl<'a> Command<'a> {
+    fn parse(line: &'a str) -> Result<Command<'a>, String> {
+        let Some(command) = line.strip_prefix(':') else {
+            return Ok(Command::Eval(line));
+        };
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        match name {
+            "ast" => Ok(Command::Ast(argument)),
+            "simplify" => Ok(Command::Simplify(argument)),
+            "print" => Ok(Command::Print(argument)),
+            "derive" => match argument.trim_start().split_once(char::is_whitespace) {
+                Some((var, expression)) => Ok(Command::Derive(var, expression)),
+                None => Err(String::from("usage: `:derive <var> <expression>`")),
+            },
+            "trace" => Ok(Command::Trace(argument)),
+            "check" => Ok(Command::Check(argument)),
+            "tree" => Ok(Command::Tree(argument)),
+            "dot" => Ok(Command::Dot(argument)),
+            "mode" => Ok(Command::Mode(argument.trim())),
+            "type" => Ok(Command::Type(argument.trim())),
+            "help" | "h" => Ok(Command::Help),
+            "quit" | "q" => Ok(Command::Quit),
+            _ => Err(format!(
+                "unknown command `:{name}`, type `:help` for a list of commands"
+            )),
+        }
+    }
+}
+
+//the number types expressions can be evaluated in
+#[derive(Debug, Clone, Copy, Default)]
+enum NumberType {
+    #[default]
+    Int,
+    Float,
+    BigInt,
+    Rational,
+}
+
+//the settings that persist between lines
+#[derive(Default)]
+struct Session {
+    options: EvalOptions,
+    number_type: NumberType,
+}
+
+impl Session {
+    //executes a single line, returns `None` if the REPL should stop
+    fn execute(&mut self, line: &str) -> Option<String> {
+        let output = match Command::parse(line) {
+            Ok(Command::Eval(input)) => match parse(input) {
+                Ok(expression) => match self.number_type {
+                    NumberType::Int => self.evaluate::<i64>(expression),
+                    NumberType::Float => self.evaluate::<f64>(expression),
+                    NumberType::BigInt => self.evaluate::<BigInt>(expression),
+                    NumberType::Rational => self.evaluate::<BigRational>(expression),
+                },
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Ast(input)) => match parse(input) {
+                Ok(expression) => format!("{expression:#?}"),
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Simplify(input)) => match parse(input) {
+                Ok(expression) => simplify(expression).to_string(),
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Print(input)) => match parse(input) {
+                Ok(expression) => [Notation::Minimal, Notation::Parenthesized, Notation::Prefix]
+                    .map(|notation| expression.display(notation).to_string())
+                    .join("\n"),
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Derive(var, input)) => match parse(input) {
+                Ok(expression) => match derive(&expression, var) {
+                    Ok(derivative) => derivative.to_string(),
+                    Err(error) => format!("error: {error}"),
+                },
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Trace(input)) => match parse(input) {
+                Ok(expression) => match self.number_type {
+                    NumberType::Int => self.trace::<i64>(expression),
+                    NumberType::Float => self.trace::<f64>(expression),
+                    NumberType::BigInt => self.trace::<BigInt>(expression),
+                    NumberType::Rational => self.trace::<BigRational>(expression),
+                },
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Check(input)) => match parse(input) {
+                Ok(expression) => {
+                    let typing = type_check(&expression);
+                    if typing.is_well_typed() {
+                        typing.root().to_string()
+                    } else {
+                        typing
+                            .errors
+                            .iter()
+                            .map(|error| format!("type error: {error}"))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    }
+                }
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Tree(input)) => match parse(input) {
+                Ok(expression) => to_tree(&expression),
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Dot(input)) => match parse(input) {
+                Ok(expression) => to_dot(&expression),
+                Err(error) => format!("parse error: {error}"),
+            },
+            Ok(Command::Mode(mode)) => self.set_mode(mode),
+            Ok(Command::Type(number_type)) => self.set_number_type(number_type),
+            Ok(Command::Help) => HELP.to_string(),
+            Ok(Command::Quit) => return None,
+            Err(message) => message,
+        };
+        Some(output)
+    }
+
+    fn evaluate<N: Number>(&self, expression: Expression) -> String {
+        let expression = expression.map(N::from_i64);
+        let boolean = type_check(&expression).root() == Type::Bool;
+        match eval_with(expression, &mut Environment::new(), &self.options) {
+            Ok(result) if boolean => result.is_true().to_string(),
+            Ok(result) => result.to_string(),
+            Err(error) => format!("error: {error}"),
+        }
+    }
+
+    fn trace<N: Number>(&self, expression: Expression) -> String {
+        let expression = expression.map(N::from_i64);
+        trace(&expression, &mut Environment::new(), &self.options).to_string()
+    }
+
+    fn set_mode(&mut self, mode: &str) -> String {
+        self.options.mode = match mode {
+            "" => return format!("{:?}", self.options.mode).to_lowercase(),
+            "checked" => ArithmeticMode::Checked,
+            "wrapping" => ArithmeticMode::Wrapping,
+            "saturating" => ArithmeticMode::Saturating,
+            _ => {
+                return format!(
+                    "unknown mode `{mode}`, expected `checked`, `wrapping` or `saturating`"
+                )
+            }
+        };
+        format!("arithmetic mode set to {mode}")
+    }
+
+    fn set_number_type(&mut self, number_type: &str) -> String {
+        self.number_type = match number_type {
+            "" => return format!("{:?}", self.number_type).to_lowercase(),
+            "int" => NumberType::Int,
+            "float" => NumberType::Float,
+            "bigint" => NumberType::BigInt,
+            "rational" => NumberType::Rational,
+            _ => {
+                return format!(
+                    "unknown type `{number_type}`, expected `int`, `float`, `bigint` or `rational`"
+                )
+            }
+        };
+        format!("number type set to {number_type}")
+    }
+}
+
+//runs the REPL until `:quit` or the end of the input
+pub fn run(input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut lines = input.lines();
+    let mut session = Session::default();
+    loop {
+        write!(output, "{PROMPT}")?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            return writeln!(output);
+        };
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match session.execute(line) {
+            Some(result) => writeln!(output, "{result}")?,
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run, Session};
+
+    #[test]
+    fn test_execute() {
+        let mut session = Session::default();
+        let mut execute = |line| session.execute(line);
+        assert_eq!(
+            execute("(10 * 9) + (5 * (3 - 4))"),
+            Some(String::from("85"))
+        );
+        assert_eq!(
+            execute("99 / 0"),
+            Some(String::from("error: division by zero"))
+        );
+        assert_eq!(
+            execute("1 +"),
+            Some(String::from(
+                "parse error: unexpected end of input, expected an expression at offset 3"
+            ))
+        );
+        assert_eq!(
+            execute("fn sq(x) = x * x; sq(3) + max(4, 5)"),
+            Some(String::from("14"))
+        );
+        assert_eq!(
+            execute("sq(3)"),
+            Some(String::from("error: undefined function `sq`"))
+        );
+        assert_eq!(execute("1 < 2 && !false"), Some(String::from("true")));
+        assert_eq!(
+            execute("true + 1"),
+            Some(String::from(
+                "error: type error: expected a number, found a boolean at left"
+            ))
+        );
+        assert_eq!(
+            execute(":check fn f(n) = n == 0; f(1) || false"),
+            Some(String::from("boolean"))
+        );
+        assert_eq!(
+            execute(":check if 1 then true else 2"),
+            Some(String::from(
+                "type error: expected a boolean, found a number at cond\n\
+                 type error: expected a boolean, found a number at else"
+            ))
+        );
+        assert!(execute(":ast 1 + 2").unwrap().starts_with("Op {"));
+        assert_eq!(
+            execute(":simplify x * (2 - 1) + 0"),
+            Some(String::from("x"))
+        );
+        assert_eq!(
+            execute(":print (1 + 2) * -x"),
+            Some(String::from(
+                "(1 + 2) * -x\n(1 + 2) * (-x)\n(* (+ 1 2) (- x))"
+            ))
+        );
+        assert_eq!(
+            execute(":derive x x ** 3 + 2 * x"),
+            Some(String::from("3 * x ** 2 + 2"))
+        );
+        assert_eq!(
+            execute(":derive x 2 ** x"),
+            Some(String::from(
+                "error: cannot differentiate `**` with a variable exponent"
+            ))
+        );
+        assert_eq!(
+            execute(":trace 2 * (3 - 4)"),
+            Some(String::from("  2 * (3 - 4)\n= 2 * -1\n= -2"))
+        );
+        assert_eq!(
+            execute(":trace 1 + 99 / 0"),
+            Some(String::from("  1 + 99 / 0\n      ^^^^^^ division by zero"))
+        );
+        assert_eq!(
+            execute(":tree -(1 + x)"),
+            Some(String::from("-\n└── +\n    ├── 1\n    └── x"))
+        );
+        assert!(execute(":dot 1 + x")
+            .unwrap()
+            .starts_with("digraph expression {"));
+        assert!(execute(":foo")
+            .unwrap()
+            .starts_with("unknown command `:foo`"));
+        assert_eq!(execute(":quit"), None);
+    }
+
+    #[test]
+    fn test_mode() {
+        let mut session = Session::default();
+        assert_eq!(session.execute(":mode"), Some(String::from("checked")));
+        assert!(session
+            .execute("9223372036854775807 + 1")
+            .unwrap()
+            .starts_with("error: integer overflow"));
+        session.execute(":mode wrapping");
+        assert_eq!(
+            session.execute("9223372036854775807 + 1"),
+            Some(String::from("-9223372036854775808"))
+        );
+        session.execute(":mode saturating");
+        assert_eq!(
+            session.execute("9223372036854775807 + 1"),
+            Some(String::from("9223372036854775807"))
+        );
+        assert!(session
+            .execute(":mode foo")
+            .unwrap()
+            .starts_with("unknown mode"));
+    }
+
+    #[test]
+    fn test_number_type() {
+        let mut session = Session::default();
+        assert_eq!(session.execute(":type"), Some(String::from("int")));
+        assert_eq!(session.execute("7 / 2"), Some(String::from("3")));
+        session.execute(":type float");
+        assert_eq!(session.execute("7 / 2"), Some(String::from("3.5")));
+        session.execute(":type rational");
+        assert_eq!(session.execute("7 / 2"), Some(String::from("7/2")));
+        session.execute(":type bigint");
+        assert_eq!(
+            session.execute("9223372036854775807 + 1"),
+            Some(String::from("9223372036854775808"))
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut output = Vec::new();
+        run("1 + 2\n\n3 * 4\n:q\n5\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> 3\n> > 12\n> ");
+    }
+}
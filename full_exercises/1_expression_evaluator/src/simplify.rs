@@ -0,0 +1,481 @@
+//shrinks an `Expression` tree before it is evaluated:
+// - constant subtrees are folded into a single literal, e.g. `(10 * 9) + 1` becomes `91` and `1 < 2` becomes `true`
+// - variables bound to a constant by `let` are replaced by that constant
+// - conditionals and logical operations with a constant deciding operand are reduced to the branch that is taken
+// - algebraic identities are applied: `x + 0`, `x - 0`, `x * 1` and `x / 1` become `x`,
+//   `x * 0` and `x - x` become `0` (only for exact number types and if `x` is a value or a variable that is bound by
+//   an enclosing `let` or is a parameter, a free variable can be unbound)
+//function calls are kept, only their arguments and the definitions of functions are simplified
+//a subtree is never folded if evaluating it fails, e.g. `99 / 0` is kept so `eval` still reports the error
+//an expression that is not well-typed is kept as it is, folding could remove the subexpression with the type error,
+//e.g. the untaken branch of `if true then 1 else false`, so evaluation would no longer reject it
+//the simplified expression evaluates to the same result as the original one in every `ArithmeticMode`,
+//because folding uses checked arithmetic, which only succeeds when the other modes agree with it
+
+use std::mem;
+
+use crate::node::Shell;
+use crate::{type_check, ArithmeticMode, Expression, Node, Number, Operation, UnaryOperation};
+
+pub fn simplify<N: Number>(e: Expression<N>) -> Expression<N> {
+    if !type_check(&e).is_well_typed() {
+        return e;
+    }
+    simplify_tree(e)
+}
+
+//the steps of `simplify`, which works with explicit stacks like `eval`, so arbitrarily deep trees can be simplified
+enum Task<N> {
+    Simplify(Expression<N>),
+    //the operands are simplified
+    Op(Operation),
+    Unary(UnaryOperation),
+    //the condition is simplified, decides which branches are kept
+    If {
+        then: Expression<N>,
+        otherwise: Expression<N>,
+    },
+    //the value is simplified, decides whether it is substituted into the body
+    Let {
+        name: String,
+        body: Expression<N>,
+    },
+    //the body of a kept `let` is simplified, the variable is unbound
+    Unbind,
+    //the definition of a function is simplified with only its parameters bound, see `Expression::Fn`
+    Enter(Vec<String>),
+    Leave,
+    //the children are simplified, nothing else is folded
+    Build(Shell),
+}
+
+fn simplify_tree<N: Number>(e: Expression<N>) -> Expression<N> {
+    let mut pending = vec![Task::Simplify(e)];
+    let mut simplified = Vec::new();
+    //the variables that are bound by enclosing `let`s or are parameters
+    let mut bound = Vec::new();
+    //the variables bound outside of the definitions that are being simplified
+    let mut outer = Vec::new();
+    while let Some(task) = pending.pop() {
+        let e = match task {
+            Task::Simplify(e) => {
+                match e.into_node() {
+                    Node::Op { op, left, right } => {
+                        pending.push(Task::Op(op));
+                        pending.push(Task::Simplify(*right));
+                        pending.push(Task::Simplify(*left));
+                    }
+                    Node::Unary { op, operand } => {
+                        pending.push(Task::Unary(op));
+                        pending.push(Task::Simplify(*operand));
+                    }
+                    Node::If {
+                        cond,
+                        then,
+                        otherwise,
+                    } => {
+                        pending.push(Task::If {
+                            then: *then,
+                            otherwise: *otherwise,
+                        });
+                        pending.push(Task::Simplify(*cond));
+                    }
+                    Node::Let { name, value, body } => {
+                        pending.push(Task::Let { name, body: *body });
+                        pending.push(Task::Simplify(*value));
+                    }
+                    Node::Fn {
+                        name,
+                        params,
+                        definition,
+                        body,
+                    } => {
+                        pending.push(Task::Build(Shell::Fn(name, params.clone())));
+                        pending.push(Task::Simplify(*body));
+                        pending.push(Task::Leave);
+                        pending.push(Task::Simplify(*definition));
+                        pending.push(Task::Enter(params));
+                    }
+                    node => match node.split() {
+                        Ok((shell, children)) => {
+                            pending.push(Task::Build(shell));
+                            pending.extend(children.into_iter().rev().map(Task::Simplify));
+                        }
+                        Err(leaf) => simplified.push(leaf.into()),
+                    },
+                }
+                continue;
+            }
+            Task::Op(op) => {
+                let right = simplified.pop().expect("the right operand is simplified");
+                let left = simplified.pop().expect("the left operand is simplified");
+                simplify_op(op, left, right, &bound)
+            }
+            Task::Unary(op) => {
+                let operand = simplified.pop().expect("the operand is simplified");
+                let result = constant(&operand)
+                    .and_then(|value| N::apply_unary(op, &value, ArithmeticMode::Checked).ok());
+                match result {
+                    Some(result) => literal(result, op == UnaryOperation::Not),
+                    None => Expression::Unary {
+                        op,
+                        operand: Box::new(operand),
+                    },
+                }
+            }
+            Task::If { then, otherwise } => {
+                let cond = simplified.pop().expect("the condition is simplified");
+                match constant(&cond) {
+                    Some(cond) if cond.is_true() => pending.push(Task::Simplify(then)),
+                    Some(_) => pending.push(Task::Simplify(otherwise)),
+                    None => {
+                        simplified.push(cond);
+                        pending.push(Task::Build(Shell::If));
+                        pending.push(Task::Simplify(otherwise));
+                        pending.push(Task::Simplify(then));
+                    }
+                }
+                continue;
+            }
+            Task::Let { name, body } => {
+                let value = simplified.pop().expect("the value is simplified");
+                match constant(&value) {
+                    Some(_) => pending.push(Task::Simplify(substitute(body, &name, &value))),
+                    None => {
+                        simplified.push(value);
+                        bound.push(name);
+                        pending.push(Task::Unbind);
+                        pending.push(Task::Simplify(body));
+                    }
+                }
+                continue;
+            }
+            Task::Unbind => {
+                let name = bound.pop().expect("the variable was bound");
+                Shell::Let(name).build(&mut simplified)
+            }
+            Task::Enter(params) => {
+                outer.push(mem::replace(&mut bound, params));
+                continue;
+            }
+            Task::Leave => {
+                bound = outer.pop().expect("the definition was entered");
+                continue;
+            }
+            Task::Build(shell) => shell.build(&mut simplified),
+        };
+        simplified.push(e);
+    }
+    simplified.pop().expect("the root is simplified last")
+}
+
+//simplifies an operation of which both operands are already simplified
+fn simplify_op<N: Number>(
+    op: Operation,
+    left: Expression<N>,
+    right: Expression<N>,
+    bound: &[String],
+) -> Expression<N> {
+    match (op, constant(&left), constant(&right)) {
+        (_, Some(l), Some(r)) => {
+            if let Ok(result) = N::apply(op, &l, &r, ArithmeticMode::Checked) {
+                return literal(result, op.is_comparison() || op.is_logical());
+            }
+        }
+        //the right operand is never evaluated, so it cannot cause an error
+        (Operation::And, Some(l), _) if !l.is_true() => return Expression::Bool(false),
+        (Operation::Or, Some(l), _) if l.is_true() => return Expression::Bool(true),
+        _ => {}
+    }
+    let is = |e: &Expression<N>, n: i64| matches!(e, Expression::Value(v) if *v == N::from_i64(n));
+    match op {
+        Operation::Mul | Operation::Div if is(&right, 1) => left,
+        Operation::Mul if is(&left, 1) => right,
+        Operation::Add | Operation::Sub if N::EXACT && is(&right, 0) => left,
+        Operation::Add if N::EXACT && is(&left, 0) => right,
+        Operation::Mul
+            if N::EXACT
+                && (is(&left, 0) && is_total(&right, bound)
+                    || is(&right, 0) && is_total(&left, bound)) =>
+        {
+            Expression::Value(N::from_i64(0))
+        }
+        Operation::Sub if N::EXACT && left == right && is_total(&left, bound) => {
+            Expression::Value(N::from_i64(0))
+        }
+        _ => Expression::Op {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+    }
+}
+
+//the value of a literal
+//in a well-typed tree booleans only appear where booleans are expected, so they can be folded as the numbers 0 and 1
+fn constant<N: Number>(e: &Expression<N>) -> Option<N> {
+    match e {
+        Expression::Value(value) => Some(value.clone()),
+        Expression::Bool(value) => Some(N::from_bool(*value)),
+        _ => None,
+    }
+}
+
+//the literal for a folded value, `boolean` if the folded subtree has the type `Bool`
+fn literal<N: Number>(value: N, boolean: bool) -> Expression<N> {
+    if boolean {
+        Expression::Bool(value.is_true())
+    } else {
+        Expression::Value(value)
+    }
+}
+
+//whether evaluating `e` cannot fail, so it can be dropped from the tree
+//a variable can only be evaluated if it is bound
+fn is_total<N>(e: &Expression<N>, bound: &[String]) -> bool {
+    match e {
+        Expression::Value(_) => true,
+        Expression::Var(name) => bound.contains(name),
+        _ => false,
+    }
+}
+
+//replaces the free occurrences of the variable `name` in `e` with `value`, without recursion
+pub(crate) fn substitute<N: Clone>(
+    e: Expression<N>,
+    name: &str,
+    value: &Expression<N>,
+) -> Expression<N> {
+    enum Task<N> {
+        Substitute(Expression<N>),
+        //a subexpression in which `name` is not visible
+        Keep(Expression<N>),
+        Build(Shell),
+    }
+    let mut pending = vec![Task::Substitute(e)];
+    let mut substituted = Vec::new();
+    while let Some(task) = pending.pop() {
+        let e = match task {
+            Task::Substitute(e) => match e.into_node() {
+                Node::Var(var) if var == name => value.clone(),
+                //an inner binding with the same name shadows `name` in its body
+                Node::Let {
+                    name: inner,
+                    value: inner_value,
+                    body,
+                } => {
+                    let keep = inner == name;
+                    pending.push(Task::Build(Shell::Let(inner)));
+                    pending.push(if keep {
+                        Task::Keep(*body)
+                    } else {
+                        Task::Substitute(*body)
+                    });
+                    pending.push(Task::Substitute(*inner_value));
+                    continue;
+                }
+                //the definition does not see the variables of enclosing `let`s
+                Node::Fn {
+                    name: function,
+                    params,
+                    definition,
+                    body,
+                } => {
+                    pending.push(Task::Build(Shell::Fn(function, params)));
+                    pending.push(Task::Substitute(*body));
+                    pending.push(Task::Keep(*definition));
+                    continue;
+                }
+                node => match node.split() {
+                    Ok((shell, children)) => {
+                        pending.push(Task::Build(shell));
+                        pending.extend(children.into_iter().rev().map(Task::Substitute));
+                        continue;
+                    }
+                    Err(leaf) => leaf.into(),
+                },
+            },
+            Task::Keep(e) => e,
+            Task::Build(shell) => shell.build(&mut substituted),
+        };
+        substituted.push(e);
+    }
+    substituted.pop().expect("the root is substituted last")
+}
+
+#[cfg(test)]
+mod test {
+    use super::simplify;
+    use crate::{eval, parse, EvalError, EvalErrorKind, Expression, Number, Operation, Path, Step};
+
+    fn simplified(input: &str) -> Expression {
+        simplify(parse(input).unwrap())
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(
+            simplified("(10 * 9) + (5 * (3 - 4))"),
+            Expression::Value(85)
+        );
+        assert_eq!(simplified("-(2 ** 3) < ~1"), Expression::Bool(true));
+        assert_eq!(simplified("x + 2 * 3"), parse("x + 6").unwrap());
+        assert_eq!(
+            simplified("let x = 2 * 3 in x * y"),
+            parse("6 * y").unwrap()
+        );
+        assert_eq!(
+            simplified("let x = 1 in x + let x = y in x"),
+            parse("1 + let x = y in x").unwrap()
+        );
+        assert_eq!(
+            simplified("let x = 2 in fn f(y) = x * (y + 0); f(x * 3)"),
+            parse("fn f(y) = x * y; f(6)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors_are_kept() {
+        assert_eq!(simplified("99 / 0"), parse("99 / 0").unwrap());
+        assert_eq!(simplified("1 + 99 / 0"), parse("1 + 99 / 0").unwrap());
+        assert_eq!(
+            simplified("9223372036854775807 + 1"),
+            parse("9223372036854775807 + 1").unwrap()
+        );
+        assert_eq!(simplified("(99 / 0) * 0"), parse("(99 / 0) * 0").unwrap());
+        assert_eq!(
+            simplified("(1 / 0) - (1 / 0)"),
+            parse("(1 / 0) - (1 / 0)").unwrap()
+        );
+        assert!(eval(simplified("-(-9223372036854775807 - 1)")).is_err());
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_eq!(simplified("x * 1 + 0"), parse("x").unwrap());
+        assert_eq!(simplified("0 + 1 * (x / 1) - 0"), parse("x").unwrap());
+        assert_eq!(simplified("(x + y) * 0"), parse("(x + y) * 0").unwrap());
+        assert_eq!(
+            simplified("let x = y + 1 in 0 * x + (x - x)"),
+            parse("let x = y + 1 in 0").unwrap()
+        );
+        assert_eq!(
+            simplified("fn f(a) = a * 0; f(x)"),
+            parse("fn f(a) = 0; f(x)").unwrap()
+        );
+        assert_eq!(
+            simplified("(x * 2) - (x * 2)"),
+            parse("(x * 2) - (x * 2)").unwrap()
+        );
+    }
+
+    //a free variable can be unbound, so it is not dropped
+    #[test]
+    fn test_free_variables_are_kept() {
+        assert_eq!(simplified("x - x"), parse("x - x").unwrap());
+        assert_eq!(simplified("0 * x"), parse("0 * x").unwrap());
+        assert_eq!(
+            eval(simplified("x - x")),
+            Err(EvalError {
+                kind: EvalErrorKind::UnboundVariable(String::from("x")),
+                path: Path(vec![Step::Left]),
+            })
+        );
+        //the definition does not see the variable of the `let`
+        assert_eq!(
+            simplified("let x = y + 1 in fn f() = x - x; f()"),
+            parse("let x = y + 1 in fn f() = x - x; f()").unwrap()
+        );
+        //the inner `let` shadows `x` only in its body
+        assert_eq!(
+            simplified("(let x = y in x) + x * 0"),
+            parse("(let x = y in x) + x * 0").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_branches() {
+        assert_eq!(
+            simplified("if 1 < 2 then x else 1 / 0"),
+            parse("x").unwrap()
+        );
+        assert_eq!(
+            simplified("if y > 0 then 1 + 1 else 3"),
+            parse("if y > 0 then 2 else 3").unwrap()
+        );
+        assert_eq!(simplified("false && 1 / 0 > 0"), Expression::Bool(false));
+        assert_eq!(simplified("true || x > 0"), Expression::Bool(true));
+        assert_eq!(simplified("x > 0 || true"), parse("x > 0 || true").unwrap());
+        assert_eq!(simplified("!(1 == 2) && !false"), Expression::Bool(true));
+    }
+
+    #[test]
+    fn test_type_errors_are_kept() {
+        for input in [
+            "true + 1",
+            "if true then 1 else false",
+            "if 1 < 2 then 3 else 4 + true",
+            "let b = true in b * 0",
+            "false && 1",
+            "let x = 1 < 2 in x - x",
+        ] {
+            assert_eq!(simplified(input), parse(input).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        //((((x + 0) + 0) + ...) + 0) * (1 + (1 + ... (1 + 1)))
+        let mut left = parse("x").unwrap();
+        let mut right = Expression::Value(1);
+        for _ in 0..1_000_000 {
+            left = Expression::Op {
+                op: Operation::Add,
+                left: Box::new(left),
+                right: Box::new(Expression::Value(0)),
+            };
+            right = Expression::Op {
+                op: Operation::Add,
+                left: Box::new(Expression::Value(1)),
+                right: Box::new(right),
+            };
+        }
+        let e = Expression::Op {
+            op: Operation::Mul,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        assert_eq!(simplify(e), parse("x * 1000001").unwrap());
+
+        //let x = 0 in let x = x + 1 in ... in x
+        let mut e = parse("x").unwrap();
+        for _ in 0..100_000 {
+            e = Expression::Let {
+                name: String::from("x"),
+                value: Box::new(parse("x + 1").unwrap()),
+                body: Box::new(e),
+            };
+        }
+        let e = Expression::Let {
+            name: String::from("x"),
+            value: Box::new(Expression::Value(0)),
+            body: Box::new(e),
+        };
+        assert_eq!(simplify(e), Expression::Value(100_000));
+    }
+
+    #[test]
+    fn test_inexact_numbers() {
+        let simplified = |input| simplify(parse(input).unwrap().map(f64::from_i64));
+        assert_eq!(
+            simplified("x * 0"),
+            parse("x * 0").unwrap().map(f64::from_i64)
+        );
+        assert_eq!(
+            simplified("x - x"),
+            parse("x - x").unwrap().map(f64::from_i64)
+        );
+        assert_eq!(simplified("x * 1"), parse("x").unwrap().map(f64::from_i64));
+        assert_eq!(simplified("1 / 0"), Expression::Value(f64::INFINITY));
+    }
+}
@@ -0,0 +1,271 @@
+//evaluation that records every reduction step, to explain how the result was reached
+//a reduction replaces a subexpression of which all needed operands are values by its value, e.g. `10 * 9 → 90`,
+//a function call is a single reduction, e.g. `sq(3) → 9`,
+//the steps happen in the order `eval` evaluates the subexpressions in, so the trace ends with the step that failed
+//values of type `Bool` are shown as `true` or `false`, and an expression that is not well-typed has no steps
+//`Display` for a `Trace` prints the expression after every step and points at the subexpression that failed:
+//    1 + 2 * (3 - 3) + 99 / (4 - 4)
+//  = 1 + 2 * 0 + 99 / (4 - 4)
+//  ...
+//  = 1 + 99 / 0
+//        ^^^^^^ division by zero
+
+use std::fmt;
+use std::mem;
+
+use crate::{
+    type_check, Environment, EvalError, EvalOptions, Evaluator, Expression, Number, Path, Type,
+    Typing,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reduction<N = i64> {
+    //the location of the reduced subexpression in the original expression
+    pub path: Path,
+    //the subexpression right before it was reduced, its operands are values
+    pub redex: Expression<N>,
+    //the literal it was reduced to, a `Value` or a `Bool`
+    pub value: Expression<N>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace<N = i64> {
+    pub expression: Expression<N>,
+    pub steps: Vec<Reduction<N>>,
+    pub result: Result<N, EvalError<N>>,
+}
+
+//evaluates an expression like `eval_ref` and records the reductions
+pub fn trace<N: Number>(
+    e: &Expression<N>,
+    env: &mut Environment<N>,
+    options: &EvalOptions,
+) -> Trace<N> {
+    let typing = type_check(e);
+    if let Some(error) = typing.errors.first() {
+        return Trace {
+            expression: e.clone(),
+            steps: Vec::new(),
+            result: Err(error.clone().into()),
+        };
+    }
+    //the evaluator collects the values, the reductions are replayed on a copy of the expression afterwards
+    let mut machine = Evaluator::new(env, options);
+    machine.reductions = Some(Vec::new());
+    let result = machine.evaluate(e);
+    let reductions = machine.reductions.take().unwrap_or_default();
+    let mut current = e.clone();
+    let steps = reductions
+        .into_iter()
+        .map(|(path, value)| reduce(&mut current, &typing, Path(path), value))
+        .collect();
+    Trace {
+        expression: e.clone(),
+        steps,
+        result,
+    }
+}
+
+//replaces the subexpression of `current` at `path` by its value
+fn reduce<N: Number>(
+    current: &mut Expression<N>,
+    typing: &Typing,
+    path: Path,
+    value: N,
+) -> Reduction<N> {
+    let redex = current
+        .at_mut(&path)
+        .expect("reduced subexpressions are in the tree");
+    let value = match typing.type_at(&path) {
+        Some(Type::Bool) => Expression::Bool(value.is_true()),
+        _ => Expression::Value(value),
+    };
+    let redex = mem::replace(redex, value.clone());
+    Reduction { path, redex, value }
+}
+
+//e.g. `10 * 9 → 90`
+impl<N: fmt::Display> fmt::Display for Reduction<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} → {}", self.redex, self.value)
+    }
+}
+
+impl<N: Number> fmt::Display for Trace<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut current = self.expression.clone();
+        write!(f, "  {current}")?;
+        for step in &self.steps {
+            *current
+                .at_mut(&step.path)
+                .expect("reduced subexpressions are in the tree") = step.value.clone();
+            write!(f, "\n= {current}")?;
+        }
+        if let Err(error) = &self.result {
+            let text = current.to_string();
+            let range = current
+                .locate(&error.path)
+                .expect("the failing subexpression is in the tree");
+            let column = text[..range.start].chars().count();
+            let width = text[range].chars().count();
+            write!(
+                f,
+                "\n  {}{} {}",
+                " ".repeat(column),
+                "^".repeat(width),
+                error.kind
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::trace;
+    use crate::{eval_ref, parse, ArithmeticMode, Budget, Environment, EvalOptions, Step};
+
+    fn traced(input: &str) -> String {
+        let e = parse(input).unwrap();
+        trace(&e, &mut Environment::new(), &EvalOptions::default()).to_string()
+    }
+
+    #[test]
+    fn test_steps() {
+        let e = parse("(10 * 9) + (5 * (3 - 4))").unwrap();
+        let trace = trace(&e, &mut Environment::new(), &EvalOptions::default());
+        let steps: Vec<_> = trace.steps.iter().map(|step| step.to_string()).collect();
+        assert_eq!(
+            steps,
+            ["10 * 9 → 90", "3 - 4 → -1", "5 * -1 → -5", "90 + -5 → 85"]
+        );
+        assert_eq!(trace.result, Ok(85));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            traced("(10 * 9) + (5 * (3 - 4))"),
+            "  10 * 9 + 5 * (3 - 4)\n\
+             = 90 + 5 * (3 - 4)\n\
+             = 90 + 5 * -1\n\
+             = 90 + -5\n\
+             = 85"
+        );
+        assert_eq!(
+            traced("let x = 2 + 3 in if x > 4 then x else 0"),
+            "  let x = 2 + 3 in if x > 4 then x else 0\n\
+             = let x = 5 in if x > 4 then x else 0\n\
+             = let x = 5 in if 5 > 4 then x else 0\n\
+             = let x = 5 in if true then x else 0\n\
+             = let x = 5 in if true then 5 else 0\n\
+             = let x = 5 in 5\n\
+             = 5"
+        );
+        assert_eq!(
+            traced("false && 1 / 0 > 0"),
+            "  false && 1 / 0 > 0\n= false"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            traced("1 + 2 * (3 - 3) + 99 / (4 - 4)"),
+            "  1 + 2 * (3 - 3) + 99 / (4 - 4)\n\
+             = 1 + 2 * 0 + 99 / (4 - 4)\n\
+             = 1 + 0 + 99 / (4 - 4)\n\
+             = 1 + 99 / (4 - 4)\n\
+             = 1 + 99 / 0\n\
+             \x20     ^^^^^^ division by zero"
+        );
+        assert_eq!(
+            traced("2 * y"),
+            "  2 * y\n\
+             \x20     ^ unbound variable `y`"
+        );
+        //an expression that is not well-typed is not evaluated
+        assert_eq!(
+            traced("1 / 0 + !3"),
+            "  1 / 0 + !3\n\
+             \x20          ^ type error: expected a boolean, found a number"
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            traced("fn sq(x) = x * x; sq(1 + 2) + abs(-4)"),
+            "  fn sq(x) = x * x; sq(1 + 2) + abs(-4)\n\
+             = fn sq(x) = x * x; sq(3) + abs(-4)\n\
+             = fn sq(x) = x * x; 9 + abs(-4)\n\
+             = fn sq(x) = x * x; 9 + 4\n\
+             = fn sq(x) = x * x; 13\n\
+             = 13"
+        );
+        //an error in a function points into its definition
+        assert_eq!(
+            traced("fn f(x) = 1 / x; f(0)"),
+            "  fn f(x) = 1 / x; f(0)\n\
+             \x20           ^^^^^ division by zero"
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let e = parse("1 + 2 * 3").unwrap();
+        let options = EvalOptions {
+            budget: Budget {
+                max_nodes: Some(4),
+                max_depth: None,
+            },
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            trace(&e, &mut Environment::new(), &options).to_string(),
+            "  1 + 2 * 3\n\
+             \x20         ^ budget exhausted: more than 4 subexpressions evaluated"
+        );
+    }
+
+    #[test]
+    fn test_same_as_eval() {
+        let mut env = Environment::new();
+        env.bind("x", 3);
+        let options = EvalOptions {
+            mode: ArithmeticMode::Wrapping,
+            ..EvalOptions::default()
+        };
+        for input in [
+            "let y = x * x in y - x",
+            "9223372036854775807 + x",
+            "if x > 0 then x / 0 else 1",
+            "x > 0 || 1 / 0 > 0",
+            "fn f(n) = if n > 0 then n + f(n - 1) else y; f(x)",
+            "fn f(n) = f(n); f(1)",
+            "1 / 0 + true",
+        ] {
+            let e = parse(input).unwrap();
+            assert_eq!(
+                trace(&e, &mut env, &options).result,
+                eval_ref(&e, &mut env, &options)
+            );
+            assert_eq!(env.lookup("y"), None);
+        }
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        //1 / 0 + 1 + 1 + ... fails at the bottom of a tree that is 1M levels deep
+        let e = parse(&format!("1 / 0{}", " + 1".repeat(999_999))).unwrap();
+        let failed = trace(&e, &mut Environment::new(), &EvalOptions::default());
+        assert!(failed.steps.is_empty());
+        assert_eq!(failed.result.unwrap_err().path.0, [Step::Left; 999_999]);
+
+        let e = parse(&vec!["1"; 1000].join(" + ")).unwrap();
+        let summed = trace(&e, &mut Environment::new(), &EvalOptions::default());
+        assert_eq!(summed.steps.len(), 999);
+        assert_eq!(summed.steps[998].to_string(), "999 + 1 → 1000");
+        assert_eq!(summed.result, Ok(1000));
+    }
+}
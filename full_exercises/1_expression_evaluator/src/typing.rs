@@ -0,0 +1,582 @@
+//static types, checked before evaluation so that mistakes like `true + 1` are found without evaluating anything
+//there are two types, numbers and booleans, and the rules are:
+// - literals have their obvious type, the variables of the environment are numbers
+// - arithmetic and bitwise operators, `-` and `~` take and yield numbers
+// - `<`, `<=`, `>` and `>=` compare numbers, `==` and `!=` compare two values of the same type, all yield booleans
+// - `&&`, `||` and `!` take and yield booleans
+// - the condition of an `if` is a boolean, both branches have the same type, which is the type of the `if`
+// - a variable bound by `let` has the type of its value, a `let` or `fn` has the type of its body
+// - the types of the parameters and the result of a function are inferred from its definition and its calls,
+//   a function has a single signature, it is not generic, and types that are never constrained are numbers
+// - the built-in functions take and yield numbers
+//unbound variables, undefined functions and calls with the wrong number of arguments are not type errors,
+//evaluation reports them, in the order it encounters them
+//at runtime a boolean is a number like the result of a comparison, 1 for `true` and 0 for `false`
+//the tree is walked without recursion, so arbitrarily deep trees can be checked
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{Builtin, Expression, Operation, Path, Step, UnaryOperation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Number,
+    Bool,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Number => f.write_str("number"),
+            Type::Bool => f.write_str("boolean"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeErrorKind {
+    //the subexpression has another type than its position requires,
+    //e.g. the condition of an `if`, or the else branch of an `if` with a then branch of another type
+    Mismatch { expected: Type, found: Type },
+}
+
+//a type error, together with the path to the subexpression that has the wrong type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub path: Path,
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeErrorKind::Mismatch { expected, found } => {
+                write!(f, "expected a {expected}, found a {found}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_root() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} at {}", self.kind, self.path)
+        }
+    }
+}
+
+impl Error for TypeError {}
+
+//the result of `type_check`
+#[derive(Debug, Clone)]
+pub struct Typing {
+    //every subexpression in preorder, with the number of subexpressions in its subtree and the step to it
+    nodes: Vec<(Type, usize, Option<Step>)>,
+    //in the order the subexpressions are evaluated in, which reports the first one
+    pub errors: Vec<TypeError>,
+}
+
+impl Typing {
+    pub fn is_well_typed(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    //the type of the whole expression
+    pub fn root(&self) -> Type {
+        self.nodes[0].0
+    }
+
+    //the type of the subexpression at `path`, or `None` if the path does not exist in the tree
+    //a subexpression with a type error has the type its position requires
+    pub fn type_at(&self, path: &Path) -> Option<Type> {
+        let mut node = 0;
+        for step in path.steps() {
+            let (_, size, _) = self.nodes[node];
+            let mut child = node + 1;
+            loop {
+                //the children of a node end where its subtree ends, a leaf has none
+                if child >= node + size {
+                    return None;
+                }
+                if self.nodes[child].2 == Some(*step) {
+                    break;
+                }
+                child += self.nodes[child].1;
+            }
+            node = child;
+        }
+        Some(self.nodes[node].0)
+    }
+}
+
+//infers the type of every subexpression of `e` and collects the type errors
+pub fn type_check<N>(e: &Expression<N>) -> Typing {
+    let mut checker = Checker {
+        path: Vec::new(),
+        slots: Vec::new(),
+        nodes: Vec::new(),
+        variables: Vec::new(),
+        visible: 0,
+        functions: Vec::new(),
+        errors: Vec::new(),
+    };
+    checker.check(e);
+    let mut nodes = Vec::with_capacity(checker.nodes.len());
+    for i in 0..checker.nodes.len() {
+        let (slot, size, step) = checker.nodes[i];
+        let slot = checker.resolve(slot);
+        let found = match checker.slots[slot] {
+            Slot::Known(found) => found,
+            _ => Type::Number,
+        };
+        nodes.push((found, size, step));
+    }
+    Typing {
+        nodes,
+        errors: checker.errors,
+    }
+}
+
+//the type of a subexpression, variable or parameter while it is inferred
+//slots that have to have the same type are linked, the last slot of a chain holds the type if it is known
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    Known(Type),
+    Unknown,
+    Same(usize),
+}
+
+struct Signature<'e> {
+    name: &'e str,
+    params: Vec<usize>,
+    result: usize,
+}
+
+struct Checker<'e> {
+    //the steps from the root to the current subexpression, so errors can point at it
+    path: Vec<Step>,
+    slots: Vec<Slot>,
+    //the slot of every subexpression in preorder, the size of its subtree once it is checked, and the step to it
+    nodes: Vec<(usize, usize, Option<Step>)>,
+    //the variables bound by `let`s and the parameters around the current subexpression, the innermost one last
+    variables: Vec<(&'e str, usize)>,
+    //the variables from this index on are visible, the ones before are outside of the current function definition
+    visible: usize,
+    //the functions defined by the `Fn`s around the current subexpression, the innermost one last
+    functions: Vec<Signature<'e>>,
+    errors: Vec<TypeError>,
+}
+
+//a pending piece of work of the `Checker`, handled in last-in first-out order
+enum Task<'e, N> {
+    //checks the subexpression reached from the current one by the step, or the root
+    Check(Option<Step>, &'e Expression<N>),
+    //infers the type of the subexpression with the given node, after its children are checked
+    Finish(usize, &'e Expression<N>),
+    //binds the variable of a `let` to the type of its value, the first child of the given node
+    Bind(&'e str, usize),
+    Unbind,
+    //ends the definition of the function of the given node, restoring the visible variables
+    Leave(usize, usize),
+}
+
+impl<'e> Checker<'e> {
+    fn check<N>(&mut self, e: &'e Expression<N>) {
+        let mut tasks = vec![Task::Check(None, e)];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Check(step, e) => {
+                    self.path.extend(step);
+                    let node = self.nodes.len();
+                    let slot = self.slot(Slot::Unknown);
+                    self.nodes.push((slot, 1, step));
+                    tasks.push(Task::Finish(node, e));
+                    match e {
+                        Expression::Op { left, right, .. } => tasks.extend([
+                            Task::Check(Some(Step::Right), right),
+                            Task::Check(Some(Step::Left), left),
+                        ]),
+                        Expression::Unary { operand, .. } => {
+                            tasks.push(Task::Check(Some(Step::Operand), operand))
+                        }
+                        Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => {}
+                        Expression::Let { name, value, body } => tasks.extend([
+                            Task::Unbind,
+                            Task::Check(Some(Step::Body), body),
+                            Task::Bind(name, node + 1),
+                            Task::Check(Some(Step::Value), value),
+                        ]),
+                        Expression::If {
+                            cond,
+                            then,
+                            otherwise,
+                        } => tasks.extend([
+                            Task::Check(Some(Step::Else), otherwise),
+                            Task::Check(Some(Step::Then), then),
+                            Task::Check(Some(Step::Cond), cond),
+                        ]),
+                        //the function is visible in its own definition, which only sees the parameters
+                        Expression::Fn {
+                            name,
+                            params,
+                            definition,
+                            body,
+                        } => {
+                            let slots: Vec<_> =
+                                params.iter().map(|_| self.slot(Slot::Unknown)).collect();
+                            let result = self.slot(Slot::Unknown);
+                            tasks.extend([
+                                Task::Check(Some(Step::Body), body),
+                                Task::Leave(node, self.visible),
+                                Task::Check(Some(Step::Definition), definition),
+                            ]);
+                            self.visible = self.variables.len();
+                            for (param, &slot) in params.iter().zip(&slots) {
+                                self.variables.push((param, slot));
+                            }
+                            self.functions.push(Signature {
+                                name,
+                                params: slots,
+                                result,
+                            });
+                        }
+                        Expression::Call { args, .. } => tasks.extend(
+                            args.iter()
+                                .enumerate()
+                                .rev()
+                                .map(|(i, arg)| Task::Check(Some(Step::Arg(i)), arg)),
+                        ),
+                    }
+                }
+                Task::Finish(node, e) => {
+                    self.nodes[node].1 = self.nodes.len() - node;
+                    self.finish(node, e);
+                    if self.nodes[node].2.is_some() {
+                        self.path.pop();
+                    }
+                }
+                Task::Bind(name, value) => {
+                    let slot = self.nodes[value].0;
+                    self.variables.push((name, slot));
+                }
+                Task::Unbind => {
+                    self.variables.pop();
+                }
+                Task::Leave(node, visible) => {
+                    let function = self.functions.last().expect("the function is defined");
+                    let result = function.result;
+                    self.variables.truncate(self.visible);
+                    self.visible = visible;
+                    self.unify(result, self.nodes[node + 1].0, Step::Definition);
+                }
+            }
+        }
+    }
+
+    //infers the type of a subexpression from the types of its children
+    fn finish<N>(&mut self, node: usize, e: &'e Expression<N>) {
+        let slot = self.nodes[node].0;
+        let children = self.children(node);
+        let found = match e {
+            Expression::Op { op, .. } => {
+                let [left, right] = [children[0], children[1]];
+                if op.is_logical() {
+                    self.expect(left, Type::Bool, Step::Left);
+                    self.expect(right, Type::Bool, Step::Right);
+                } else if matches!(op, Operation::Eq | Operation::Ne) {
+                    self.unify(left, right, Step::Right);
+                } else {
+                    self.expect(left, Type::Number, Step::Left);
+                    self.expect(right, Type::Number, Step::Right);
+                }
+                Slot::Known(if op.is_comparison() || op.is_logical() {
+                    Type::Bool
+                } else {
+                    Type::Number
+                })
+            }
+            Expression::Unary { op, .. } => {
+                let operand = match op {
+                    UnaryOperation::Not => Type::Bool,
+                    UnaryOperation::Neg | UnaryOperation::BitNot => Type::Number,
+                };
+                self.expect(children[0], operand, Step::Operand);
+                Slot::Known(operand)
+            }
+            Expression::Value(_) => Slot::Known(Type::Number),
+            Expression::Bool(_) => Slot::Known(Type::Bool),
+            Expression::Var(name) => match self.variables[self.visible..]
+                .iter()
+                .rev()
+                .find(|(bound, _)| bound == name)
+            {
+                Some(&(_, variable)) => Slot::Same(variable),
+                None => Slot::Known(Type::Number),
+            },
+            Expression::Let { .. } => Slot::Same(children[1]),
+            Expression::If { .. } => {
+                self.expect(children[0], Type::Bool, Step::Cond);
+                self.unify(children[1], children[2], Step::Else);
+                Slot::Same(children[1])
+            }
+            Expression::Fn { .. } => {
+                self.functions.pop();
+                Slot::Same(children[1])
+            }
+            Expression::Call { name, .. } => {
+                let function = self.functions.iter().rev().find(|f| f.name == name);
+                match function {
+                    Some(function) => {
+                        let (params, result) = (function.params.clone(), function.result);
+                        if params.len() == children.len() {
+                            for (i, (param, arg)) in params.into_iter().zip(children).enumerate() {
+                                self.unify(param, arg, Step::Arg(i));
+                            }
+                        }
+                        Slot::Same(result)
+                    }
+                    None => match Builtin::from_name(name) {
+                        Some(builtin) if builtin.arity() == children.len() => {
+                            for (i, arg) in children.into_iter().enumerate() {
+                                self.expect(arg, Type::Number, Step::Arg(i));
+                            }
+                            Slot::Known(Type::Number)
+                        }
+                        _ => Slot::Unknown,
+                    },
+                }
+            }
+        };
+        self.slots[slot] = found;
+    }
+
+    //the slots of the children of a checked subexpression, in order
+    fn children(&self, node: usize) -> Vec<usize> {
+        let end = node + self.nodes[node].1;
+        let mut children = Vec::new();
+        let mut child = node + 1;
+        while child < end {
+            children.push(self.nodes[child].0);
+            child += self.nodes[child].1;
+        }
+        children
+    }
+
+    fn slot(&mut self, slot: Slot) -> usize {
+        self.slots.push(slot);
+        self.slots.len() - 1
+    }
+
+    //the last slot of the chain of linked slots
+    //the slots on the way are linked to it directly, so long chains, e.g. of nested `let`s, are only followed once
+    fn resolve(&mut self, slot: usize) -> usize {
+        let mut last = slot;
+        while let Slot::Same(next) = self.slots[last] {
+            last = next;
+        }
+        let mut slot = slot;
+        while let Slot::Same(next) = self.slots[slot] {
+            self.slots[slot] = Slot::Same(last);
+            slot = next;
+        }
+        last
+    }
+
+    //requires the child reached by `step` to have the type `expected`
+    fn expect(&mut self, slot: usize, expected: Type, step: Step) {
+        let slot = self.resolve(slot);
+        match self.slots[slot] {
+            Slot::Known(found) if found != expected => self.error(step, expected, found),
+            _ => self.slots[slot] = Slot::Known(expected),
+        }
+    }
+
+    //requires the child reached by `step`, with the slot `found`, to have the same type as `expected`
+    fn unify(&mut self, expected: usize, found: usize, step: Step) {
+        let (expected, found) = (self.resolve(expected), self.resolve(found));
+        match (self.slots[expected], self.slots[found]) {
+            _ if expected == found => {}
+            (Slot::Known(expected), Slot::Known(found)) if expected != found => {
+                self.error(step, expected, found)
+            }
+            (Slot::Unknown, _) => self.slots[expected] = Slot::Same(found),
+            _ => self.slots[found] = Slot::Same(expected),
+        }
+    }
+
+    fn error(&mut self, step: Step, expected: Type, found: Type) {
+        let mut path = self.path.clone();
+        path.push(step);
+        self.errors.push(TypeError {
+            kind: TypeErrorKind::Mismatch { expected, found },
+            path: Path(path),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{type_check, Type, TypeError, TypeErrorKind};
+    use crate::{parse, Expression, Operation, Path, Step};
+
+    fn errors(input: &str) -> Vec<String> {
+        let typing = type_check(&parse(input).unwrap());
+        typing
+            .errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect()
+    }
+
+    fn type_of(input: &str) -> Type {
+        let typing = type_check(&parse(input).unwrap());
+        assert!(typing.is_well_typed(), "{input}: {:?}", typing.errors);
+        typing.root()
+    }
+
+    #[test]
+    fn test_types() {
+        assert_eq!(type_of("1 + 2 * x"), Type::Number);
+        assert_eq!(type_of("true"), Type::Bool);
+        assert_eq!(type_of("1 < 2 && !(x == 3) || false"), Type::Bool);
+        assert_eq!(type_of("(1 < 2) == false"), Type::Bool);
+        assert_eq!(
+            type_of("let b = x > 0 in if b then 1 else -1"),
+            Type::Number
+        );
+        assert_eq!(type_of("if true then false else 1 != 2"), Type::Bool);
+        assert_eq!(type_of("max(abs(x), 3) % 2"), Type::Number);
+        //the types of the parameters and the result are inferred from the definition and the calls
+        assert_eq!(
+            type_of("fn f(a, b) = if a then b else 0; f(true, 1)"),
+            Type::Number
+        );
+        assert_eq!(type_of("fn f(n) = n; f(true)"), Type::Bool);
+        assert_eq!(
+            type_of("fn f(n) = if n > 0 then f(n - 1) else n == 0; f(5)"),
+            Type::Bool
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            type_check(&parse("true + 1").unwrap()).errors,
+            [TypeError {
+                kind: TypeErrorKind::Mismatch {
+                    expected: Type::Number,
+                    found: Type::Bool,
+                },
+                path: Path(vec![Step::Left]),
+            }]
+        );
+        assert_eq!(
+            errors("if x then 1 else false"),
+            [
+                "expected a boolean, found a number at cond",
+                "expected a number, found a boolean at else"
+            ]
+        );
+        assert_eq!(
+            errors("!1 == 2"),
+            [
+                "expected a boolean, found a number at left.operand",
+                "expected a boolean, found a number at right"
+            ]
+        );
+        assert_eq!(
+            errors("-true"),
+            ["expected a number, found a boolean at operand"]
+        );
+        assert_eq!(
+            errors("abs(1 < 2)"),
+            ["expected a number, found a boolean at arg0"]
+        );
+        assert_eq!(
+            errors("fn f(b) = if b then 1 else 2; f(1)"),
+            ["expected a boolean, found a number at body.arg0"]
+        );
+        //the recursive call is checked first, it makes the result a number
+        assert_eq!(
+            errors("fn f(n) = if n == 0 then true else f(n - 1) + 1; f(1)"),
+            [
+                "expected a boolean, found a number at definition.else",
+                "expected a number, found a boolean at definition"
+            ]
+        );
+        //a function has a single signature
+        assert_eq!(
+            errors("fn id(x) = x; id(1) + id(true)"),
+            ["expected a number, found a boolean at body.right.arg0"]
+        );
+        assert_eq!(errors("true"), Vec::<String>::new());
+        assert_eq!(
+            errors("false || 1").concat(),
+            "expected a boolean, found a number at right"
+        );
+        let error = &type_check(&parse("1 && true").unwrap()).errors[0];
+        assert_eq!(
+            error.to_string(),
+            "expected a boolean, found a number at left"
+        );
+    }
+
+    //evaluation reports the errors that are not about types
+    #[test]
+    fn test_left_to_evaluation() {
+        assert_eq!(type_of("y + undefined(1)"), Type::Number);
+        assert_eq!(type_of("fn f(a) = a; f(1, 2) + abs()"), Type::Number);
+        //the definition does not see the variables of enclosing `let`s
+        assert_eq!(type_of("let x = true in fn f() = x + 1; f()"), Type::Number);
+    }
+
+    #[test]
+    fn test_type_at() {
+        let typing = type_check(&parse("let b = 1 < x in if b then f(2) else 3").unwrap());
+        let type_at = |steps: &[Step]| typing.type_at(&Path(steps.to_vec()));
+        assert_eq!(type_at(&[]), Some(Type::Number));
+        assert_eq!(type_at(&[Step::Value]), Some(Type::Bool));
+        assert_eq!(type_at(&[Step::Value, Step::Right]), Some(Type::Number));
+        assert_eq!(type_at(&[Step::Body, Step::Cond]), Some(Type::Bool));
+        assert_eq!(
+            type_at(&[Step::Body, Step::Then, Step::Arg(0)]),
+            Some(Type::Number)
+        );
+        assert_eq!(type_at(&[Step::Body, Step::Left]), None);
+        assert_eq!(type_at(&[Step::Value, Step::Left, Step::Left]), None);
+    }
+
+    //paths that go below a leaf do not exist, even if the next node in preorder has the step
+    #[test]
+    fn test_type_at_below_leaf() {
+        let typing = type_check(&parse("1 + 2").unwrap());
+        let type_at = |steps: &[Step]| typing.type_at(&Path(steps.to_vec()));
+        assert_eq!(type_at(&[Step::Left, Step::Right]), None);
+        assert_eq!(type_at(&[Step::Right, Step::Left]), None);
+        assert_eq!(type_at(&[Step::Right, Step::Right]), None);
+        let typing = type_check(&parse("true").unwrap());
+        assert_eq!(typing.type_at(&Path(vec![Step::Operand])), None);
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let mut e = Expression::Bool(true);
+        for i in 0..1_000_000 {
+            e = Expression::Op {
+                op: Operation::And,
+                left: Box::new(e),
+                right: Box::new(Expression::Op {
+                    op: Operation::Lt,
+                    left: Box::new(Expression::Value(i)),
+                    right: Box::new(Expression::Var(String::from("x"))),
+                }),
+            };
+        }
+        let typing = type_check(&e);
+        assert!(typing.is_well_typed());
+        assert_eq!(typing.root(), Type::Bool);
+    }
+}
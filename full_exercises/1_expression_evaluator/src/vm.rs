@@ -0,0 +1,611 @@
+//a compiler from `Expression` trees to a flat list of instructions, and a stack machine that runs them
+//a `Program` is compiled once and can be run many times, possibly with different variables in the environment,
+//without walking (or consuming) the tree again
+//neither compiling nor running recurses, so arbitrarily deep trees do not overflow the call stack
+//running a program gives the same result as `eval_with` on the tree it was compiled from, including the path of errors,
+//except when `max_nodes` of `EvalOptions::budget` runs out, see `Program::run_with`
+//the code of a function is placed where it is defined, behind a jump over it, and calls are resolved while compiling
+//an expression that is not well-typed is still compiled, but the program fails with the type error before it runs
+
+use std::mem;
+
+use crate::{
+    type_check, Builtin, Environment, EvalError, EvalErrorKind, EvalOptions, Expression, Limit,
+    Number, Operation, Path, Step, TypeError, UnaryOperation,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction<N = i64> {
+    Push(N),
+    //pushes the value of a variable bound by a `let` or a parameter of the function that is being run,
+    //numbered from the outermost one, the parameters come first
+    Local(usize),
+    //pushes the value of a variable that is not bound in the expression, it is looked up in the environment
+    Global(String),
+    //pops a value and binds it as the innermost local, until the matching `Unbind`
+    Bind,
+    Unbind,
+    //pops the right and then the left operand, and pushes the result
+    Binary(Operation),
+    Unary(UnaryOperation),
+    Jump(usize),
+    //pops the condition, and jumps if it is false
+    JumpIfFalse(usize),
+    //for `&&` and `||`: if the left operand on top of the stack determines the result,
+    //it is replaced by that result and the right operand is jumped over
+    ShortCircuit(Operation, usize),
+    //pops the given number of arguments, binds them as the locals of a new call and jumps to the function
+    Call(usize, usize),
+    //ends the innermost call and returns to the instruction after it
+    Return,
+    //pops the arguments and pushes the result of the built-in function
+    Builtin(Builtin),
+    //a call that fails when it is reached, because the function is not defined or gets the wrong number of arguments
+    Fail(EvalErrorKind<N>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Program<N = i64> {
+    code: Vec<Instruction<N>>,
+    //the subexpression every instruction was compiled from, as an index into `nodes`
+    sources: Vec<usize>,
+    //the parent and the step taken from it of every subexpression except the root (which has index 0),
+    //so the path of an error only has to be built when it happens
+    nodes: Vec<(usize, Step)>,
+    //how deeply every subexpression is nested in the tree, the root is at depth 0
+    depths: Vec<usize>,
+    //the first error of `type_check`, reported instead of running the program
+    error: Option<TypeError>,
+}
+
+//a pending action of the compiler, handled in last-in first-out order
+enum Task<'a, N> {
+    Compile(&'a Expression<N>, usize),
+    Emit(Instruction<N>, usize),
+    Bind(&'a str, usize),
+    Unbind(usize),
+    //makes a function visible until the matching `Undefine`
+    Define(&'a str, usize, usize),
+    Undefine,
+    //starts compiling the definition of a function, which only sees its parameters
+    Enter(&'a [String]),
+    //ends the definition of a function, restoring the variables of the enclosing scope
+    Leave(usize),
+    //marks the current position as the target of the jumps to this label
+    Label(usize),
+}
+
+pub fn compile<N: Number>(e: &Expression<N>) -> Program<N> {
+    let mut program = Program {
+        code: Vec::new(),
+        sources: Vec::new(),
+        nodes: Vec::new(),
+        depths: vec![0],
+        error: type_check(e).errors.into_iter().next(),
+    };
+    //jump targets are label numbers during compilation, they are resolved to positions at the end
+    let mut labels = Vec::new();
+    let mut scope = Vec::new();
+    //the scopes of the definitions that enclose the subexpression that is compiled
+    let mut enclosing = Vec::new();
+    //the name, entry label and number of parameters of the visible functions
+    let mut functions: Vec<(&str, usize, usize)> = Vec::new();
+    let mut tasks = vec![Task::Compile(e, 0)];
+    while let Some(task) = tasks.pop() {
+        let (e, node) = match task {
+            Task::Compile(e, node) => (e, node),
+            Task::Emit(instruction, node) => {
+                program.emit(instruction, node);
+                continue;
+            }
+            Task::Bind(name, node) => {
+                scope.push(name);
+                program.emit(Instruction::Bind, node);
+                continue;
+            }
+            Task::Unbind(node) => {
+                scope.pop();
+                program.emit(Instruction::Unbind, node);
+                continue;
+            }
+            Task::Label(label) => {
+                labels[label] = program.code.len();
+                continue;
+            }
+            Task::Define(name, entry, arity) => {
+                functions.push((name, entry, arity));
+                continue;
+            }
+            Task::Undefine => {
+                functions.pop();
+                continue;
+            }
+            Task::Enter(params) => {
+                enclosing.push(mem::replace(
+                    &mut scope,
+                    params.iter().map(String::as_str).collect(),
+                ));
+                continue;
+            }
+            Task::Leave(node) => {
+                scope = enclosing
+                    .pop()
+                    .expect("a definition is left after it is entered");
+                program.emit(Instruction::Return, node);
+                continue;
+            }
+        };
+        let mut child = |step| program.node(node, step);
+        let mut label = || {
+            labels.push(0);
+            labels.len() - 1
+        };
+        //the tasks are pushed in reverse order
+        match e {
+            Expression::Value(value) => program.emit(Instruction::Push(value.clone()), node),
+            Expression::Bool(value) => program.emit(Instruction::Push(N::from_bool(*value)), node),
+            Expression::Var(name) => {
+                let instruction = match scope.iter().rposition(|bound| bound == name) {
+                    Some(slot) => Instruction::Local(slot),
+                    None => Instruction::Global(name.clone()),
+                };
+                program.emit(instruction, node);
+            }
+            Expression::Let { name, value, body } => {
+                let (value_node, body_node) = (child(Step::Value), child(Step::Body));
+                tasks.extend([
+                    Task::Unbind(node),
+                    Task::Compile(body, body_node),
+                    Task::Bind(name, node),
+                    Task::Compile(value, value_node),
+                ]);
+            }
+            Expression::Op { op, left, right } => {
+                let (left_node, right_node) = (child(Step::Left), child(Step::Right));
+                if let Operation::And | Operation::Or = op {
+                    let end = label();
+                    tasks.extend([
+                        Task::Label(end),
+                        Task::Emit(Instruction::Binary(*op), node),
+                        Task::Compile(right, right_node),
+                        Task::Emit(Instruction::ShortCircuit(*op, end), node),
+                    ]);
+                } else {
+                    tasks.extend([
+                        Task::Emit(Instruction::Binary(*op), node),
+                        Task::Compile(right, right_node),
+                    ]);
+                }
+                tasks.push(Task::Compile(left, left_node));
+            }
+            Expression::Unary { op, operand } => {
+                let operand_node = child(Step::Operand);
+                tasks.extend([
+                    Task::Emit(Instruction::Unary(*op), node),
+                    Task::Compile(operand, operand_node),
+                ]);
+            }
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let (cond_node, then_node, else_node) =
+                    (child(Step::Cond), child(Step::Then), child(Step::Else));
+                let (otherwise_label, end) = (label(), label());
+                tasks.extend([
+                    Task::Label(end),
+                    Task::Compile(otherwise, else_node),
+                    Task::Label(otherwise_label),
+                    Task::Emit(Instruction::Jump(end), node),
+                    Task::Compile(then, then_node),
+                    Task::Emit(Instruction::JumpIfFalse(otherwise_label), node),
+                    Task::Compile(cond, cond_node),
+                ]);
+            }
+            Expression::Fn {
+                name,
+                params,
+                definition,
+                body,
+            } => {
+                let (definition_node, body_node) = (child(Step::Definition), child(Step::Body));
+                let (entry, end) = (label(), label());
+                tasks.extend([
+                    Task::Undefine,
+                    Task::Compile(body, body_node),
+                    Task::Label(end),
+                    Task::Leave(node),
+                    Task::Compile(definition, definition_node),
+                    Task::Enter(params),
+                    Task::Label(entry),
+                    Task::Emit(Instruction::Jump(end), node),
+                    Task::Define(name, entry, params.len()),
+                ]);
+            }
+            Expression::Call { name, args } => {
+                let arity_error = |expected| {
+                    Instruction::Fail(EvalErrorKind::WrongArity {
+                        name: name.clone(),
+                        expected,
+                        found: args.len(),
+                    })
+                };
+                let instruction = match functions.iter().rev().find(|(f, ..)| f == name) {
+                    Some(&(_, entry, arity)) if arity == args.len() => {
+                        Instruction::Call(entry, arity)
+                    }
+                    Some(&(_, _, arity)) => arity_error(arity),
+                    None => match Builtin::from_name(name) {
+                        Some(builtin) if builtin.arity() == args.len() => {
+                            Instruction::Builtin(builtin)
+                        }
+                        Some(builtin) => arity_error(builtin.arity()),
+                        None => Instruction::Fail(EvalErrorKind::UndefinedFunction(name.clone())),
+                    },
+                };
+                tasks.push(Task::Emit(instruction, node));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    tasks.push(Task::Compile(arg, child(Step::Arg(i))));
+                }
+            }
+        }
+    }
+    for instruction in &mut program.code {
+        if let Instruction::Jump(target)
+        | Instruction::JumpIfFalse(target)
+        | Instruction::ShortCircuit(_, target)
+        | Instruction::Call(target, _) = instruction
+        {
+            *target = labels[*target];
+        }
+    }
+    program
+}
+
+impl<N: Number> Program<N> {
+    fn emit(&mut self, instruction: Instruction<N>, node: usize) {
+        self.code.push(instruction);
+        self.sources.push(node);
+    }
+
+    //adds the subexpression reached by `step` from `parent`
+    fn node(&mut self, parent: usize, step: Step) -> usize {
+        self.nodes.push((parent, step));
+        self.depths.push(self.depths[parent] + 1);
+        self.nodes.len()
+    }
+
+    pub fn code(&self) -> &[Instruction<N>] {
+        &self.code
+    }
+
+    //runs the program without any variables in scope, like `eval`
+    pub fn run(&self) -> Result<N, EvalError<N>> {
+        self.run_with(&Environment::new(), &EvalOptions::default())
+    }
+
+    //runs the program with the variables bound in `env`, like `eval_with`
+    //every instruction counts as a node against `options.budget.max_nodes`, so a program usually runs out of budget
+    //at another point than `eval_with`
+    //`options.budget.max_depth` is checked like `eval_with` does: an instruction is nested as deeply as the
+    //subexpression it was compiled from, and the definition of a function one level deeper than the call
+    pub fn run_with(&self, env: &Environment<N>, options: &EvalOptions) -> Result<N, EvalError<N>> {
+        if let Some(error) = &self.error {
+            return Err(error.clone().into());
+        }
+        let mut stack = Vec::new();
+        let mut locals: Vec<N> = Vec::new();
+        //the locals of the innermost call start at `base`, and its definition is nested `root.0` deep while it is
+        //evaluated and `root.1` deep in the tree,
+        //`frames` holds the return address, the base and the root of the calls in progress
+        let mut base = 0;
+        let mut root = (0, 0);
+        let mut frames = Vec::new();
+        let mut pc = 0;
+        let mut executed = 0;
+        //a well-formed program never pops from an empty stack
+        let pop = |stack: &mut Vec<N>| stack.pop().expect("stack underflow");
+        while let Some(instruction) = self.code.get(pc) {
+            //the `Return` of a function is compiled from the `Fn`, outside of the definition, so it is not checked
+            let nested = !matches!(instruction, Instruction::Return);
+            if let Some(max) = options.budget.max_depth.filter(|_| nested) {
+                let node = self.sources[pc];
+                if root.0 + (self.depths[node] - root.1) > max {
+                    //the subexpression that is nested too deeply is the one at depth `max + 1` on the way to it
+                    let node = self.ancestor(node, max + 1 - root.0 + root.1);
+                    let kind = EvalErrorKind::BudgetExhausted(Limit::Depth(max));
+                    return Err(self.error_at(node, kind));
+                }
+            }
+            executed += 1;
+            match options.budget.max_nodes {
+                Some(max) if executed > max => {
+                    let kind = EvalErrorKind::BudgetExhausted(Limit::Nodes(max));
+                    return Err(self.error(pc, kind));
+                }
+                _ if options
+                    .cancellation
+                    .as_ref()
+                    .is_some_and(|token| token.is_cancelled()) =>
+                {
+                    return Err(self.error(pc, EvalErrorKind::Cancelled));
+                }
+                _ => {}
+            }
+            pc += 1;
+            match instruction {
+                Instruction::Push(value) => stack.push(value.clone()),
+                Instruction::Local(slot) => stack.push(locals[base + slot].clone()),
+                Instruction::Global(name) => match env.lookup(name) {
+                    Some(value) => stack.push(value.clone()),
+                    None => {
+                        let kind = EvalErrorKind::UnboundVariable(name.clone());
+                        return Err(self.error(pc - 1, kind));
+                    }
+                },
+                Instruction::Bind => locals.push(pop(&mut stack)),
+                Instruction::Unbind => {
+                    locals.pop();
+                }
+                Instruction::Binary(op) => {
+                    let right = pop(&mut stack);
+                    let left = pop(&mut stack);
+                    let result = N::apply(*op, &left, &right, options.mode)
+                        .map_err(|kind| self.error(pc - 1, kind))?;
+                    stack.push(result);
+                }
+                Instruction::Unary(op) => {
+                    let operand = pop(&mut stack);
+                    let result = N::apply_unary(*op, &operand, options.mode)
+                        .map_err(|kind| self.error(pc - 1, kind))?;
+                    stack.push(result);
+                }
+                Instruction::Jump(target) => pc = *target,
+                Instruction::JumpIfFalse(target) => {
+                    if !pop(&mut stack).is_true() {
+                        pc = *target;
+                    }
+                }
+                Instruction::ShortCircuit(op, target) => {
+                    let left = stack.last_mut().expect("stack underflow");
+                    let decided = match op {
+                        Operation::And => !left.is_true(),
+                        _ => left.is_true(),
+                    };
+                    if decided {
+                        *left = N::from_bool(*op == Operation::Or);
+                        pc = *target;
+                    }
+                }
+                Instruction::Call(target, arity) => {
+                    if frames.len() == options.max_call_depth {
+                        let kind = EvalErrorKind::RecursionLimit(options.max_call_depth);
+                        return Err(self.error(pc - 1, kind));
+                    }
+                    let depth = root.0 + (self.depths[self.sources[pc - 1]] - root.1) + 1;
+                    if let Some(max) = options.budget.max_depth.filter(|&max| depth > max) {
+                        let kind = EvalErrorKind::BudgetExhausted(Limit::Depth(max));
+                        return Err(self.error(pc - 1, kind));
+                    }
+                    frames.push((pc, base, root));
+                    base = locals.len();
+                    locals.extend(stack.drain(stack.len() - arity..));
+                    //the code of a function follows the jump over it, which is compiled from the `Fn`
+                    root = (depth, self.depths[self.sources[*target - 1]] + 1);
+                    pc = *target;
+                }
+                Instruction::Return => {
+                    locals.truncate(base);
+                    (pc, base, root) = frames.pop().expect("a return matches a call");
+                }
+                Instruction::Builtin(builtin) => {
+                    let args = stack.split_off(stack.len() - builtin.arity());
+                    let result = builtin
+                        .apply(&args, options.mode)
+                        .map_err(|kind| self.error(pc - 1, kind))?;
+                    stack.push(result);
+                }
+                Instruction::Fail(kind) => return Err(self.error(pc - 1, kind.clone())),
+            }
+        }
+        Ok(pop(&mut stack))
+    }
+
+    //the subexpression that contains `node` and is nested `depth` deep in the tree
+    fn ancestor(&self, mut node: usize, depth: usize) -> usize {
+        while self.depths[node] > depth {
+            node = self.nodes[node - 1].0;
+        }
+        node
+    }
+
+    fn error(&self, pc: usize, kind: EvalErrorKind<N>) -> EvalError<N> {
+        self.error_at(self.sources[pc], kind)
+    }
+
+    fn error_at(&self, mut node: usize, kind: EvalErrorKind<N>) -> EvalError<N> {
+        let mut steps = Vec::new();
+        while node != 0 {
+            let (parent, step) = self.nodes[node - 1];
+            steps.push(step);
+            node = parent;
+        }
+        steps.reverse();
+        EvalError {
+            kind,
+            path: Path(steps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compile, Instruction};
+    use crate::{
+        eval_with, parse, ArithmeticMode, Budget, CancellationToken, Environment, EvalErrorKind,
+        EvalOptions, Expression, Limit, Number, Operation,
+    };
+
+    //running the compiled program has to give the same result as evaluating the tree
+    fn assert_same<N: Number>(input: &str, env: &Environment<N>, options: &EvalOptions) {
+        let e = parse(input).unwrap().map(N::from_i64);
+        let program = compile(&e);
+        let expected = eval_with(e, &mut env.clone(), options);
+        assert_eq!(program.run_with(env, options), expected, "{input}");
+    }
+
+    #[test]
+    fn test_compile() {
+        use Instruction::*;
+        let program = compile(&parse("let x = 1 in if x < y then x else 2 - x").unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Push(1),
+                Bind,
+                Local(0),
+                Global(String::from("y")),
+                Binary(Operation::Lt),
+                JumpIfFalse(8),
+                Local(0),
+                Jump(11),
+                Push(2),
+                Local(0),
+                Binary(Operation::Sub),
+                Unbind,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_as_eval() {
+        let inputs = [
+            "(10 * 9) + (5 * (3 - 4))",
+            "2 ** 62 + 2 ** 62",
+            "1 + 2 * (99 / (3 - 3))",
+            "let x = 3 in (let x = x * x in x) + x",
+            "let x = y in let y = 2 in x + y",
+            "1 + let x = 1 in z",
+            "if 1 < 2 then 3 else 1 / 0",
+            "if x > 0 then 1 / 0 else 3 ** -1",
+            "false && 1 / 0 == 0 || 2 > 1 && true",
+            "true && false || (true || 1 / 0 < 0)",
+            "-(-9223372036854775807 - 1) + ~5 << 65",
+            "fn sq(x) = x * x; sq(3) + sq(4)",
+            "fn fact(n) = if n > 0 then n * fact(n - 1) else 1; fact(20) + fact(21)",
+            "fn f(a, b) = a - b + y; let y = 1 in f(2, y) + (fn f(a) = a; f(x))",
+            "fn f() = fn g(n) = n; g(x); fn h() = g(1); f() + h()",
+            "fn f(n) = if n == 0 then 1 / 0 else f(n + 1); f(0 - 2)",
+            "fn f(n) = f(n + 1); f(0)",
+            "fn f(x) = x; f(1, 1 / 0) + f(2, 3)",
+            "max(abs(-3), gcd(12, 18)) - min(x, 0) + abs(1, 2)",
+            "fn abs(x) = 0; abs(-5) + undefined(1 / 0)",
+            "if 1 then 2 else false",
+        ];
+        let mut env = Environment::new();
+        env.bind("x", 0);
+        env.bind("y", 7);
+        for mode in [
+            ArithmeticMode::Checked,
+            ArithmeticMode::Wrapping,
+            ArithmeticMode::Saturating,
+        ] {
+            for input in inputs {
+                let options = EvalOptions {
+                    mode,
+                    ..EvalOptions::default()
+                };
+                assert_same::<i64>(input, &env, &options);
+            }
+        }
+        let env = Environment::new();
+        for input in inputs {
+            assert_same::<f64>(input, &env, &EvalOptions::default());
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        //one instruction per subexpression
+        let program = compile(&parse("1 + 2 * 3").unwrap());
+        let options = |max_nodes| EvalOptions {
+            budget: Budget {
+                max_nodes: Some(max_nodes),
+                max_depth: None,
+            },
+            ..EvalOptions::default()
+        };
+        let env = Environment::new();
+        assert_eq!(program.run_with(&env, &options(5)), Ok(7));
+        assert_eq!(
+            program.run_with(&env, &options(4)).unwrap_err().kind,
+            EvalErrorKind::BudgetExhausted(Limit::Nodes(4))
+        );
+        let token = CancellationToken::new();
+        let options = EvalOptions {
+            cancellation: Some(token.clone()),
+            ..EvalOptions::default()
+        };
+        assert_eq!(program.run_with(&env, &options), Ok(7));
+        token.cancel();
+        assert_eq!(
+            program.run_with(&env, &options).unwrap_err().kind,
+            EvalErrorKind::Cancelled
+        );
+    }
+
+    //unlike the number of nodes, the depth is limited exactly like `eval_with` does it
+    #[test]
+    fn test_max_depth() {
+        let inputs = [
+            "1 + 2 * 3",
+            "((1 + 2) + 3) + 4",
+            "let x = 1 in if x < 2 then -x else 0",
+            "fn f(n) = if n > 0 then f(n - 1) else 0; f(3)",
+            "fn f(n) = if n > 0 then 1 + f(n - 1) else 0; 1 + (fn g() = f(2); g())",
+            "fn f() = 1; ((f()))",
+            "fn f() = (1 + (2 + 3)); f() + (fn g() = f(); 4 * g())",
+            "max(1, abs(-2 * 3)) + undefined(1 + 2)",
+        ];
+        let env = Environment::new();
+        for input in inputs {
+            for max_depth in 0..8 {
+                let options = EvalOptions {
+                    budget: Budget {
+                        max_nodes: None,
+                        max_depth: Some(max_depth),
+                    },
+                    ..EvalOptions::default()
+                };
+                assert_same::<i64>(input, &env, &options);
+            }
+        }
+        let program = compile(&parse("1 + 2 * 3").unwrap());
+        let options = EvalOptions {
+            budget: Budget {
+                max_nodes: None,
+                max_depth: Some(1),
+            },
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            program.run_with(&env, &options).unwrap_err().to_string(),
+            "budget exhausted: subexpressions nested more than 1 deep at right.left"
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let mut e = Expression::Value(0);
+        for i in 0..1_000_000 {
+            e = Expression::Op {
+                op: Operation::Add,
+                left: Box::new(Expression::Value(i % 3)),
+                right: Box::new(e),
+            };
+        }
+        let program = compile(&e);
+        assert_eq!(program.run(), Ok(999_999));
+    }
+}
@@ -1,89 +1,601 @@
-//In this exercise, you will implement the LocalStorageVec data structure, which
-//is a growable, generic list that resides either on the stack (if its size is below a
-//given number `N`), or on the heap if it grows larger
-//This list is generic over the element type `T`, as well as the size `N` of the stack allocated buffer
-
-//You may have noticed that this is a lib.rs file and not a main.rs file
-//This file naming convention is one possibility to tell Cargo to build a static library instead of an application binary
-//There is thus no `main` function in which you can test your code, instead we wrote some tests inside the module called `test` at the bottom of the file
-//You can run the tests with `cargo test`
-
-
-//TODO 1: complete the implementation of the LocalStorageVec enum
-//Define a variant called `Stack` containing two named fields:
-// - `buf` is an array with elements of type `T` and size `N`
-// - `len` is a field of type `usize` (len represents the number of elements currently in the array, while the const-generic `N` represents its capacity)
-//Define a variant called `Heap`, containing a single unnamed field of type `Vec<T>`, which is a heap-based growable, contiguous list of type `T`
-//(you can find more info about when each variant is used in TODO 2)
+//a `LocalStorageVec` is a growable, generic list that resides either on the stack (as long as it holds at most
+//`N` elements), or on the heap if it grows larger
+//it is generic over the element type `T`, which can be any type, e.g. `String` or `Box<T>`, and over the size `N`
+//of the stack allocated buffer
+
+use std::borrow::{Borrow, BorrowMut};
+use std::iter::{self, FusedIterator};
+use std::mem::{self, MaybeUninit};
+use std::ops::{Bound, Deref, DerefMut, Index, IndexMut, Range, RangeBounds};
+use std::slice::{self, SliceIndex};
+use std::vec;
+
+//`Stack` holds the elements in `buf`, `len` is the number of elements, while `N` is the capacity of `buf`
+//`Heap` holds the elements in a `Vec`, it is only used while there are more than `N` elements
+//`Buffer` is private to the crate, so other crates can match on `buf` but cannot name, build or use it
+#[allow(private_interfaces)]
 pub enum LocalStorageVec<T, const N: usize> {
+    Stack {
+        buf: Buffer<T, N>,
+        //a copy of the count of `buf`, which is the source of truth: all methods read the count of `buf`,
+        //`len` only exists so the variant can be matched on its length, and it is only written when the variant
+        //is created and by `StackMut`
+        len: usize,
+    },
+    Heap(Vec<T>),
+}
+
+//the storage of the `Stack` variant: `N` slots of which the first `len` are initialized, the rest is uninitialized
+//memory, so `T` does not need a default value to fill them
+//the buffer drops its initialized elements itself and therefore keeps the count: `LocalStorageVec` cannot
+//implement `Drop`, because then the `Vec` could not be moved out of the `Heap` variant by pattern matching
+pub(crate) struct Buffer<T, const N: usize> {
+    slots: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> Buffer<T, N> {
+    fn new() -> Buffer<T, N> {
+        Buffer {
+            slots: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
+    }
+
+    //the buffer must not be full
+    fn push(&mut self, value: T) {
+        self.slots[self.len].write(value);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        //safety: the slot was initialized, and it is no longer counted, so it is not read or dropped again
+        Some(unsafe { self.slots[self.len].assume_init_read() })
+    }
+
+    fn as_slice(&self) -> &[T] {
+        //safety: the first `len` slots are initialized, and `MaybeUninit<T>` has the same layout as `T`
+        unsafe { slice::from_raw_parts(self.slots.as_ptr().cast(), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        //safety: as in `as_slice`, and the slice borrows the buffer mutably
+        unsafe { slice::from_raw_parts_mut(self.slots.as_mut_ptr().cast(), self.len) }
+    }
+
+    //moves the elements to a `Vec` with room for `additional` more elements
+    fn into_vec(mut self, additional: usize) -> Vec<T> {
+        let mut vec = Vec::with_capacity(self.len.saturating_add(additional));
+        let len = mem::take(&mut self.len);
+        //safety: the first `len` slots are initialized, and they are no longer counted, so they are only read once
+        vec.extend(
+            self.slots[..len]
+                .iter()
+                .map(|slot| unsafe { slot.assume_init_read() }),
+        );
+        vec
+    }
+
+    //drops the elements from `len` on, if there are that many
+    fn truncate(&mut self, len: usize) {
+        let len = len.min(self.len);
+        let old_len = mem::replace(&mut self.len, len);
+        for slot in &mut self.slots[self.len..old_len] {
+            //safety: the slot is initialized, and it is no longer counted, so it is only dropped once
+            unsafe { slot.assume_init_drop() };
+        }
+    }
+
+    //moves the elements from `at` on, which must not be more than `len`, to a new buffer
+    fn split_off(&mut self, at: usize) -> Buffer<T, N> {
+        let mut tail = Buffer::new();
+        let len = mem::replace(&mut self.len, at);
+        for slot in &self.slots[at..len] {
+            //safety: the slot is initialized, and it is no longer counted, so it is only read once
+            tail.push(unsafe { slot.assume_init_read() });
+        }
+        tail
+    }
+}
+
+impl<T, const N: usize> Drop for Buffer<T, N> {
+    fn drop(&mut self) {
+        self.truncate(0);
+    }
+}
+
+//mutable access to the buffer of the `Stack` variant, which copies the count of the buffer to `len` when it is
+//dropped, also if dropping an element panics
+struct StackMut<'a, T, const N: usize> {
+    buf: &'a mut Buffer<T, N>,
+    copy: &'a mut usize,
+}
+
+impl<T, const N: usize> Deref for StackMut<'_, T, N> {
+    type Target = Buffer<T, N>;
+
+    fn deref(&self) -> &Buffer<T, N> {
+        self.buf
+    }
+}
 
+impl<T, const N: usize> DerefMut for StackMut<'_, T, N> {
+    fn deref_mut(&mut self) -> &mut Buffer<T, N> {
+        self.buf
+    }
+}
+
+impl<T, const N: usize> Drop for StackMut<'_, T, N> {
+    fn drop(&mut self) {
+        *self.copy = self.buf.len;
+    }
 }
 
-//The `std::convert::From` and `std::convert::Into` traits allow a type to be easily created FROM
-//another type, or be converted INTO another type, respectively.
-//The String type, for example, implements the `From<&str>` trait (https://doc.rust-lang.org/std/string/struct.String.html#impl-From%3C%26str%3E-for-String) which makes the following code valid:
-//  let my_str: &str = "hello";
-//  let my_string: String = String::from(my_str);
-//TODO 2 implement the `From<[T; N]>` trait on your LocalStorageVec type so you can create a LocalStorageVec from an array
-//As you can see, `N` (size of the given array) can differ from `M` (max size of the stack allocated buffer):
-// - if N == M: the buffer is allocated on the stack and has exactly, and only the elements of the given array (the LocalStorageVec enum has the `Stack` variant)
-// - if N < M: the buffer is allocated on the stack and contains all elements of the given array (the LocalStorageVec enum has the `Stack` variant)
-//             the M-N leftover elements are filled with "default values" (instead of uninitialized memory, for simplicity), that is why we bound the type T to implement the `Default` trait
-//             note: the syntax `impl<T>... where T: Default` is the same as `impl<T: Default>...`
-// - if N > M: the buffer is allocated on the heap and contains all elements of the given array
-//             nothing is allocated on the stack (the LocalStorageVec enum has the `Heap` variant)
-impl<T, const N: usize, const M: usize> From<[T; N]> for LocalStorageVec<T, M>
-where T: Default {
-  fn from(array: [T; N]) -> Self {
+//creates a `LocalStorageVec` with the elements of an array, `N` (the size of the array) can differ from `M`
+//(the size of the stack allocated buffer):
+// - if N <= M: the elements are moved to the stack, the remaining M - N slots stay uninitialized
+// - if N > M: the elements are moved to the heap, nothing is stored on the stack
+impl<T, const N: usize, const M: usize> From<[T; N]> for LocalStorageVec<T, M> {
+    fn from(array: [T; N]) -> Self {
         if N <= M {
-           
+            let mut buf = Buffer::new();
+            for value in array {
+                buf.push(value);
+            }
+            LocalStorageVec::stack(buf)
         } else {
-          //hint: `Vec<T>` implements the `From<[T,N]>` trait, see the docs
-          
+            LocalStorageVec::Heap(Vec::from(array))
         }
     }
 }
 
-//TODO 3: complete these functions
-//To make implementation easier, you should bound `T` to implement `Copy` and `Default`
 impl<T, const N: usize> LocalStorageVec<T, N> {
-
     //returns an empty LocalStorageVec without heap allocation
     pub fn new() -> LocalStorageVec<T, N> {
+        LocalStorageVec::stack(Buffer::new())
+    }
 
+    fn stack(buf: Buffer<T, N>) -> LocalStorageVec<T, N> {
+        LocalStorageVec::Stack { len: buf.len, buf }
     }
 
-    //return the current number of elements
-    pub fn len(???) -> usize {
+    //the buffer, if the elements are on the stack
+    fn stack_mut(&mut self) -> Option<StackMut<'_, T, N>> {
+        match self {
+            LocalStorageVec::Stack { buf, len } => Some(StackMut { buf, copy: len }),
+            LocalStorageVec::Heap(_) => None,
+        }
+    }
 
+    //returns the current number of elements
+    pub fn len(&self) -> usize {
+        match self {
+            LocalStorageVec::Stack { buf, .. } => buf.len,
+            LocalStorageVec::Heap(vec) => vec.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    //insert a new element at the back
+    //inserts a new element at the back
     //if the size exceeds the stack allocated buffer size, the whole buffer is moved to the heap
-    pub fn push(???) {
-        
+    pub fn push(&mut self, value: T) {
+        if let Some(mut buf) = self.stack_mut() {
+            if buf.len < N {
+                return buf.push(value);
+            }
+        }
+        self.spill(1).push(value);
     }
 
-    //pop and return the last element
+    //moves the elements to the heap if they are on the stack, with room for `additional` more elements,
+    //and returns the `Vec` that holds them
+    fn spill(&mut self, additional: usize) -> &mut Vec<T> {
+        if let LocalStorageVec::Stack { .. } = self {
+            let LocalStorageVec::Stack { buf, .. } = mem::take(self) else {
+                unreachable!("the buffer is on the stack");
+            };
+            *self = LocalStorageVec::Heap(buf.into_vec(additional));
+        }
+        let LocalStorageVec::Heap(vec) = self else {
+            unreachable!("the elements are on the heap");
+        };
+        vec
+    }
+
+    //removes and returns the last element
     //if the size gets equal to the stack allocated buffer size, the buffer gets moved back to the stack
-    //(whether or not this is efficient behaviour is not relevant for today)
-    //info: `Option` is used when there could be no result, where you would usually use some `null` value in many other impertive languages (see https://doc.rust-lang.org/std/option/index.html)
-    pub fn pop(???) -> Option<T> {
-        
+    pub fn pop(&mut self) -> Option<T> {
+        if let Some(mut buf) = self.stack_mut() {
+            return buf.pop();
+        }
+        let LocalStorageVec::Heap(vec) = self else {
+            unreachable!("the elements are on the heap");
+        };
+        let value = vec.pop();
+        self.unspill();
+        value
+    }
+
+    //moves the elements back to the stack if they are on the heap and fit on the stack
+    //every method that removes elements calls it, so the elements are only on the heap while there are more than `N`
+    fn unspill(&mut self) {
+        match self {
+            LocalStorageVec::Heap(vec) if vec.len() <= N => {
+                let mut buf = Buffer::new();
+                for value in vec.drain(..) {
+                    buf.push(value);
+                }
+                *self = LocalStorageVec::stack(buf);
+            }
+            _ => {}
+        }
+    }
+
+    //the number of elements that can be held without moving them to a new allocation, `N` on the stack
+    pub fn capacity(&self) -> usize {
+        match self {
+            LocalStorageVec::Stack { .. } => N,
+            LocalStorageVec::Heap(vec) => vec.capacity(),
+        }
+    }
+
+    //inserts an element at `index`, shifting the elements after it to the right, panics if `index > len`
+    pub fn insert(&mut self, index: usize, value: T) {
+        let len = self.len();
+        if index > len {
+            panic!("insertion index (is {index}) should be <= len (is {len})");
+        }
+        self.push(value);
+        self[index..].rotate_right(1);
+    }
+
+    //removes and returns the element at `index`, shifting the elements after it to the left, panics if `index >= len`
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        if index >= len {
+            panic!("removal index (is {index}) should be < len (is {len})");
+        }
+        self[index..].rotate_left(1);
+        self.pop().expect("the element is there")
+    }
+
+    //removes and returns the element at `index`, which is replaced by the last element, panics if `index >= len`
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        if index >= len {
+            panic!("swap_remove index (is {index}) should be < len (is {len})");
+        }
+        self.swap(index, len - 1);
+        self.pop().expect("the element is there")
+    }
+
+    //drops the elements from `len` on, if there are that many
+    pub fn truncate(&mut self, len: usize) {
+        if let Some(mut buf) = self.stack_mut() {
+            return buf.truncate(len);
+        }
+        let LocalStorageVec::Heap(vec) = self else {
+            unreachable!("the elements are on the heap");
+        };
+        vec.truncate(len);
+        self.unspill();
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    //keeps only the elements for which `keep` returns true, in their order
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        match self {
+            LocalStorageVec::Stack { .. } => {
+                for value in mem::take(self) {
+                    if keep(&value) {
+                        self.push(value);
+                    }
+                }
+            }
+            LocalStorageVec::Heap(vec) => {
+                vec.retain(keep);
+                self.unspill();
+            }
+        }
+    }
+
+    //removes consecutive repeated elements, only the first of every run of equal elements is kept
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        match self {
+            LocalStorageVec::Stack { .. } => {
+                for value in mem::take(self) {
+                    if self.last() != Some(&value) {
+                        self.push(value);
+                    }
+                }
+            }
+            LocalStorageVec::Heap(vec) => {
+                vec.dedup();
+                self.unspill();
+            }
+        }
+    }
+
+    //removes the elements in `range` and returns an iterator over them, panics like `Vec::drain` if the range is
+    //out of bounds
+    //unlike `Vec::drain`, the elements are removed right away, not only once the iterator is dropped
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> IntoIter<T, N> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        //indexing checks the range
+        let count = self[range].len();
+        let start = match range.0 {
+            Bound::Included(start) => start,
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let mut drained = self.split_off(start);
+        let mut tail = drained.split_off(count);
+        self.append(&mut tail);
+        drained.into_iter()
+    }
+
+    //moves the elements from `at` on to a new `LocalStorageVec`, panics if `at > len`
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let len = self.len();
+        if at > len {
+            panic!("`at` split index (is {at}) should be <= len (is {len})");
+        }
+        if let Some(mut buf) = self.stack_mut() {
+            return LocalStorageVec::stack(buf.split_off(at));
+        }
+        let LocalStorageVec::Heap(vec) = self else {
+            unreachable!("the elements are on the heap");
+        };
+        let mut tail = LocalStorageVec::Heap(vec.split_off(at));
+        self.unspill();
+        tail.unspill();
+        tail
+    }
+
+    //moves all elements of `other` to the back, leaving `other` empty
+    pub fn append(&mut self, other: &mut Self) {
+        self.extend(mem::take(other));
+    }
+
+    //shortens the vector to `len` elements, or fills it up to `len` elements with clones of `value`
+    pub fn resize(&mut self, len: usize, value: T)
+    where
+        T: Clone,
+    {
+        match len.checked_sub(self.len()) {
+            Some(additional) => self.extend(iter::repeat_n(value, additional)),
+            None => self.truncate(len),
+        }
+    }
+
+    //the elements, wherever they are stored
+    //`LocalStorageVec` dereferences to this slice, so all methods of slices, e.g. `iter`, `sort` or `contains`,
+    //can be called on it, and `&LocalStorageVec` can be passed where a `&[T]` is expected
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            LocalStorageVec::Stack { buf, .. } => buf.as_slice(),
+            LocalStorageVec::Heap(vec) => vec,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            LocalStorageVec::Stack { buf, .. } => buf.as_mut_slice(),
+            LocalStorageVec::Heap(vec) => vec,
+        }
+    }
+}
+
+impl<T, const N: usize> Default for LocalStorageVec<T, N> {
+    fn default() -> Self {
+        LocalStorageVec::new()
+    }
+}
+
+impl<T, const N: usize> Deref for LocalStorageVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
     }
 }
 
-//TODO 4 implement the `std::ops::Index` trait to read an item at a given index in the buffer
-//for example: let item: &T = my_local_storage_vec[42];
+impl<T, const N: usize> DerefMut for LocalStorageVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
 
+impl<T, const N: usize> AsRef<[T]> for LocalStorageVec<T, N> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
 
-//Notice how the `Index` trait is generic over the type used for indexing
-//The previous `Index<usize>` impl allows you to get single elements
-//However, if the index type parameter is a `Range<usize>` type, you can create slices (= type &[T]) from your buffer
-//for example: let items_slice: &[T] = my_local_storage_buffer[42..68];
-//TODO 5 implement this
+impl<T, const N: usize> AsMut<[T]> for LocalStorageVec<T, N> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
 
+//`Borrow` requires comparisons and hashes of the borrowed slice to agree with the ones of `LocalStorageVec`,
+//which holds because `LocalStorageVec` does not implement them differently
+impl<T, const N: usize> Borrow<[T]> for LocalStorageVec<T, N> {
+    fn borrow(&self) -> &[T] {
+        self
+    }
+}
 
+impl<T, const N: usize> BorrowMut<[T]> for LocalStorageVec<T, N> {
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+//indexing works like for slices and `Vec`, with the same panics when the index is out of bounds:
+// - a `usize` gives a single element, e.g. `let item: &T = &my_local_storage_vec[42];`
+// - a range of any type gives a slice, e.g. `let items: &[T] = &my_local_storage_vec[42..68];` or `[..=7]`
+impl<T, I: SliceIndex<[T]>, const N: usize> Index<I> for LocalStorageVec<T, N> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &I::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const N: usize> IndexMut<I> for LocalStorageVec<T, N> {
+    fn index_mut(&mut self, index: I) -> &mut I::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+//collects the elements on the stack as long as there are at most `N`, see `Extend`
+impl<T, const N: usize> FromIterator<T> for LocalStorageVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = LocalStorageVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+//the elements are moved to the heap at most once: as soon as the iterator is known to yield more elements than
+//fit on the stack, either from its `size_hint` or when the buffer is full, with room for the rest of the elements
+//according to the `size_hint`
+impl<T, const N: usize> Extend<T> for LocalStorageVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        if self.len().saturating_add(lower) > N {
+            self.spill(lower);
+        }
+        while let LocalStorageVec::Stack { .. } = self {
+            let Some(value) = iter.next() else {
+                return;
+            };
+            if self.len() < N {
+                self.push(value);
+            } else {
+                let (lower, _) = iter.size_hint();
+                self.spill(lower.saturating_add(1)).push(value);
+            }
+        }
+        if let LocalStorageVec::Heap(vec) = self {
+            vec.extend(iter);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a, const N: usize> Extend<&'a T> for LocalStorageVec<T, N> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<T, const N: usize> IntoIterator for LocalStorageVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        match self {
+            LocalStorageVec::Stack { mut buf, .. } => {
+                //the iterator drops the elements it does not yield, the buffer must not drop any of them
+                let len = mem::take(&mut buf.len);
+                IntoIter(Elements::Stack { buf, left: 0..len })
+            }
+            LocalStorageVec::Heap(vec) => IntoIter(Elements::Heap(vec.into_iter())),
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a LocalStorageVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut LocalStorageVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+//an iterator that moves the elements out of a `LocalStorageVec`, from the front to the back
+pub struct IntoIter<T, const N: usize>(Elements<T, N>);
+
+enum Elements<T, const N: usize> {
+    //the slots in `left` are initialized and not yet yielded, the buffer itself counts no elements
+    Stack {
+        buf: Buffer<T, N>,
+        left: Range<usize>,
+    },
+    Heap(vec::IntoIter<T>),
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match &mut self.0 {
+            Elements::Stack { buf, left } => {
+                let index = left.next()?;
+                //safety: the slot is initialized, and it is no longer in `left`, so it is not read or dropped again
+                Some(unsafe { buf.slots[index].assume_init_read() })
+            }
+            Elements::Heap(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            Elements::Stack { left, .. } => left.size_hint(),
+            Elements::Heap(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        match &mut self.0 {
+            Elements::Stack { buf, left } => {
+                let index = left.next_back()?;
+                //safety: as in `next`
+                Some(unsafe { buf.slots[index].assume_init_read() })
+            }
+            Elements::Heap(iter) => iter.next_back(),
+        }
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        if let Elements::Stack { buf, left } = &mut self.0 {
+            for index in mem::take(left) {
+                //safety: the slots in `left` are initialized, and they are no longer in it, so they are only dropped once
+                unsafe { buf.slots[index].assume_init_drop() };
+            }
+        }
+    }
+}
 
 //DO NOT change the contents of the tests!
 #[cfg(test)]
@@ -156,3 +668,449 @@ mod test {
     }
     
 }
+
+//tests beyond the ones of the exercise, many with element types that are neither `Copy` nor `Default`
+//the buffer is partly uninitialized memory, run them with `cargo +nightly miri test` to check the unsafe code
+#[cfg(test)]
+mod test_any_type {
+    use crate::LocalStorageVec;
+    use std::borrow::{Borrow, BorrowMut};
+    use std::panic;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_strings() {
+        let mut vec: LocalStorageVec<String, 2> = LocalStorageVec::from([String::from("a")]);
+        vec.push(String::from("b"));
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 2, .. }));
+        vec.push(String::from("c"));
+        assert!(matches!(vec, LocalStorageVec::Heap(ref v) if v.len() == 3));
+        assert_eq!(vec[2], "c");
+        assert_eq!(vec.pop().as_deref(), Some("c"));
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 2, .. }));
+        assert_eq!(vec[0..2], ["a", "b"]);
+        assert_eq!(vec.pop().as_deref(), Some("b"));
+        assert_eq!(vec.pop().as_deref(), Some("a"));
+        assert_eq!(vec.pop(), None);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn test_boxes() {
+        let mut vec: LocalStorageVec<Box<[u8]>, 0> = LocalStorageVec::new();
+        assert!(vec.pop().is_none());
+        vec.push(Box::new([1, 2]));
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(*vec[0], [1, 2]);
+        assert_eq!(vec.pop().as_deref(), Some(&[1, 2][..]));
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 0, .. }));
+    }
+
+    //every clone of an `Rc` that is still alive counts, so the count shows which elements were dropped
+    #[test]
+    fn test_drop() {
+        let counter = Rc::new(());
+        let clones = || [(); 3].map(|()| Rc::clone(&counter));
+
+        let vec: LocalStorageVec<_, 5> = LocalStorageVec::from(clones());
+        assert_eq!(Rc::strong_count(&counter), 4);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&counter), 1);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from(clones());
+        vec.push(Rc::clone(&counter));
+        assert_eq!(Rc::strong_count(&counter), 5);
+        //back to the stack
+        drop(vec.pop());
+        drop(vec.pop());
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 2, .. }));
+        assert_eq!(Rc::strong_count(&counter), 3);
+        //and to the heap again
+        vec.push(Rc::clone(&counter));
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(Rc::strong_count(&counter), 4);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_slice_methods() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([3, 1, 2]);
+        assert!(vec.contains(&2));
+        assert_eq!(vec.iter().sum::<i32>(), 6);
+        vec.sort();
+        assert_eq!(vec.binary_search(&3), Ok(2));
+        vec.push(5);
+        vec.push(4);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        vec.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(*vec, [5, 4, 3, 2, 1]);
+        for value in vec.iter_mut() {
+            *value *= 10;
+        }
+        assert_eq!(vec.first(), Some(&50));
+        assert_eq!(vec.as_slice(), [50, 40, 30, 20, 10]);
+    }
+
+    #[test]
+    fn test_slice_conversions() {
+        fn total(values: &[String]) -> usize {
+            values.iter().map(String::len).sum()
+        }
+        fn clear<S: AsMut<[String]>>(mut values: S) -> S {
+            values.as_mut().iter_mut().for_each(String::clear);
+            values
+        }
+        let vec: LocalStorageVec<String, 2> =
+            LocalStorageVec::from([String::from("ab"), String::from("c")]);
+        assert_eq!(total(&vec), 3);
+        assert_eq!(total(vec.as_ref()), 3);
+        assert_eq!(Borrow::<[String]>::borrow(&vec).len(), 2);
+        let mut vec = clear(vec);
+        assert_eq!(vec.as_mut_slice(), ["", ""]);
+        vec.push(String::from("d"));
+        assert_eq!(total(&vec), 1);
+        BorrowMut::<[String]>::borrow_mut(&mut vec).reverse();
+        assert_eq!(vec[0], "d");
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut vec: LocalStorageVec<i32, 10> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        assert_eq!(vec[2..], [2, 3, 4, 5]);
+        assert_eq!(vec[..2], [0, 1]);
+        assert_eq!(vec[1..=3], [1, 2, 3]);
+        assert_eq!(vec[..=1], [0, 1]);
+        assert_eq!(vec[..], [0, 1, 2, 3, 4, 5]);
+        assert_eq!(vec[6..], []);
+        vec[0] = 10;
+        vec[1..3].fill(7);
+        vec[4..].swap(0, 1);
+        assert_eq!(vec[..], [10, 7, 7, 3, 5, 4]);
+
+        let mut vec: LocalStorageVec<i32, 2> = LocalStorageVec::from([0, 1, 2]);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        vec[..=1].reverse();
+        vec[2..][0] += 1;
+        assert_eq!(vec[..], [1, 0, 3]);
+    }
+
+    //the messages are the ones of `Vec`
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 3")]
+    fn test_index_out_of_bounds() {
+        let vec: LocalStorageVec<i32, 10> = LocalStorageVec::from([0, 1, 2]);
+        let _ = vec[3];
+    }
+
+    #[test]
+    #[should_panic(expected = "range end index 5 out of range for slice of length 3")]
+    fn test_range_out_of_bounds() {
+        let mut vec: LocalStorageVec<i32, 2> = LocalStorageVec::from([0, 1, 2]);
+        vec[1..5].fill(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 2 but ends at 1")]
+    fn test_range_decreasing() {
+        let vec: LocalStorageVec<i32, 10> = LocalStorageVec::from([0, 1, 2]);
+        let (start, end) = (2, 0);
+        let _ = &vec[start..=end];
+    }
+
+    #[test]
+    fn test_same_panics_as_vec() {
+        fn message(index: impl FnOnce() + panic::UnwindSafe) -> String {
+            let error = panic::catch_unwind(index).unwrap_err();
+            error.downcast_ref::<String>().unwrap().clone()
+        }
+        let stack: LocalStorageVec<i32, 3> = LocalStorageVec::from([0, 1, 2]);
+        let heap: LocalStorageVec<i32, 1> = LocalStorageVec::from([0, 1, 2]);
+        let vec = Vec::from([0, 1, 2]);
+        assert_eq!(
+            message(|| {
+                let _ = &stack[4..];
+            }),
+            message(|| {
+                let _ = &vec[4..];
+            })
+        );
+        assert_eq!(
+            message(|| {
+                let _ = &heap[..=3];
+            }),
+            message(|| {
+                let _ = &vec[..=3];
+            })
+        );
+        assert_eq!(
+            message(|| {
+                let _ = &heap[1..=usize::MAX];
+            }),
+            message(|| {
+                let _ = &vec[1..=usize::MAX];
+            })
+        );
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let vec: LocalStorageVec<String, 4> =
+            LocalStorageVec::from(["a", "b", "c"].map(String::from));
+        let mut iter = vec.into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next_back().as_deref(), Some("c"));
+        assert_eq!(iter.collect::<Vec<_>>(), ["a", "b"]);
+
+        let vec: LocalStorageVec<String, 2> =
+            LocalStorageVec::from(["a", "b", "c"].map(String::from));
+        assert_eq!(vec.into_iter().rev().collect::<String>(), "cba");
+
+        let mut vec: LocalStorageVec<i32, 4> = LocalStorageVec::from([1, 2, 3]);
+        for value in &mut vec {
+            *value += 1;
+        }
+        let mut sum = 0;
+        for value in &vec {
+            sum += value;
+        }
+        assert_eq!(sum, 9);
+    }
+
+    //the elements that are not yielded are dropped with the iterator, the yielded ones by their new owner
+    #[test]
+    fn test_into_iter_drop() {
+        let counter = Rc::new(());
+        let vec: LocalStorageVec<_, 5> =
+            LocalStorageVec::from([(); 4].map(|()| Rc::clone(&counter)));
+        let mut iter = vec.into_iter();
+        let first = iter.next();
+        let last = iter.next_back();
+        assert_eq!(Rc::strong_count(&counter), 5);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop((first, last));
+        assert_eq!(Rc::strong_count(&counter), 1);
+
+        let vec: LocalStorageVec<_, 1> =
+            LocalStorageVec::from([(); 4].map(|()| Rc::clone(&counter)));
+        let mut iter = vec.into_iter();
+        drop(iter.next());
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let vec: LocalStorageVec<_, 4> = (0..4).collect();
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 4, .. }));
+        assert_eq!(vec[..], [0, 1, 2, 3]);
+        //the size hint of a filter gives no lower bound
+        let vec: LocalStorageVec<_, 4> = (0..10).filter(|n| n % 2 == 0).collect();
+        assert!(matches!(vec, LocalStorageVec::Heap(ref v) if v[..] == [0, 2, 4, 6, 8]));
+        let vec: LocalStorageVec<String, 4> = "a b".split(' ').map(String::from).collect();
+        assert_eq!(vec.as_slice(), ["a", "b"]);
+    }
+
+    //a size hint too large to allocate fails like it does for a `Vec`, not with an arithmetic overflow
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_extend_huge_size_hint() {
+        let mut vec: LocalStorageVec<u8, 4> = LocalStorageVec::from([1]);
+        vec.extend(std::iter::repeat_n(0, usize::MAX));
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut vec: LocalStorageVec<i32, 4> = LocalStorageVec::from([1]);
+        vec.extend([2, 3]);
+        vec.extend(&[4]);
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 4, .. }));
+        //the size hint shows that the elements do not fit, so they are moved to the heap before any is added
+        vec.extend(5..=100);
+        assert!(
+            matches!(vec, LocalStorageVec::Heap(ref v) if v.len() == 100 && v.capacity() == 100)
+        );
+        assert_eq!(vec.iter().sum::<i32>(), 5050);
+
+        //without a size hint the elements are moved when the buffer is full
+        let mut vec: LocalStorageVec<i32, 2> = LocalStorageVec::new();
+        vec.extend((0..5).filter(|_| true));
+        assert!(matches!(vec, LocalStorageVec::Heap(ref v) if v[..] == [0, 1, 2, 3, 4]));
+    }
+
+    fn strings<const M: usize, const N: usize>(values: [&str; M]) -> LocalStorageVec<String, N> {
+        LocalStorageVec::from(values.map(String::from))
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut vec = strings::<2, 3>(["a", "c"]);
+        vec.insert(1, String::from("b"));
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 3, .. }));
+        vec.insert(0, String::from("_"));
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        vec.insert(4, String::from("d"));
+        assert_eq!(vec.as_slice(), ["_", "a", "b", "c", "d"]);
+        assert_eq!(vec.remove(0), "_");
+        assert_eq!(vec.swap_remove(0), "a");
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 3, .. }));
+        assert_eq!(vec.as_slice(), ["d", "b", "c"]);
+        assert_eq!(vec.remove(1), "b");
+        assert_eq!(vec.swap_remove(1), "c");
+        assert_eq!(vec.as_slice(), ["d"]);
+        assert_eq!(vec.capacity(), 3);
+    }
+
+    #[test]
+    fn test_truncate_clear() {
+        let counter = Rc::new(());
+        let mut vec: LocalStorageVec<_, 2> =
+            LocalStorageVec::from([(); 4].map(|()| Rc::clone(&counter)));
+        vec.truncate(5);
+        assert_eq!(Rc::strong_count(&counter), 5);
+        vec.truncate(3);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        vec.truncate(1);
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 1, .. }));
+        assert_eq!(Rc::strong_count(&counter), 2);
+        vec.push(Rc::clone(&counter));
+        vec.clear();
+        assert!(vec.is_empty());
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_retain_dedup() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([1, 2, 3, 4, 5, 6]);
+        vec.retain(|n| n % 2 == 0);
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 3, .. }));
+        vec.retain(|n| *n != 4);
+        assert_eq!(vec[..], [2, 6]);
+
+        let mut vec = strings::<7, 4>(["a", "a", "b", "a", "c", "c", "c"]);
+        vec.dedup();
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 4, .. }));
+        assert_eq!(vec.as_slice(), ["a", "b", "a", "c"]);
+        vec.push(String::from("c"));
+        vec.dedup();
+        vec.retain(|s| s != "b");
+        assert_eq!(vec.as_slice(), ["a", "a", "c"]);
+        vec.dedup();
+        assert_eq!(vec.as_slice(), ["a", "c"]);
+    }
+
+    #[test]
+    fn test_split_off_append() {
+        let mut vec = strings::<5, 3>(["a", "b", "c", "d", "e"]);
+        let mut tail = vec.split_off(1);
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 1, .. }));
+        assert!(matches!(tail, LocalStorageVec::Heap(_)));
+        let mut end = tail.split_off(2);
+        assert_eq!(tail.as_slice(), ["b", "c"]);
+        assert_eq!(end.as_slice(), ["d", "e"]);
+        assert!(tail.split_off(2).is_empty());
+        vec.append(&mut end);
+        assert!(end.is_empty());
+        assert_eq!(vec.as_slice(), ["a", "d", "e"]);
+        vec.append(&mut tail);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(vec.as_slice(), ["a", "d", "e", "b", "c"]);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut vec = strings::<5, 3>(["a", "b", "c", "d", "e"]);
+        let drained: Vec<_> = vec.drain(1..3).collect();
+        assert_eq!(drained, ["b", "c"]);
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 3, .. }));
+        assert_eq!(vec.as_slice(), ["a", "d", "e"]);
+        assert_eq!(vec.drain(..=0).collect::<Vec<_>>(), ["a"]);
+        assert_eq!(vec.drain(1..).rev().collect::<Vec<_>>(), ["e"]);
+        assert_eq!(vec.drain(1..1).count(), 0);
+        assert_eq!(vec.as_slice(), ["d"]);
+
+        //the elements that are not yielded are dropped with the iterator
+        let counter = Rc::new(());
+        let mut vec: LocalStorageVec<_, 2> =
+            LocalStorageVec::from([(); 4].map(|()| Rc::clone(&counter)));
+        let mut drained = vec.drain(..);
+        drop(drained.next());
+        drop(drained);
+        assert!(vec.is_empty());
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    //the length of the variant follows the buffer even if dropping an element panics
+    #[test]
+    fn test_len_after_panicking_drop() {
+        struct Bomb;
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                panic!("boom");
+            }
+        }
+        let mut vec: LocalStorageVec<Bomb, 3> = LocalStorageVec::from([Bomb]);
+        let truncate = panic::AssertUnwindSafe(|| vec.truncate(0));
+        assert!(panic::catch_unwind(truncate).is_err());
+        assert!(vec.is_empty());
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 0, .. }));
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut vec: LocalStorageVec<String, 3> = LocalStorageVec::new();
+        vec.resize(2, String::from("a"));
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 2, .. }));
+        vec.resize(10, String::from("b"));
+        assert!(matches!(vec, LocalStorageVec::Heap(ref v) if v.len() == 10));
+        assert!(vec.capacity() >= 10);
+        assert_eq!(vec[1..3], ["a", "b"]);
+        vec.resize(1, String::new());
+        assert!(matches!(vec, LocalStorageVec::Stack { len: 1, .. }));
+        assert_eq!(vec.as_slice(), ["a"]);
+    }
+
+    #[test]
+    fn test_same_mutation_panics_as_vec() {
+        fn message(mutate: impl FnOnce() + panic::UnwindSafe) -> String {
+            let error = panic::catch_unwind(mutate).unwrap_err();
+            error.downcast_ref::<String>().unwrap().clone()
+        }
+        let lsv = || -> LocalStorageVec<i32, 3> { LocalStorageVec::from([0, 1, 2]) };
+        let vec = || Vec::from([0, 1, 2]);
+        assert_eq!(
+            message(|| lsv().insert(4, 0)),
+            message(|| vec().insert(4, 0))
+        );
+        assert_eq!(
+            message(|| {
+                lsv().remove(3);
+            }),
+            message(|| {
+                vec().remove(3);
+            })
+        );
+        assert_eq!(
+            message(|| {
+                lsv().swap_remove(5);
+            }),
+            message(|| {
+                vec().swap_remove(5);
+            })
+        );
+        assert_eq!(
+            message(|| drop(lsv().split_off(4))),
+            message(|| drop(vec().split_off(4)))
+        );
+        assert_eq!(
+            message(|| drop(lsv().drain(2..5))),
+            message(|| drop(vec().drain(2..5)))
+        );
+        let (start, end) = (3, 1);
+        assert_eq!(
+            message(|| drop(lsv().drain(start..=end))),
+            message(|| drop(vec().drain(start..=end)))
+        );
+    }
+}